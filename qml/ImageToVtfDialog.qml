@@ -32,6 +32,14 @@ Dialog {
     
     property bool clampTexture: false
     property bool noLod: false
+    property bool dilateEdges: false
+    property int heightKernel: 0
+    property real heightStrength: 2.0
+    property bool heightInAlpha: false
+    property bool flipGreen: false
+    property bool renormalizeNormals: false
+    property int sprayGame: 0
+    property bool autoFormat: true
     property bool pointSample: false
     property bool trilinear: false
     property bool noCompression: false
@@ -107,7 +115,9 @@ Dialog {
             var res = root.app.import_image_to_vtf(
                 inputPath, outputPath,
                 root.generateMipmaps, root.isNormalMap,
-                root.clampTexture, root.noLod,
+                root.clampTexture, root.noLod, root.dilateEdges,
+                root.heightKernel, root.heightStrength, root.heightInAlpha,
+                root.flipGreen, root.renormalizeNormals, root.sprayGame, root.autoFormat,
                 root.resizeMode, root.customWidth, root.customHeight
             )
             
@@ -398,12 +408,137 @@ Dialog {
                             
                             VtfCheckBox { label: "Generate Mipmaps"; checked: root.generateMipmaps; onCheckedChanged: root.generateMipmaps = checked }
                             VtfCheckBox { label: "Normal Map"; checked: root.isNormalMap; onCheckedChanged: root.isNormalMap = checked }
+                            VtfCheckBox { visible: root.isNormalMap; label: "Flip Green (OpenGL)"; checked: root.flipGreen; onCheckedChanged: root.flipGreen = checked }
+                            VtfCheckBox { visible: root.isNormalMap; label: "Renormalize"; checked: root.renormalizeNormals; onCheckedChanged: root.renormalizeNormals = checked }
                             VtfCheckBox { label: "Clamp (No Tiling)"; checked: root.clampTexture; onCheckedChanged: root.clampTexture = checked }
                             VtfCheckBox { label: "No LOD"; checked: root.noLod; onCheckedChanged: root.noLod = checked }
+                            VtfCheckBox { label: "Dilate Edges"; checked: root.dilateEdges; onCheckedChanged: root.dilateEdges = checked }
+                            VtfCheckBox { label: "Auto Format"; checked: root.autoFormat; onCheckedChanged: root.autoFormat = checked }
                             VtfCheckBox { label: "Point Sample"; checked: root.pointSample; onCheckedChanged: root.pointSample = checked }
                             VtfCheckBox { label: "Trilinear"; checked: root.trilinear; onCheckedChanged: root.trilinear = checked }
                         }
-                        
+
+                        Rectangle { Layout.fillWidth: true; height: 1; color: themeRoot.panelBorder }
+
+                        ColumnLayout {
+                            Layout.fillWidth: true
+                            spacing: 6
+
+                            Text {
+                                text: "HEIGHT MAP"
+                                color: themeRoot.textDim
+                                font.pixelSize: 10
+                                font.bold: true
+                            }
+
+                            ComboBox {
+                                id: heightKernelCombo
+                                Layout.fillWidth: true
+                                model: ["Off", "Sobel", "Scharr", "Prewitt"]
+                                currentIndex: root.heightKernel
+                                onCurrentIndexChanged: root.heightKernel = currentIndex
+
+                                background: Rectangle {
+                                    implicitHeight: 28
+                                    color: themeRoot.inputBg
+                                    border.color: themeRoot.inputBorder
+                                    radius: 4
+                                }
+
+                                contentItem: Text {
+                                    leftPadding: 8
+                                    text: heightKernelCombo.displayText
+                                    color: themeRoot.textColor
+                                    font.pixelSize: 11
+                                    verticalAlignment: Text.AlignVCenter
+                                }
+                            }
+
+                            RowLayout {
+                                Layout.fillWidth: true
+                                visible: root.heightKernel > 0
+                                spacing: 8
+
+                                Text {
+                                    text: "Strength"
+                                    color: themeRoot.textColor
+                                    font.pixelSize: 11
+                                }
+
+                                TextField {
+                                    Layout.fillWidth: true
+                                    text: root.heightStrength.toString()
+                                    color: themeRoot.textColor
+                                    font.pixelSize: 11
+                                    horizontalAlignment: Text.AlignHCenter
+                                    validator: DoubleValidator { bottom: 0.01; top: 100 }
+                                    onTextChanged: {
+                                        var val = parseFloat(text)
+                                        if (!isNaN(val) && val > 0) root.heightStrength = val
+                                    }
+                                    background: Rectangle {
+                                        implicitHeight: 26
+                                        color: themeRoot.inputBg
+                                        border.color: themeRoot.inputBorder
+                                        radius: 4
+                                    }
+                                }
+                            }
+
+                            VtfCheckBox {
+                                visible: root.heightKernel > 0
+                                label: "Height in Alpha"
+                                checked: root.heightInAlpha
+                                onCheckedChanged: root.heightInAlpha = checked
+                            }
+                        }
+
+                        Rectangle { Layout.fillWidth: true; height: 1; color: themeRoot.panelBorder }
+
+                        ColumnLayout {
+                            Layout.fillWidth: true
+                            spacing: 6
+
+                            Text {
+                                text: "SPRAY"
+                                color: themeRoot.textDim
+                                font.pixelSize: 10
+                                font.bold: true
+                            }
+
+                            ComboBox {
+                                id: sprayGameCombo
+                                Layout.fillWidth: true
+                                model: ["Off", "Team Fortress 2", "Counter-Strike: Source", "Half-Life 2: Deathmatch", "Garry's Mod"]
+                                currentIndex: root.sprayGame
+                                onCurrentIndexChanged: root.sprayGame = currentIndex
+
+                                background: Rectangle {
+                                    implicitHeight: 28
+                                    color: themeRoot.inputBg
+                                    border.color: themeRoot.inputBorder
+                                    radius: 4
+                                }
+
+                                contentItem: Text {
+                                    leftPadding: 8
+                                    text: sprayGameCombo.displayText
+                                    color: themeRoot.textColor
+                                    font.pixelSize: 11
+                                    verticalAlignment: Text.AlignVCenter
+                                }
+                            }
+
+                            Text {
+                                visible: root.sprayGame > 0
+                                Layout.fillWidth: true
+                                text: "Size and format are picked to fit the game's spray limits"
+                                color: themeRoot.textDim
+                                font.pixelSize: 10
+                                wrapMode: Text.WordWrap
+                            }
+                        }
+
                         Item { Layout.fillHeight: true }
                     }
                 }
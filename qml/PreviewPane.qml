@@ -124,6 +124,12 @@ Item {
         function onMipmap_changed() {
             refreshDebounce.restart()
         }
+        function onSlice_changed() {
+            refreshDebounce.restart()
+        }
+        function onTonemap_changed() {
+            refreshDebounce.restart()
+        }
     }
     
     // Timer to refresh thumbnails until all are loaded
@@ -1002,6 +1008,228 @@ Item {
                 }
             }
             
+            // Depth slice selector (volume textures)
+            RowLayout {
+                visible: textureProvider && textureProvider.slice_count > 1
+                spacing: 4
+                
+                ThemedIcon {
+                    width: 14
+                    height: 14
+                    source: "qrc:/media/layers.svg"
+                    sourceSize: Qt.size(14, 14)
+                    themeRoot: root.themeRoot
+                }
+                
+                Rectangle {
+                    id: sliceDownBtn
+                    width: 28
+                    height: 24
+                    radius: 4
+                    color: sliceDownMouse.containsMouse ? root.buttonHover : root.buttonBg
+                    
+                    // Smooth hover animation
+                    scale: sliceDownMouse.pressed ? 0.97 : 1.0
+                    Behavior on scale { NumberAnimation { duration: root.animDurationFast; easing.type: Easing.OutCubic } }
+                    Behavior on color { ColorAnimation { duration: root.animDurationFast } }
+                    
+                    ThemedIcon {
+                        anchors.centerIn: parent
+                        width: 10
+                        height: 10
+                        source: "qrc:/media/arrow-left.svg"
+                        sourceSize: Qt.size(10, 10)
+                        themeRoot: root.themeRoot
+                    }
+                    
+                    MouseArea {
+                        id: sliceDownMouse
+                        anchors.fill: parent
+                        hoverEnabled: true
+                        cursorShape: Qt.PointingHandCursor
+                        onClicked: {
+                            if (textureProvider && textureProvider.current_slice > 0) {
+                                textureProvider.set_slice(textureProvider.current_slice - 1)
+                            }
+                        }
+                    }
+                }
+                
+                Text {
+                    text: (textureProvider ? (textureProvider.current_slice + 1) : 0) + "/" + (textureProvider ? textureProvider.slice_count : 0)
+                    color: root.textColor
+                    font.pixelSize: 11
+                    font.family: "monospace"
+                }
+                
+                Rectangle {
+                    id: sliceUpBtn
+                    width: 28
+                    height: 24
+                    radius: 4
+                    color: sliceUpMouse.containsMouse ? root.buttonHover : root.buttonBg
+                    
+                    // Smooth hover animation
+                    scale: sliceUpMouse.pressed ? 0.97 : 1.0
+                    Behavior on scale { NumberAnimation { duration: root.animDurationFast; easing.type: Easing.OutCubic } }
+                    Behavior on color { ColorAnimation { duration: root.animDurationFast } }
+                    
+                    ThemedIcon {
+                        anchors.centerIn: parent
+                        width: 10
+                        height: 10
+                        source: "qrc:/media/arrow-right.svg"
+                        sourceSize: Qt.size(10, 10)
+                        themeRoot: root.themeRoot
+                    }
+                    
+                    MouseArea {
+                        id: sliceUpMouse
+                        anchors.fill: parent
+                        hoverEnabled: true
+                        cursorShape: Qt.PointingHandCursor
+                        onClicked: {
+                            if (textureProvider && textureProvider.current_slice < textureProvider.slice_count - 1) {
+                                textureProvider.set_slice(textureProvider.current_slice + 1)
+                            }
+                        }
+                    }
+                }
+            }
+            
+            // HDR exposure and tonemap (16-bit formats)
+            RowLayout {
+                visible: textureProvider && textureProvider.is_hdr
+                spacing: 4
+                
+                Text {
+                    text: "EV"
+                    color: root.textColor
+                    font.pixelSize: 11
+                    font.family: "monospace"
+                }
+                
+                Rectangle {
+                    id: exposureDownBtn
+                    width: 28
+                    height: 24
+                    radius: 4
+                    color: exposureDownMouse.containsMouse ? root.buttonHover : root.buttonBg
+                    
+                    // Smooth hover animation
+                    scale: exposureDownMouse.pressed ? 0.97 : 1.0
+                    Behavior on scale { NumberAnimation { duration: root.animDurationFast; easing.type: Easing.OutCubic } }
+                    Behavior on color { ColorAnimation { duration: root.animDurationFast } }
+                    
+                    ThemedIcon {
+                        anchors.centerIn: parent
+                        width: 10
+                        height: 10
+                        source: "qrc:/media/minus.svg"
+                        sourceSize: Qt.size(10, 10)
+                        themeRoot: root.themeRoot
+                    }
+                    
+                    MouseArea {
+                        id: exposureDownMouse
+                        anchors.fill: parent
+                        hoverEnabled: true
+                        cursorShape: Qt.PointingHandCursor
+                        onClicked: {
+                            if (textureProvider) {
+                                textureProvider.set_preview_exposure(textureProvider.exposure - 0.5)
+                            }
+                        }
+                    }
+                }
+                
+                // Exposure value, click to reset
+                Text {
+                    text: textureProvider ? ((textureProvider.exposure > 0 ? "+" : "") + textureProvider.exposure.toFixed(1)) : "0.0"
+                    color: root.textColor
+                    font.pixelSize: 11
+                    font.family: "monospace"
+                    
+                    MouseArea {
+                        anchors.fill: parent
+                        cursorShape: Qt.PointingHandCursor
+                        onClicked: {
+                            if (textureProvider) {
+                                textureProvider.set_preview_exposure(0)
+                            }
+                        }
+                    }
+                }
+                
+                Rectangle {
+                    id: exposureUpBtn
+                    width: 28
+                    height: 24
+                    radius: 4
+                    color: exposureUpMouse.containsMouse ? root.buttonHover : root.buttonBg
+                    
+                    // Smooth hover animation
+                    scale: exposureUpMouse.pressed ? 0.97 : 1.0
+                    Behavior on scale { NumberAnimation { duration: root.animDurationFast; easing.type: Easing.OutCubic } }
+                    Behavior on color { ColorAnimation { duration: root.animDurationFast } }
+                    
+                    ThemedIcon {
+                        anchors.centerIn: parent
+                        width: 10
+                        height: 10
+                        source: "qrc:/media/plus.svg"
+                        sourceSize: Qt.size(10, 10)
+                        themeRoot: root.themeRoot
+                    }
+                    
+                    MouseArea {
+                        id: exposureUpMouse
+                        anchors.fill: parent
+                        hoverEnabled: true
+                        cursorShape: Qt.PointingHandCursor
+                        onClicked: {
+                            if (textureProvider) {
+                                textureProvider.set_preview_exposure(textureProvider.exposure + 0.5)
+                            }
+                        }
+                    }
+                }
+                
+                // Tonemap operator, click to cycle
+                Rectangle {
+                    id: tonemapBtn
+                    width: 64
+                    height: 24
+                    radius: 4
+                    color: tonemapMouse.containsMouse ? root.buttonHover : root.buttonBg
+                    
+                    // Smooth hover animation
+                    scale: tonemapMouse.pressed ? 0.97 : 1.0
+                    Behavior on scale { NumberAnimation { duration: root.animDurationFast; easing.type: Easing.OutCubic } }
+                    Behavior on color { ColorAnimation { duration: root.animDurationFast } }
+                    
+                    Text {
+                        anchors.centerIn: parent
+                        text: ["Clamp", "Reinhard", "ACES"][textureProvider ? textureProvider.tonemap_mode : 0]
+                        color: root.textColor
+                        font.pixelSize: 11
+                        font.family: "monospace"
+                    }
+                    
+                    MouseArea {
+                        id: tonemapMouse
+                        anchors.fill: parent
+                        hoverEnabled: true
+                        cursorShape: Qt.PointingHandCursor
+                        onClicked: {
+                            if (textureProvider) {
+                                textureProvider.set_preview_tonemap((textureProvider.tonemap_mode + 1) % 3)
+                            }
+                        }
+                    }
+                }
+            }
+            
             // SPACER - pushes everything after to the right
             Item { Layout.fillWidth: true }
             
@@ -1282,6 +1510,32 @@ Item {
                 }
             }
             
+            // Header editor toggle, for files on disk
+            Rectangle {
+                visible: textureProvider && textureProvider.is_loaded
+                         && !textureProvider.current_texture.toString().startsWith("vpk:")
+                color: headerEditMouse.containsMouse ? root.buttonHover : root.buttonBg
+                radius: 3
+                width: headerEditText.width + 12
+                height: 18
+
+                Text {
+                    id: headerEditText
+                    anchors.centerIn: parent
+                    text: "Edit Header"
+                    color: root.textColor
+                    font.pixelSize: 10
+                }
+
+                MouseArea {
+                    id: headerEditMouse
+                    anchors.fill: parent
+                    hoverEnabled: true
+                    cursorShape: Qt.PointingHandCursor
+                    onClicked: headerEditor.open()
+                }
+            }
+            
             Item { Layout.fillWidth: true }
             
             // Color picker hint
@@ -1346,6 +1600,144 @@ Item {
         }
     }
     
+    // HEADER EDITOR - flags and header values, written without re-encoding
+    Popup {
+        id: headerEditor
+        anchors.centerIn: parent
+        width: 260
+        modal: true
+        padding: 12
+
+        property int editFlags: 0
+        property real editBumpScale: 1.0
+        property int editFirstFrame: 0
+
+        // Flags that only change how the texture is sampled or loaded
+        readonly property var editableFlags: [
+            { bit: 0x0001, name: "Point Sample" },
+            { bit: 0x0002, name: "Trilinear" },
+            { bit: 0x0010, name: "Anisotropic" },
+            { bit: 0x0004, name: "Clamp S" },
+            { bit: 0x0008, name: "Clamp T" },
+            { bit: 0x0100, name: "No Mipmaps" },
+            { bit: 0x0200, name: "No LOD" },
+            { bit: 0x0080, name: "Normal Map" },
+            { bit: 0x0040, name: "PWL Corrected" },
+            { bit: 0x00080000, name: "Pre-sRGB" }
+        ]
+
+        onAboutToShow: {
+            editFlags = textureProvider.texture_flags
+            editBumpScale = textureProvider.bumpmap_scale
+            editFirstFrame = textureProvider.first_frame
+        }
+
+        background: Rectangle {
+            color: root.panelBg
+            border.color: root.panelBorder
+            radius: 6
+        }
+
+        contentItem: ColumnLayout {
+            spacing: 6
+
+            Text {
+                text: "HEADER"
+                color: root.textDim
+                font.pixelSize: 10
+                font.bold: true
+            }
+
+            Repeater {
+                model: headerEditor.editableFlags
+
+                CheckBox {
+                    id: flagCheck
+                    required property var modelData
+                    checked: (headerEditor.editFlags & modelData.bit) !== 0
+                    onToggled: {
+                        if (checked) headerEditor.editFlags |= modelData.bit
+                        else headerEditor.editFlags &= ~modelData.bit
+                    }
+
+                    contentItem: Text {
+                        leftPadding: flagCheck.indicator.width + 6
+                        text: flagCheck.modelData.name
+                        color: root.textColor
+                        font.pixelSize: 11
+                        verticalAlignment: Text.AlignVCenter
+                    }
+                }
+            }
+
+            RowLayout {
+                Layout.fillWidth: true
+                spacing: 8
+
+                Text {
+                    text: "Bump Scale"
+                    color: root.textColor
+                    font.pixelSize: 11
+                }
+
+                TextField {
+                    Layout.fillWidth: true
+                    text: headerEditor.editBumpScale.toString()
+                    color: root.textColor
+                    font.pixelSize: 11
+                    validator: DoubleValidator { bottom: 0; top: 100 }
+                    onTextChanged: {
+                        var val = parseFloat(text)
+                        if (!isNaN(val)) headerEditor.editBumpScale = val
+                    }
+                }
+            }
+
+            RowLayout {
+                Layout.fillWidth: true
+                visible: textureProvider && textureProvider.frame_count > 1
+                spacing: 8
+
+                Text {
+                    text: "First Frame"
+                    color: root.textColor
+                    font.pixelSize: 11
+                }
+
+                SpinBox {
+                    Layout.fillWidth: true
+                    from: 0
+                    to: textureProvider ? Math.max(0, textureProvider.frame_count - 1) : 0
+                    value: headerEditor.editFirstFrame
+                    onValueModified: headerEditor.editFirstFrame = value
+                }
+            }
+
+            RowLayout {
+                Layout.fillWidth: true
+                Layout.topMargin: 6
+                spacing: 8
+
+                Item { Layout.fillWidth: true }
+
+                Button {
+                    text: "Cancel"
+                    onClicked: headerEditor.close()
+                }
+
+                Button {
+                    text: "Save"
+                    onClicked: {
+                        if (textureProvider.save_header(headerEditor.editFlags, headerEditor.editBumpScale, headerEditor.editFirstFrame)) {
+                            root.previewVersion++
+                            headerEditor.close()
+                        }
+                    }
+                }
+            }
+        }
+    }
+    
     // Drop area
     DropArea {
         anchors.fill: parent
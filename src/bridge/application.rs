@@ -90,6 +90,51 @@ pub mod qobject {
         #[qinvokable]
         fn export_vtf_to_image(self: &VFileXApp, source: &QString, dest: &QString) -> bool;
 
+        // Export a cubemap VTF as a cross or six face images
+        // layout: "horizontal", "vertical" or "faces"
+        #[qinvokable]
+        fn export_cubemap(
+            self: &VFileXApp,
+            source: &QString,
+            dest: &QString,
+            layout: &QString,
+        ) -> bool;
+
+        // Flip, renormalize or detail-blend a normal map VTF, keeping its flags and frames
+        // detail_path: image or VTF to blend on top, empty for none
+        // storage: "keep", "rgb" (expand two-channel maps) or "uv88"
+        // Returns the output path, or an error message prefixed with "ERR:"
+        #[qinvokable]
+        fn process_normal_map(
+            self: &VFileXApp,
+            source: &QString,
+            dest: &QString,
+            flip_red: bool,
+            flip_green: bool,
+            renormalize: bool,
+            detail_path: &QString,
+            storage: &QString,
+        ) -> QString;
+
+        // Rebuild a VTF in another version or format, keeping its flags, frames and resources
+        // version_minor: 7.x target, 0 to keep. format: VtfFormat id, -1 to keep.
+        // mip_policy: 0 keep, 1 regenerate, 2 remove
+        // Returns the output path, or an error message prefixed with "ERR:"
+        #[qinvokable]
+        fn transcode_vtf(
+            self: &VFileXApp,
+            source: &QString,
+            dest: &QString,
+            version_minor: i32,
+            format: i32,
+            mip_policy: i32,
+        ) -> QString;
+
+        // Convert an Xbox 360 or PS3 VTF (VTFX) to a PC 7.5 VTF
+        // Returns the output path, or an error message prefixed with "ERR:"
+        #[qinvokable]
+        fn convert_console_vtf(self: &VFileXApp, source: &QString, dest: &QString) -> QString;
+
         // Open a path in the system file browser
         #[qinvokable]
         fn reveal_in_explorer(self: &VFileXApp, path: &QString);
@@ -174,6 +219,8 @@ pub mod qobject {
         // Import an image file (PNG, JPG, etc.) and convert it to VTF
         // Returns the output VTF path on success, or error message prefixed with "ERR:"
         // resize_mode: 0 = auto power of 2, 1 = keep original, 2 = custom size
+        // height_kernel: 0 = source is a regular image, 1 = Sobel, 2 = Scharr, 3 = Prewitt
+        // flip_green / renormalize_normals only apply to normal maps
         #[qinvokable]
         fn import_image_to_vtf(
             self: &VFileXApp, 
@@ -183,6 +230,14 @@ pub mod qobject {
             is_normal_map: bool,
             clamp: bool,
             no_lod: bool,
+            dilate_edges: bool,
+            height_kernel: i32,
+            height_strength: f64,
+            height_in_alpha: bool,
+            flip_green: bool,
+            renormalize_normals: bool,
+            spray_game: i32,
+            auto_format: bool,
             resize_mode: i32,
             custom_width: i32,
             custom_height: i32
@@ -197,7 +252,7 @@ pub mod qobject {
         // Takes a QStringList of image paths and output directory
         // Returns number of successful conversions
         #[qinvokable]
-    fn batch_import_images_to_vtf(self: &VFileXApp, image_paths: &QStringList, output_dir: &QString, generate_mipmaps: bool, is_normal_map: bool, clamp: bool, no_lod: bool, resize_mode: i32, custom_width: i32, custom_height: i32) -> i32;
+    fn batch_import_images_to_vtf(self: &VFileXApp, image_paths: &QStringList, output_dir: &QString, generate_mipmaps: bool, is_normal_map: bool, clamp: bool, no_lod: bool, dilate_edges: bool, height_kernel: i32, height_strength: f64, height_in_alpha: bool, flip_green: bool, renormalize_normals: bool, spray_game: i32, auto_format: bool, resize_mode: i32, custom_width: i32, custom_height: i32) -> i32;
 
         // Get list of available themes
         // Returns list of theme names (without .toml extension)
@@ -253,7 +308,11 @@ pub mod qobject {
 use crate::schema::ShaderRegistry;
 use crate::bridge::qt_helpers;
 use crate::vpk_archive::{count_vpk_archives, VPK_MANAGER};
-use crate::vtf::{VtfBuilder, VtfDecoder, VtfError};
+use crate::normal_map::{self, NormalMapOp};
+use crate::vtf::{
+    CubemapLayout, DEFAULT_MAX_ERROR, HeightKernel, HeightToNormal, MipPolicy, SprayGame,
+    TranscodeOptions, VtfBuilder, VtfDecoder, VtfError, VtfFormat, VtfVersion, vtfx_to_vtf,
+};
 use qobject::*;
 
 const APP_NAME: &str = "VFileX";
@@ -406,15 +465,134 @@ impl qobject::VFileXApp {
         let source_str = source.to_string();
         let dest_str = dest.to_string();
 
-        match VtfDecoder::load_file(&source_str) {
-            Ok(vtf) => match vtf.decode_main() {
+        let Ok(vtf) = VtfDecoder::load_file(&source_str) else {
+            return false;
+        };
+
+        // HDR textures keep their range in EXR/HDR and their precision in 16-bit PNG,
+        // sRGB encoded like a 16-bit PNG import expects
+        let lower = dest_str.to_lowercase();
+        let keeps_precision = [".exr", ".hdr", ".png"]
+            .iter()
+            .any(|ext| lower.ends_with(ext));
+        if vtf.header.is_hdr() && keeps_precision {
+            return match vtf.decode_hdr(0, 0) {
                 Ok(frame) => frame.save(&dest_str).is_ok(),
                 Err(_) => false,
-            },
+            };
+        }
+
+        match vtf.decode_main() {
+            Ok(frame) => frame.save(&dest_str).is_ok(),
             Err(_) => false,
         }
     }
 
+    // Export a cubemap VTF as a cross or six face images
+    fn export_cubemap(&self, source: &QString, dest: &QString, layout: &QString) -> bool {
+        let layout = match layout.to_string().as_str() {
+            "vertical" => CubemapLayout::VerticalCross,
+            "faces" => CubemapLayout::Separate,
+            _ => CubemapLayout::HorizontalCross,
+        };
+
+        match VtfDecoder::load_file(source.to_string()) {
+            Ok(vtf) => vtf.export_cubemap(dest.to_string(), 0, 0, layout).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    // Process a normal map VTF and write the result to dest
+    fn process_normal_map(
+        &self,
+        source: &QString,
+        dest: &QString,
+        flip_red: bool,
+        flip_green: bool,
+        renormalize: bool,
+        detail_path: &QString,
+        storage: &QString,
+    ) -> QString {
+        let mut ops = Vec::new();
+        if flip_red {
+            ops.push(NormalMapOp::FlipRed);
+        }
+        if flip_green {
+            ops.push(NormalMapOp::FlipGreen);
+        }
+        if renormalize {
+            ops.push(NormalMapOp::Renormalize);
+        }
+        let detail_path = detail_path.to_string();
+        if !detail_path.is_empty() {
+            match NormalMapOp::detail_from_file(&detail_path) {
+                Ok(detail) => ops.push(detail),
+                Err(e) => return QString::from(format!("ERR: {}", e).as_str()),
+            }
+        }
+
+        let format = match storage.to_string().as_str() {
+            "rgb" => Some(VtfFormat::Rgb888),
+            "uv88" => Some(VtfFormat::Uv88),
+            _ => None,
+        };
+
+        let dest = dest.to_string();
+        let result = VtfDecoder::load_file(source.to_string())
+            .and_then(|vtf| normal_map::process_vtf(&vtf, &ops, format))
+            .and_then(|data| std::fs::write(&dest, data).map_err(VtfError::from));
+        match result {
+            Ok(()) => QString::from(dest.as_str()),
+            Err(e) => QString::from(format!("ERR: {}", e).as_str()),
+        }
+    }
+
+    // Transcode a VTF to another version, format or mip policy and write it to dest
+    fn transcode_vtf(
+        &self,
+        source: &QString,
+        dest: &QString,
+        version_minor: i32,
+        format: i32,
+        mip_policy: i32,
+    ) -> QString {
+        let format = if format < 0 {
+            None
+        } else {
+            match VtfFormat::try_from(format) {
+                Ok(format) => Some(format),
+                Err(e) => return QString::from(format!("ERR: {}", e).as_str()),
+            }
+        };
+        let options = TranscodeOptions {
+            version: (version_minor > 0).then(|| VtfVersion::new(7, version_minor as u32)),
+            format,
+            mips: MipPolicy::from_index(mip_policy).unwrap_or_default(),
+        };
+
+        let dest = dest.to_string();
+        let result = VtfDecoder::load_file(source.to_string())
+            .and_then(|vtf| vtf.transcode(&options))
+            .and_then(|data| std::fs::write(&dest, data).map_err(VtfError::from));
+        match result {
+            Ok(()) => QString::from(dest.as_str()),
+            Err(e) => QString::from(format!("ERR: {}", e).as_str()),
+        }
+    }
+
+    // Rewrite a console VTFX as a PC VTF and write it to dest
+    fn convert_console_vtf(&self, source: &QString, dest: &QString) -> QString {
+        let dest = dest.to_string();
+        let result = std::fs::read(source.to_string())
+            .map_err(VtfError::from)
+            .and_then(|data| vtfx_to_vtf(&data))
+            .and_then(|data| std::fs::write(&dest, data).map_err(VtfError::from));
+        match result {
+            Ok(()) => QString::from(dest.as_str()),
+            Err(e) => QString::from(format!("ERR: {}", e).as_str()),
+        }
+    }
+
     // Open a path in the system file browser
     fn reveal_in_explorer(&self, path: &QString) {
         let path_str = path.to_string();
@@ -1017,6 +1195,14 @@ auto_save = {}
         is_normal_map: bool,
         clamp: bool,
         no_lod: bool,
+        dilate_edges: bool,
+        height_kernel: i32,
+        height_strength: f64,
+        height_in_alpha: bool,
+        flip_green: bool,
+        renormalize_normals: bool,
+        spray_game: i32,
+        auto_format: bool,
         resize_mode: i32,
         custom_width: i32,
         custom_height: i32
@@ -1038,6 +1224,15 @@ auto_save = {}
     let final_height: u32;
     let builder_result: Result<VtfBuilder, VtfError>;
 
+        // Normal map clean-up, run on every frame after resizing
+        let mut normal_ops = Vec::new();
+        if is_normal_map && flip_green {
+            normal_ops.push(NormalMapOp::FlipGreen);
+        }
+        if is_normal_map && renormalize_normals {
+            normal_ops.push(NormalMapOp::Renormalize);
+        }
+
         if is_animated_gif {
             // Decode GIF frames
             use image::codecs::gif::GifDecoder;
@@ -1100,7 +1295,11 @@ auto_save = {}
                 } else {
                     buf
                 };
-                frames_raw.push(img_buf.into_raw());
+                let mut raw = img_buf.into_raw();
+                if let Err(e) = normal_map::apply(&mut raw, fw, fh, &normal_ops) {
+                    return QString::from(format!("ERR: Failed to process normal map: {}", e).as_str());
+                }
+                frames_raw.push(raw);
             }
 
             builder_result = VtfBuilder::from_frames(final_width, final_height, frames_raw);
@@ -1147,7 +1346,7 @@ auto_save = {}
             };
 
             // Resize if needed
-            let final_data = if final_width != width || final_height != height {
+            let mut final_data = if final_width != width || final_height != height {
                 let resized = image::imageops::resize(
                     &rgba,
                     final_width,
@@ -1159,19 +1358,44 @@ auto_save = {}
                 rgba.into_raw()
             };
 
+            if let Err(e) = normal_map::apply(&mut final_data, final_width, final_height, &normal_ops) {
+                return QString::from(format!("ERR: Failed to process normal map: {}", e).as_str());
+            }
+
             builder_result = Ok(VtfBuilder::new(final_width, final_height, final_data));
         }
         
         // Build VTF from constructed builder
-        let builder = match builder_result {
+        let mut builder = match builder_result {
             Ok(b) => b
                 .mipmaps(generate_mipmaps)
                 .normal_map(is_normal_map)
                 .clamp(clamp)
-                .no_lod(no_lod),
+                .no_lod(no_lod)
+                .dilate(dilate_edges),
             Err(e) => return QString::from(format!("ERR: Failed to build VTF builder: {}", e).as_str()),
         };
 
+        // Height maps become normal maps, wrapping at the edges unless clamped
+        if height_kernel > 0 {
+            builder = builder.normal_from_height(HeightToNormal {
+                kernel: HeightKernel::from_index(height_kernel - 1),
+                strength: height_strength as f32,
+                wrap: !clamp,
+                height_in_alpha,
+            });
+        }
+
+        // Pick the format from the image content
+        if auto_format {
+            builder = builder.auto_format(DEFAULT_MAX_ERROR);
+        }
+
+        // Sprays pick their own size and format to fit the game's limits
+        if let Some(game) = SprayGame::from_index(spray_game - 1) {
+            builder = builder.spray(game);
+        }
+
         match builder.save(&output) {
             Ok(_) => QString::from(output.as_str()),
             Err(e) => QString::from(format!("ERR: Failed to save VTF: {}", e).as_str()),
@@ -1198,7 +1422,7 @@ auto_save = {}
     }
 
     // Batch convert images to VTF
-    fn batch_import_images_to_vtf(&self, image_paths: &QStringList, output_dir: &QString, generate_mipmaps: bool, is_normal_map: bool, clamp: bool, no_lod: bool, resize_mode: i32, custom_width: i32, custom_height: i32) -> i32 {
+    fn batch_import_images_to_vtf(&self, image_paths: &QStringList, output_dir: &QString, generate_mipmaps: bool, is_normal_map: bool, clamp: bool, no_lod: bool, dilate_edges: bool, height_kernel: i32, height_strength: f64, height_in_alpha: bool, flip_green: bool, renormalize_normals: bool, spray_game: i32, auto_format: bool, resize_mode: i32, custom_width: i32, custom_height: i32) -> i32 {
         let output_directory = output_dir.to_string();
         let mut success_count = 0;
         
@@ -1228,7 +1452,8 @@ auto_save = {}
                 
                 let result = self.import_image_to_vtf(
                     &input_qstr, &output_qstr, generate_mipmaps, is_normal_map,
-                    clamp, no_lod, resize_mode, custom_width, custom_height
+                    clamp, no_lod, dilate_edges, height_kernel, height_strength, height_in_alpha,
+                    flip_green, renormalize_normals, spray_game, auto_format, resize_mode, custom_width, custom_height
                 );
                 if !result.to_string().starts_with("ERR:") {
                     success_count += 1;
@@ -9,7 +9,7 @@ use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 
 use crate::vpk_archive::VPK_MANAGER;
-use crate::vtf::{DecodedFrame, VtfDecoder, VtfImage};
+use crate::vtf::{DecodedFrame, Tonemap, VtfDecoder, VtfEditor, VtfFlags, VtfImage, VtfResult};
 
 /// Convert a local file path to a proper file:// URL
 /// On Windows: C:\path\to\file -> file:///C:/path/to/file
@@ -61,9 +61,17 @@ pub mod qobject {
         #[qproperty(i32, current_frame)]
         #[qproperty(i32, mipmap_count)]
         #[qproperty(i32, current_mipmap)]
+        #[qproperty(i32, slice_count)]
+        #[qproperty(i32, current_slice)]
+        #[qproperty(bool, is_hdr)]
+        #[qproperty(f64, exposure)]
+        #[qproperty(i32, tonemap_mode)]
         #[qproperty(bool, has_alpha)]
         #[qproperty(bool, is_animated)]
         #[qproperty(QString, format_name)]
+        #[qproperty(i32, texture_flags)]
+        #[qproperty(f64, bumpmap_scale)]
+        #[qproperty(i32, first_frame)]
         #[qproperty(QString, error_message)]
         #[qproperty(bool, is_loaded)]
         type TextureProvider = super::TextureProviderRust;
@@ -107,6 +115,18 @@ pub mod qobject {
         #[qinvokable]
         fn set_mipmap(self: Pin<&mut TextureProvider>, level: i32);
 
+        // Set the current depth slice (for volume textures)
+        #[qinvokable]
+        fn set_slice(self: Pin<&mut TextureProvider>, slice: i32);
+
+        // Set the preview exposure in stops (HDR textures)
+        #[qinvokable]
+        fn set_preview_exposure(self: Pin<&mut TextureProvider>, stops: f64);
+
+        // Set the preview tonemap operator (HDR textures): 0 clamp, 1 Reinhard, 2 ACES
+        #[qinvokable]
+        fn set_preview_tonemap(self: Pin<&mut TextureProvider>, mode: i32);
+
         // Save the current frame as an image file
         #[qinvokable]
         fn save_as_image(self: &TextureProvider, path: &QString) -> bool;
@@ -119,6 +139,16 @@ pub mod qobject {
         #[qinvokable]
         fn get_texture_info(self: &TextureProvider) -> QString;
 
+        // Write new header flags, bump scale and first frame into the loaded file
+        // without re-encoding it, then reload. Textures from VPKs are read-only.
+        #[qinvokable]
+        fn save_header(
+            self: Pin<&mut TextureProvider>,
+            flags: i32,
+            bumpmap_scale: f64,
+            first_frame: i32,
+        ) -> bool;
+
         // Get a temporary file path with the current frame saved as PNG
         // Returns empty string if no texture is loaded
         #[qinvokable]
@@ -145,6 +175,14 @@ pub mod qobject {
         #[qsignal]
         fn mipmap_changed(self: Pin<&mut TextureProvider>);
 
+        // Emitted when the depth slice changes
+        #[qsignal]
+        fn slice_changed(self: Pin<&mut TextureProvider>);
+
+        // Emitted when the HDR exposure or tonemap operator changes
+        #[qsignal]
+        fn tonemap_changed(self: Pin<&mut TextureProvider>);
+
         // Emitted when an error occurs
         #[qsignal]
         fn error_occurred(self: Pin<&mut TextureProvider>, message: QString);
@@ -153,16 +191,17 @@ pub mod qobject {
 
 use qobject::*;
 
+// Textures bigger than this on either side open on a smaller mip
+const MAX_INITIAL_PREVIEW_SIZE: u32 = 4096;
+
 // Rust implementation of the texture provider
 pub struct TextureProviderRust {
     // Loaded VTF image
     vtf_image: Option<VtfImage>,
-    // Current decoded frame
-    current_decoded: Option<DecodedFrame>,
+    // Current decoded frame, shared with the image's frame cache
+    current_decoded: Option<Arc<DecodedFrame>>,
     // Cached preview path
     preview_path: Option<PathBuf>,
-    // Pre-cached frame paths for animated textures (frame index -> path)
-    frame_cache: HashMap<(i32, i32), PathBuf>,  // (frame, mipmap) -> path
 
     // Q_PROPERTY backing fields
     current_texture: QString,
@@ -172,9 +211,17 @@ pub struct TextureProviderRust {
     current_frame: i32,
     mipmap_count: i32,
     current_mipmap: i32,
+    slice_count: i32,
+    current_slice: i32,
+    is_hdr: bool,
+    exposure: f64,
+    tonemap_mode: i32,
     has_alpha: bool,
     is_animated: bool,
     format_name: QString,
+    texture_flags: i32,
+    bumpmap_scale: f64,
+    first_frame: i32,
     error_message: QString,
     is_loaded: bool,
 }
@@ -185,7 +232,6 @@ impl Default for TextureProviderRust {
             vtf_image: None,
             current_decoded: None,
             preview_path: None,
-            frame_cache: HashMap::new(),
             current_texture: QString::default(),
             texture_width: 0,
             texture_height: 0,
@@ -193,9 +239,17 @@ impl Default for TextureProviderRust {
             current_frame: 0,
             mipmap_count: 0,
             current_mipmap: 0,
+            slice_count: 0,
+            current_slice: 0,
+            is_hdr: false,
+            exposure: 0.0,
+            tonemap_mode: 0,
             has_alpha: false,
             is_animated: false,
             format_name: QString::default(),
+            texture_flags: 0,
+            bumpmap_scale: 1.0,
+            first_frame: 0,
             error_message: QString::default(),
             is_loaded: false,
         }
@@ -207,19 +261,25 @@ impl qobject::TextureProvider {
     fn load_texture(mut self: Pin<&mut Self>, path: &QString) -> bool {
         let path_str = path.to_string();
 
-        match VtfDecoder::load_file(&path_str) {
+        // Streamed so only the mips and frames that get shown are read from disk. Not
+        // mapped: the file stays open while it's previewed, and another tool rewriting
+        // it would crash a mapping instead of failing a read.
+        let opened = std::fs::File::open(&path_str)
+            .map_err(Into::into)
+            .and_then(VtfDecoder::from_reader)
+            .map(|mut vtf| {
+                vtf.file_path = Some(path_str.clone());
+                vtf
+            });
+        match opened {
             Ok(vtf) => {
-                // Clear old frame cache
-                self.as_mut().rust_mut().frame_cache.clear();
-                
                 self.as_mut().update_from_vtf(&vtf);
                 self.as_mut().set_current_texture(path.clone());
-                
-                // Pre-cache all frames for animated textures to prevent stutter
-                if vtf.is_animated() && vtf.header.frames > 1 {
-                    self.as_mut().precache_animation_frames(&vtf);
-                }
-                
+
+                // Huge textures open on a smaller mip, the full one decodes on request
+                let mipmap = vtf.preview_mip(MAX_INITIAL_PREVIEW_SIZE);
+                self.as_mut().set_current_mipmap(mipmap as i32);
+
                 self.as_mut().rust_mut().vtf_image = Some(vtf);
 
                 // Decode the first frame
@@ -238,32 +298,6 @@ impl qobject::TextureProvider {
         }
     }
     
-    // Pre-cache all animation frames to prevent stutter during playback
-    fn precache_animation_frames(mut self: Pin<&mut Self>, vtf: &VtfImage) {
-        let frame_count = vtf.header.frames;
-        let temp_dir = std::env::temp_dir();
-        
-        // Use mipmap 0 for full quality, or 1 if many mipmaps available
-        let mipmap: i32 = if vtf.header.mipmap_count > 3 { 1 } else { 0 };
-        
-        for frame in 0..frame_count {
-            let preview_path = temp_dir.join(format!("VFileX_preview_{}_{}.png", frame, mipmap));
-            
-            // Skip if already cached on disk
-            if preview_path.exists() {
-                self.as_mut().rust_mut().frame_cache.insert((frame as i32, mipmap), preview_path);
-                continue;
-            }
-            
-            // Decode and save this frame
-            if let Ok(decoded) = vtf.decode(mipmap as u8, frame) {
-                if decoded.save(preview_path.to_str().unwrap_or("")).is_ok() {
-                    self.as_mut().rust_mut().frame_cache.insert((frame as i32, mipmap), preview_path);
-                }
-            }
-        }
-    }
-
     // Load a VTF texture from a material's base texture path
     fn load_from_material_path(
         mut self: Pin<&mut Self>,
@@ -436,7 +470,7 @@ impl qobject::TextureProvider {
 
     // Helper: Generate thumbnail from a file path
     fn generate_thumbnail_from_file(&self, vtf_path: &Path, thumbnail_path: &Path) -> QString {
-        match VtfDecoder::load_file(vtf_path.to_string_lossy().as_ref()) {
+        match VtfDecoder::open_mapped(vtf_path) {
             Ok(vtf) => self.generate_thumbnail_from_vtf(&vtf, thumbnail_path),
             Err(_) => QString::default(),
         }
@@ -526,6 +560,44 @@ impl qobject::TextureProvider {
         self.as_mut().mipmap_changed();
     }
 
+    // Set the current depth slice
+    fn set_slice(mut self: Pin<&mut Self>, slice: i32) {
+        if slice < 0 || slice >= self.slice_count {
+            return;
+        }
+
+        self.as_mut().set_current_slice(slice);
+        self.as_mut().decode_current_frame();
+        self.as_mut().slice_changed();
+    }
+
+    // Set the preview exposure in stops
+    fn set_preview_exposure(mut self: Pin<&mut Self>, stops: f64) {
+        let stops = stops.clamp(-16.0, 16.0);
+        if stops == self.exposure {
+            return;
+        }
+
+        self.as_mut().set_exposure(stops);
+        if self.is_hdr {
+            self.as_mut().decode_current_frame();
+        }
+        self.as_mut().tonemap_changed();
+    }
+
+    // Set the preview tonemap operator
+    fn set_preview_tonemap(mut self: Pin<&mut Self>, mode: i32) {
+        if !(0..=2).contains(&mode) || mode == self.tonemap_mode {
+            return;
+        }
+
+        self.as_mut().set_tonemap_mode(mode);
+        if self.is_hdr {
+            self.as_mut().decode_current_frame();
+        }
+        self.as_mut().tonemap_changed();
+    }
+
     // Save the current frame as an image file
     fn save_as_image(&self, path: &QString) -> bool {
         self.current_decoded
@@ -538,7 +610,6 @@ impl qobject::TextureProvider {
     fn clear(mut self: Pin<&mut Self>) {
         self.as_mut().rust_mut().vtf_image = None;
         self.as_mut().rust_mut().current_decoded = None;
-        self.as_mut().rust_mut().frame_cache.clear();
         self.as_mut().set_current_texture(QString::default());
         self.as_mut().set_texture_width(0);
         self.as_mut().set_texture_height(0);
@@ -546,9 +617,15 @@ impl qobject::TextureProvider {
         self.as_mut().set_current_frame(0);
         self.as_mut().set_mipmap_count(0);
         self.as_mut().set_current_mipmap(0);
+        self.as_mut().set_slice_count(0);
+        self.as_mut().set_current_slice(0);
+        self.as_mut().set_is_hdr(false);
         self.as_mut().set_has_alpha(false);
         self.as_mut().set_is_animated(false);
         self.as_mut().set_format_name(QString::default());
+        self.as_mut().set_texture_flags(0);
+        self.as_mut().set_bumpmap_scale(1.0);
+        self.as_mut().set_first_frame(0);
         self.as_mut().set_error_message(QString::default());
         self.as_mut().set_is_loaded(false);
     }
@@ -572,6 +649,48 @@ impl qobject::TextureProvider {
         }
     }
 
+    // Write header edits into the loaded file and reload it
+    fn save_header(
+        mut self: Pin<&mut Self>,
+        flags: i32,
+        bumpmap_scale: f64,
+        first_frame: i32,
+    ) -> bool {
+        let path = self.current_texture.to_string();
+        let result = if path.starts_with("vpk:") {
+            Err("Textures inside VPKs can't be edited".to_string())
+        } else {
+            // Close the file first, Windows won't rewrite a file that's open
+            self.as_mut().rust_mut().vtf_image = None;
+            self.as_mut().rust_mut().current_decoded = None;
+            VtfEditor::open(&path)
+                .and_then(|mut editor| {
+                    editor.header.flags = VtfFlags::from_bits_truncate(flags as u32);
+                    editor.header.bumpmap_scale = bumpmap_scale as f32;
+                    editor.header.first_frame = first_frame as u16;
+                    editor.save()
+                })
+                .map_err(|e| e.to_string())
+        };
+
+        match result {
+            Ok(()) => {
+                tex_log!("✓ Saved header: {}", path);
+                self.load_texture(&QString::from(path.as_str()))
+            }
+            Err(e) => {
+                tex_log!("✗ Failed to save header: {}", e);
+                if self.vtf_image.is_none() {
+                    self.as_mut().load_texture(&QString::from(path.as_str()));
+                }
+                let msg = QString::from(format!("Failed to save header: {}", e).as_str());
+                self.as_mut().set_error_message(msg.clone());
+                self.as_mut().error_occurred(msg);
+                false
+            }
+        }
+    }
+
     // Update properties from a VTF image
     fn update_from_vtf(mut self: Pin<&mut Self>, vtf: &VtfImage) {
         self.as_mut().set_texture_width(vtf.header.width as i32);
@@ -582,11 +701,19 @@ impl qobject::TextureProvider {
         self.as_mut()
             .set_mipmap_count(vtf.header.mipmap_count as i32);
         self.as_mut().set_current_mipmap(0);
+        self.as_mut().set_slice_count(vtf.depth() as i32);
+        self.as_mut().set_current_slice(0);
+        self.as_mut().set_is_hdr(vtf.header.is_hdr());
         self.as_mut().set_has_alpha(vtf.has_alpha());
         self.as_mut().set_is_animated(vtf.is_animated());
         self.as_mut().set_format_name(QString::from(
             format!("{:?}", vtf.header.high_res_format).as_str(),
         ));
+        self.as_mut()
+            .set_texture_flags(vtf.header.flags.bits() as i32);
+        self.as_mut()
+            .set_bumpmap_scale(vtf.header.bumpmap_scale as f64);
+        self.as_mut().set_first_frame(vtf.header.first_frame as i32);
         self.as_mut().set_is_loaded(true);
         self.as_mut().set_error_message(QString::default());
     }
@@ -596,8 +723,22 @@ impl qobject::TextureProvider {
         let frame = self.current_frame as u16;
         let mipmap = self.current_mipmap as u8;
 
+        // Volume textures lose depth with every mip, keep the slice in range
+        let depth = self
+            .vtf_image
+            .as_ref()
+            .map(|vtf| vtf.header.mipmap_depth(mipmap) as i32)
+            .unwrap_or(0);
+        if depth != self.slice_count {
+            self.as_mut().set_slice_count(depth);
+        }
+        if self.current_slice >= depth.max(1) {
+            self.as_mut().set_current_slice(depth.max(1) - 1);
+        }
+        let slice = self.current_slice as u32;
+
         if let Some(ref vtf) = self.vtf_image {
-            match vtf.decode(mipmap, frame) {
+            match self.decode_for_display(vtf, mipmap, frame, slice) {
                 Ok(decoded) => {
                     // Update dimensions for current mipmap
                     self.as_mut().set_texture_width(decoded.width as i32);
@@ -613,6 +754,25 @@ impl qobject::TextureProvider {
         }
     }
 
+    // Decode a slice for display, tonemapping HDR formats with the preview exposure.
+    // Other formats go through the image's frame cache, so stepping back and forth
+    // through an animation decodes each frame once.
+    fn decode_for_display(
+        &self,
+        vtf: &VtfImage,
+        mipmap: u8,
+        frame: u16,
+        slice: u32,
+    ) -> VtfResult<Arc<DecodedFrame>> {
+        if !vtf.header.is_hdr() {
+            return vtf.decode_cached(mipmap, frame, slice);
+        }
+
+        let tonemap = Tonemap::from_index(self.tonemap_mode);
+        vtf.decode_slice_hdr(mipmap, frame, slice)
+            .map(|decoded| Arc::new(decoded.tonemap(self.exposure as f32, tonemap)))
+    }
+
     // Get a temporary file path with the current frame saved as PNG
     fn get_preview_path(mut self: Pin<&mut Self>) -> QString {
         let frame = self.current_frame;
@@ -626,14 +786,8 @@ impl qobject::TextureProvider {
             }
             prefer
         } else { self.current_mipmap };
-        
-        // Check if we have this frame pre-cached (for animated textures)
-        if let Some(cached_path) = self.frame_cache.get(&(frame, mipmap)) {
-            if cached_path.exists() {
-                return QString::from(cached_path.to_str().unwrap_or(""));
-            }
-        }
-        
+        let slice = self.current_slice;
+
         // Make sure we have a decoded frame
         if self.current_decoded.is_none() && self.vtf_image.is_some() {
             self.as_mut().decode_current_frame();
@@ -646,7 +800,8 @@ impl qobject::TextureProvider {
         // At this point we have a decoded frame
         let decoded = self.current_decoded.as_ref().unwrap();
         let temp_dir = std::env::temp_dir();
-        let preview_path = temp_dir.join(format!("VFileX_preview_{}_{}.png", frame, mipmap));
+        let preview_path =
+            temp_dir.join(format!("VFileX_preview_{}_{}_{}.png", frame, mipmap, slice));
 
         // If the decoded frame matches our desired mipmap, save that; otherwise decode new one
         if decoded.mipmap_level as i32 == mipmap as i32 {
@@ -666,7 +821,8 @@ impl qobject::TextureProvider {
 
         // Otherwise decode the requested mipmap for preview (without mutating provider state)
         if let Some(ref vtf) = self.vtf_image {
-            match vtf.decode(mipmap as u8, frame as u16) {
+            let preview_slice = (slice as u32).min(vtf.header.mipmap_depth(mipmap as u8) - 1);
+            match self.decode_for_display(vtf, mipmap as u8, frame as u16, preview_slice) {
                 Ok(decoded_preview) => {
                     match decoded_preview.save(preview_path.to_str().unwrap_or("")) {
                         Ok(_) => {
@@ -758,8 +914,9 @@ impl TextureCache {
             return Ok(Arc::clone(vtf));
         }
 
-        // Load the texture
-        let vtf = VtfDecoder::load_file(path)?;
+        // Load the texture. Cached images live on, so stream rather than map them.
+        let mut vtf = VtfDecoder::from_reader(std::fs::File::open(path)?)?;
+        vtf.file_path = Some(path.to_string());
         let vtf = Arc::new(vtf);
 
         // Add to cache (simple LRU: just clear if full)
@@ -0,0 +1,371 @@
+//! Channel packing: build one texture out of several source images, the way
+//! Source shaders expect masks to be stored in spare channels
+
+use crate::schema::{SHADER_SCHEMAS, ShaderDef};
+use crate::vtf::{VtfBuilder, VtfError, VtfResult};
+use std::path::Path;
+
+// An RGBA channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    R = 0,
+    G = 1,
+    B = 2,
+    A = 3,
+}
+
+// Where an output channel takes its values from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelSource {
+    // One channel of a source image, by the order the images were added
+    Image {
+        image: usize,
+        channel: Channel,
+        invert: bool,
+    },
+    // Rec. 709 luma of a source image, for grayscale masks saved as RGB
+    Luminance {
+        image: usize,
+        invert: bool,
+    },
+    // The same value everywhere
+    Constant(u8),
+}
+
+impl ChannelSource {
+    pub fn image(image: usize, channel: Channel) -> Self {
+        ChannelSource::Image {
+            image,
+            channel,
+            invert: false,
+        }
+    }
+
+    pub fn luminance(image: usize) -> Self {
+        ChannelSource::Luminance {
+            image,
+            invert: false,
+        }
+    }
+
+    // Same source, with 255 - value
+    pub fn inverted(self) -> Self {
+        match self {
+            ChannelSource::Image {
+                image,
+                channel,
+                invert,
+            } => ChannelSource::Image {
+                image,
+                channel,
+                invert: !invert,
+            },
+            ChannelSource::Luminance { image, invert } => ChannelSource::Luminance {
+                image,
+                invert: !invert,
+            },
+            ChannelSource::Constant(value) => ChannelSource::Constant(255 - value),
+        }
+    }
+
+    fn image_index(&self) -> Option<usize> {
+        match self {
+            ChannelSource::Image { image, .. } | ChannelSource::Luminance { image, .. } => {
+                Some(*image)
+            }
+            ChannelSource::Constant(_) => None,
+        }
+    }
+}
+
+// Packing conventions for the masks in the phong and envmap parameter groups.
+// Each preset is named after the material parameter that reads it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelPreset {
+    // Base color in RGB, envmap mask in alpha
+    BaseAlphaEnvmapMask,
+    // Normal map in RGB, envmap mask in alpha
+    NormalMapAlphaEnvmapMask,
+    // Base color in RGB, phong mask in alpha
+    BaseMapAlphaPhongMask,
+    // Phong exponent in R, albedo tint mask in G, rim mask in A
+    PhongExponentTexture,
+}
+
+impl ChannelPreset {
+    pub const ALL: [ChannelPreset; 4] = [
+        ChannelPreset::BaseAlphaEnvmapMask,
+        ChannelPreset::NormalMapAlphaEnvmapMask,
+        ChannelPreset::BaseMapAlphaPhongMask,
+        ChannelPreset::PhongExponentTexture,
+    ];
+
+    // The material parameter this layout is for
+    pub fn parameter(&self) -> &'static str {
+        match self {
+            ChannelPreset::BaseAlphaEnvmapMask => "$basealphaenvmapmask",
+            ChannelPreset::NormalMapAlphaEnvmapMask => "$normalmapalphaenvmapmask",
+            ChannelPreset::BaseMapAlphaPhongMask => "$basemapalphaphongmask",
+            ChannelPreset::PhongExponentTexture => "$phongexponenttexture",
+        }
+    }
+
+    pub fn from_parameter(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.parameter().eq_ignore_ascii_case(name))
+    }
+
+    // Display name of the parameter from the shader schemas
+    pub fn display_name(&self) -> String {
+        SHADER_SCHEMAS
+            .iter()
+            .find_map(|shader| shader.get_parameter(self.parameter()))
+            .map(|param| param.display_name.clone())
+            .unwrap_or_else(|| self.parameter().to_string())
+    }
+
+    // What each source image should be, in the order they're added
+    pub fn inputs(&self) -> &'static [&'static str] {
+        match self {
+            ChannelPreset::BaseAlphaEnvmapMask => &["Base texture", "Envmap mask"],
+            ChannelPreset::NormalMapAlphaEnvmapMask => &["Normal map", "Envmap mask"],
+            ChannelPreset::BaseMapAlphaPhongMask => &["Base texture", "Phong mask"],
+            ChannelPreset::PhongExponentTexture => {
+                &["Phong exponent", "Albedo tint mask", "Rim mask"]
+            }
+        }
+    }
+
+    pub fn channels(&self) -> [ChannelSource; 4] {
+        use ChannelSource as S;
+        match self {
+            ChannelPreset::BaseAlphaEnvmapMask
+            | ChannelPreset::NormalMapAlphaEnvmapMask
+            | ChannelPreset::BaseMapAlphaPhongMask => [
+                S::image(0, Channel::R),
+                S::image(0, Channel::G),
+                S::image(0, Channel::B),
+                S::luminance(1),
+            ],
+            // Blue is unused by the shader
+            ChannelPreset::PhongExponentTexture => [
+                S::luminance(0),
+                S::luminance(1),
+                S::Constant(0),
+                S::luminance(2),
+            ],
+        }
+    }
+
+    // Presets for the phong and envmap parameters this shader supports
+    pub fn for_shader(shader: &ShaderDef) -> Vec<ChannelPreset> {
+        Self::ALL
+            .into_iter()
+            .filter(|preset| {
+                shader
+                    .get_parameter(preset.parameter())
+                    .is_some_and(|param| matches!(param.category.as_str(), "Phong" | "Reflections"))
+            })
+            .collect()
+    }
+}
+
+// Builds RGBA data from several same-sized images, then hands it to a VtfBuilder
+pub struct ChannelComposer {
+    width: u32,
+    height: u32,
+    images: Vec<Vec<u8>>,
+    channels: [ChannelSource; 4],
+    preset: Option<ChannelPreset>,
+}
+
+impl ChannelComposer {
+    // Starts out as opaque black
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            images: Vec::new(),
+            channels: [
+                ChannelSource::Constant(0),
+                ChannelSource::Constant(0),
+                ChannelSource::Constant(0),
+                ChannelSource::Constant(255),
+            ],
+            preset: None,
+        }
+    }
+
+    // Add a source image as RGBA8 bytes. Images are numbered in the order added.
+    pub fn image(mut self, rgba: Vec<u8>) -> VtfResult<Self> {
+        if rgba.len() != (self.width * self.height * 4) as usize {
+            return Err(VtfError::InvalidData(format!(
+                "Source image {} does not match {}x{}",
+                self.images.len(),
+                self.width,
+                self.height
+            )));
+        }
+        self.images.push(rgba);
+        Ok(self)
+    }
+
+    // Add a source image from a file, resized to the composer's size if needed
+    pub fn image_file<P: AsRef<Path>>(self, path: P) -> VtfResult<Self> {
+        let img = image::open(path)
+            .map_err(|e| VtfError::InvalidData(format!("Failed to load image: {}", e)))?
+            .to_rgba8();
+        let img = if img.dimensions() != (self.width, self.height) {
+            image::imageops::resize(
+                &img,
+                self.width,
+                self.height,
+                image::imageops::FilterType::Lanczos3,
+            )
+        } else {
+            img
+        };
+        self.image(img.into_raw())
+    }
+
+    // Fill one output channel
+    pub fn channel(mut self, channel: Channel, source: ChannelSource) -> Self {
+        self.channels[channel as usize] = source;
+        self
+    }
+
+    // Use a preset's channel layout. Individual channels can still be changed after.
+    pub fn preset(mut self, preset: ChannelPreset) -> Self {
+        self.channels = preset.channels();
+        self.preset = Some(preset);
+        self
+    }
+
+    // Pack the channels into RGBA8
+    pub fn compose(&self) -> VtfResult<Vec<u8>> {
+        for (index, source) in self.channels.iter().enumerate() {
+            if let Some(image) = source.image_index()
+                && image >= self.images.len()
+            {
+                return Err(VtfError::InvalidData(format!(
+                    "Channel {} reads image {} but only {} were added",
+                    index,
+                    image,
+                    self.images.len()
+                )));
+            }
+        }
+
+        let pixels = (self.width * self.height) as usize;
+        let mut output = vec![0u8; pixels * 4];
+        for (index, source) in self.channels.iter().enumerate() {
+            for (pixel, out) in output.chunks_exact_mut(4).enumerate() {
+                let value = match *source {
+                    ChannelSource::Image {
+                        image,
+                        channel,
+                        invert,
+                    } => {
+                        let value = self.images[image][pixel * 4 + channel as usize];
+                        if invert { 255 - value } else { value }
+                    }
+                    ChannelSource::Luminance { image, invert } => {
+                        let p = &self.images[image][pixel * 4..pixel * 4 + 4];
+                        let value =
+                            (0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32)
+                                .round() as u8;
+                        if invert { 255 - value } else { value }
+                    }
+                    ChannelSource::Constant(value) => value,
+                };
+                out[index] = value;
+            }
+        }
+        Ok(output)
+    }
+
+    // A VtfBuilder for the packed texture. The normal map preset is flagged as one.
+    pub fn into_builder(self) -> VtfResult<VtfBuilder> {
+        let rgba = self.compose()?;
+        let is_normal = self.preset == Some(ChannelPreset::NormalMapAlphaEnvmapMask);
+        Ok(VtfBuilder::new(self.width, self.height, rgba).normal_map(is_normal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vtf::{VtfDecoder, VtfFormat};
+
+    fn solid(rgba: [u8; 4]) -> Vec<u8> {
+        rgba.repeat(4)
+    }
+
+    #[test]
+    fn test_compose_mapping_inversion_and_constants() {
+        let composer = ChannelComposer::new(2, 2)
+            .image(solid([10, 20, 30, 40]))
+            .unwrap()
+            .image(solid([100, 100, 100, 255]))
+            .unwrap()
+            .channel(Channel::R, ChannelSource::image(0, Channel::A))
+            .channel(Channel::G, ChannelSource::image(1, Channel::G).inverted())
+            .channel(Channel::B, ChannelSource::Constant(77))
+            .channel(Channel::A, ChannelSource::luminance(1));
+        assert_eq!(composer.compose().unwrap(), solid([40, 155, 77, 100]));
+
+        // Referencing an image that was never added is an error
+        let missing = ChannelComposer::new(2, 2).channel(Channel::R, ChannelSource::luminance(0));
+        assert!(missing.compose().is_err());
+    }
+
+    #[test]
+    fn test_phong_exponent_preset() {
+        let built = ChannelComposer::new(2, 2)
+            .preset(ChannelPreset::PhongExponentTexture)
+            .image(solid([200, 200, 200, 255]))
+            .unwrap()
+            .image(solid([255, 255, 255, 255]))
+            .unwrap()
+            .image(solid([0, 0, 0, 255]))
+            .unwrap()
+            .into_builder()
+            .unwrap()
+            .format(VtfFormat::Rgba8888)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert_eq!(&vtf.decode(0, 0).unwrap().data[..4], &[200, 255, 0, 0]);
+    }
+
+    #[test]
+    fn test_presets_come_from_schema() {
+        let shader = SHADER_SCHEMAS
+            .iter()
+            .find(|shader| shader.name == "VertexLitGeneric")
+            .unwrap();
+        assert_eq!(
+            ChannelPreset::for_shader(shader),
+            ChannelPreset::ALL.to_vec()
+        );
+
+        for preset in ChannelPreset::ALL {
+            assert_eq!(
+                ChannelPreset::from_parameter(preset.parameter()),
+                Some(preset)
+            );
+            assert_ne!(preset.display_name(), preset.parameter());
+            assert_eq!(
+                preset.inputs().len(),
+                preset
+                    .channels()
+                    .iter()
+                    .filter_map(|source| source.image_index())
+                    .max()
+                    .unwrap()
+                    + 1
+            );
+        }
+    }
+}
@@ -3,6 +3,8 @@
 //! Abandon all hope, ye who enter here.
 //! (Just kidding, it's actually pretty nice)
 pub mod bridge;
+pub mod channel_pack;
+pub mod normal_map;
 pub mod schema;
 pub mod vmt;
 pub mod vpk_archive;
@@ -0,0 +1,282 @@
+//! Normal map processing: convention flips, renormalization, detail blending and
+//! two-channel storage, on RGBA8 pixels or whole VTF files
+
+use crate::vtf::{VtfBuilder, VtfDecoder, VtfError, VtfFormat, VtfImage, VtfResult};
+use std::path::Path;
+
+// One processing step. Steps run in the order given to `apply`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NormalMapOp {
+    // Invert X
+    FlipRed,
+    // Invert Y, converts OpenGL style maps to Source's DirectX convention and back
+    FlipGreen,
+    // Push every normal back to unit length
+    Renormalize,
+    // Rebuild Z from X and Y, for data that came from two-channel storage
+    ReconstructZ,
+    // Reoriented Normal Mapping blend of a detail map, tiled across the base
+    Detail {
+        rgba: Vec<u8>,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl NormalMapOp {
+    // Load a detail normal map from any image file, or from a VTF
+    pub fn detail_from_file<P: AsRef<Path>>(path: P) -> VtfResult<Self> {
+        let path = path.as_ref();
+        let is_vtf = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("vtf"));
+
+        if is_vtf {
+            let vtf = VtfDecoder::load_file(path)?;
+            let mut rgba = vtf.decode(0, 0)?.data;
+            if is_two_channel(vtf.format()) {
+                reconstruct_z(&mut rgba);
+            }
+            return Ok(NormalMapOp::Detail {
+                rgba,
+                width: vtf.width(),
+                height: vtf.height(),
+            });
+        }
+
+        let img = image::open(path)
+            .map_err(|e| VtfError::InvalidData(format!("Failed to load image: {}", e)))?
+            .to_rgba8();
+        Ok(NormalMapOp::Detail {
+            width: img.width(),
+            height: img.height(),
+            rgba: img.into_raw(),
+        })
+    }
+}
+
+// Formats that only keep X and Y, leaving Z to be rebuilt in the shader
+pub fn is_two_channel(format: VtfFormat) -> bool {
+    matches!(format, VtfFormat::Uv88 | VtfFormat::Ati2n)
+}
+
+fn unpack(v: u8) -> f32 {
+    v as f32 / 255.0 * 2.0 - 1.0
+}
+
+fn pack(v: f32) -> u8 {
+    ((v.clamp(-1.0, 1.0) * 0.5 + 0.5) * 255.0).round() as u8
+}
+
+fn read(pixel: &[u8]) -> [f32; 3] {
+    [unpack(pixel[0]), unpack(pixel[1]), unpack(pixel[2])]
+}
+
+fn write(pixel: &mut [u8], normal: [f32; 3]) {
+    let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+    let normal = if length > 1e-6 {
+        normal.map(|n| n / length)
+    } else {
+        [0.0, 0.0, 1.0]
+    };
+    for (out, n) in pixel.iter_mut().zip(normal) {
+        *out = pack(n);
+    }
+}
+
+fn flip(rgba: &mut [u8], channel: usize) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        pixel[channel] = 255 - pixel[channel];
+    }
+}
+
+fn renormalize(rgba: &mut [u8]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let normal = read(pixel);
+        write(pixel, normal);
+    }
+}
+
+fn reconstruct_z(rgba: &mut [u8]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let (x, y) = (unpack(pixel[0]), unpack(pixel[1]));
+        write(pixel, [x, y, (1.0 - x * x - y * y).max(0.0).sqrt()]);
+    }
+}
+
+// Reoriented Normal Mapping (Barré-Brisebois and Hill): rotate the detail normal
+// so its "up" follows the base normal
+fn blend_rnm(base: [f32; 3], detail: [f32; 3]) -> [f32; 3] {
+    let t = [base[0], base[1], base[2] + 1.0];
+    let u = [-detail[0], -detail[1], detail[2]];
+    let scale = (t[0] * u[0] + t[1] * u[1] + t[2] * u[2]) / t[2];
+    [
+        t[0] * scale - u[0],
+        t[1] * scale - u[1],
+        t[2] * scale - u[2],
+    ]
+}
+
+// Run the steps over RGBA8 pixels in place. Alpha is never touched.
+pub fn apply(rgba: &mut [u8], width: u32, height: u32, ops: &[NormalMapOp]) -> VtfResult<()> {
+    if rgba.len() != (width * height * 4) as usize {
+        return Err(VtfError::InvalidData("Image size mismatch".into()));
+    }
+
+    for op in ops {
+        match op {
+            NormalMapOp::FlipRed => flip(rgba, 0),
+            NormalMapOp::FlipGreen => flip(rgba, 1),
+            NormalMapOp::Renormalize => renormalize(rgba),
+            NormalMapOp::ReconstructZ => reconstruct_z(rgba),
+            NormalMapOp::Detail {
+                rgba: detail,
+                width: detail_width,
+                height: detail_height,
+            } => {
+                let (dw, dh) = (*detail_width as usize, *detail_height as usize);
+                if dw == 0 || dh == 0 || detail.len() != dw * dh * 4 {
+                    return Err(VtfError::InvalidData("Detail map size mismatch".into()));
+                }
+                for (index, pixel) in rgba.chunks_exact_mut(4).enumerate() {
+                    let (x, y) = (index % width as usize, index / width as usize);
+                    let tap = ((y % dh) * dw + x % dw) * 4;
+                    let blended = blend_rnm(read(pixel), read(&detail[tap..tap + 4]));
+                    write(pixel, blended);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Decode every frame of a 2D normal map VTF, run the steps and encode it again with
+// the same version, flags, frames and mip setup. `format` picks new storage, e.g.
+// Uv88 for two channels or an RGB format to expand a two-channel map.
+pub fn process_vtf(
+    vtf: &VtfImage,
+    ops: &[NormalMapOp],
+    format: Option<VtfFormat>,
+) -> VtfResult<Vec<u8>> {
+    if vtf.is_envmap() || vtf.is_volume() {
+        return Err(VtfError::InvalidData(
+            "Normal map processing needs a 2D texture".into(),
+        ));
+    }
+
+    let (width, height) = (vtf.width(), vtf.height());
+    let two_channel = is_two_channel(vtf.format());
+    let mut frames = Vec::with_capacity(vtf.frame_count() as usize);
+    for decoded in vtf.decode_all_frames(0)? {
+        let mut rgba = decoded.data;
+        // The decoder fills Z with a placeholder for two-channel formats
+        if two_channel {
+            reconstruct_z(&mut rgba);
+        }
+        apply(&mut rgba, width, height, ops)?;
+        frames.push(rgba);
+    }
+
+    VtfBuilder::from_frames(width, height, frames)?
+        .format(format.unwrap_or(vtf.format()))
+        .version(vtf.header.version)
+        .mipmaps(vtf.mipmap_count() > 1)
+        .normal_map(true)
+        .flags(vtf.header.flags)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vtf::VtfFlags;
+
+    fn normal(x: f32, y: f32, z: f32) -> [u8; 4] {
+        let length = (x * x + y * y + z * z).sqrt();
+        [pack(x / length), pack(y / length), pack(z / length), 255]
+    }
+
+    #[test]
+    fn test_flip_and_renormalize() {
+        let mut rgba = [normal(0.5, 0.5, 0.7), [200, 200, 200, 7]].concat();
+        apply(
+            &mut rgba,
+            2,
+            1,
+            &[NormalMapOp::FlipGreen, NormalMapOp::Renormalize],
+        )
+        .unwrap();
+
+        let first = read(&rgba[..4]);
+        assert!(first[0] > 0.0 && first[1] < 0.0);
+        let second = read(&rgba[4..]);
+        let length = second.iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((length - 1.0).abs() < 0.01);
+        assert_eq!(rgba[7], 7);
+    }
+
+    #[test]
+    fn test_detail_blend() {
+        let flat = normal(0.0, 0.0, 1.0);
+        let tilted = normal(0.3, -0.4, 0.866);
+
+        // A flat detail leaves the base alone, and a flat base takes the detail as-is
+        let mut base = tilted.to_vec();
+        let detail = NormalMapOp::Detail {
+            rgba: flat.to_vec(),
+            width: 1,
+            height: 1,
+        };
+        apply(&mut base, 1, 1, &[detail]).unwrap();
+        assert!(base.iter().zip(tilted).all(|(a, b)| a.abs_diff(b) <= 1));
+
+        // Detail maps tile across larger bases
+        let mut base = flat.repeat(4);
+        let detail = NormalMapOp::Detail {
+            rgba: tilted.to_vec(),
+            width: 1,
+            height: 1,
+        };
+        apply(&mut base, 2, 2, &[detail]).unwrap();
+        assert!(
+            base.chunks(4)
+                .all(|p| p.iter().zip(tilted).all(|(a, b)| a.abs_diff(b) <= 1))
+        );
+    }
+
+    #[test]
+    fn test_process_vtf_two_channel_round_trip() {
+        let source = normal(0.3, -0.4, 0.866).repeat(4 * 4 * 2);
+        let frames = vec![source[..64].to_vec(), source[64..].to_vec()];
+        let built = VtfBuilder::from_frames(4, 4, frames)
+            .unwrap()
+            .format(VtfFormat::Rgba8888)
+            .normal_map(true)
+            .clamp(true)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+
+        let packed = process_vtf(&vtf, &[], Some(VtfFormat::Uv88)).unwrap();
+        let packed = VtfDecoder::load_from_memory(&packed).unwrap();
+        assert_eq!(packed.format(), VtfFormat::Uv88);
+        assert_eq!(packed.frame_count(), 2);
+        assert!(
+            packed
+                .header
+                .flags
+                .contains(VtfFlags::CLAMPS | VtfFlags::NORMAL)
+        );
+
+        // Expanding back rebuilds Z
+        let expanded = process_vtf(&packed, &[], Some(VtfFormat::Rgba8888)).unwrap();
+        let expanded = VtfDecoder::load_from_memory(&expanded).unwrap();
+        let top = expanded.decode(0, 1).unwrap();
+        assert!(
+            top.data
+                .iter()
+                .zip(&source)
+                .all(|(a, b)| a.abs_diff(*b) <= 1)
+        );
+    }
+}
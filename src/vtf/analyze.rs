@@ -0,0 +1,236 @@
+//! Content analysis: what a texture's pixels actually use, and the format that fits
+
+use super::VtfResult;
+use super::dxt::{DxtQuality, compress_dxt};
+use super::formats::convert_to_rgba;
+use super::header::{VtfFlags, VtfFormat};
+
+// Root mean square error, in 0-255 steps, above which DXT is considered too lossy
+pub const DEFAULT_MAX_ERROR: f32 = 6.0;
+
+// How much of the alpha channel is in use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AlphaContent {
+    // Fully opaque
+    None,
+    // Only fully opaque or fully transparent
+    OneBit,
+    // Anything in between
+    Full,
+}
+
+impl AlphaContent {
+    // ONEBITALPHA or EIGHTBITALPHA for this content stored in `format`
+    pub fn flags(&self, format: VtfFormat) -> VtfFlags {
+        if !format.has_alpha_channel() || *self == AlphaContent::None {
+            VtfFlags::empty()
+        } else if *self == AlphaContent::OneBit || format == VtfFormat::Dxt1OneBitAlpha {
+            VtfFlags::ONEBITALPHA
+        } else {
+            VtfFlags::EIGHTBITALPHA
+        }
+    }
+}
+
+// What the pixels of an image use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentAnalysis {
+    pub alpha: AlphaContent,
+    // Red, green and blue match everywhere
+    pub grayscale: bool,
+    // Nearly every pixel decodes to a unit-length normal facing out of the surface,
+    // so X and Y carry all of it
+    pub normal_map: bool,
+}
+
+// Alpha content of RGBA8 pixels
+pub fn alpha_content(rgba: &[u8]) -> AlphaContent {
+    rgba.chunks_exact(4)
+        .map(|pixel| match pixel[3] {
+            255 => AlphaContent::None,
+            0 => AlphaContent::OneBit,
+            _ => AlphaContent::Full,
+        })
+        .max()
+        .unwrap_or(AlphaContent::None)
+}
+
+fn is_normal_map(rgba: &[u8]) -> bool {
+    let pixels = rgba.len() / 4;
+    if pixels == 0 {
+        return false;
+    }
+    let unit = rgba
+        .chunks_exact(4)
+        .filter(|pixel| {
+            let [x, y, z] = [pixel[0], pixel[1], pixel[2]].map(|v| v as f32 / 255.0 * 2.0 - 1.0);
+            let length = (x * x + y * y + z * z).sqrt();
+            z > 0.0 && (length - 1.0).abs() < 0.1
+        })
+        .count();
+    unit as f32 >= pixels as f32 * 0.95
+}
+
+pub fn analyze(rgba: &[u8]) -> ContentAnalysis {
+    let grayscale = rgba
+        .chunks_exact(4)
+        .all(|pixel| pixel[0].abs_diff(pixel[1]) <= 1 && pixel[1].abs_diff(pixel[2]) <= 1);
+    ContentAnalysis {
+        alpha: alpha_content(rgba),
+        grayscale,
+        // A gray image is never a useful normal map, flat or not
+        normal_map: !grayscale && is_normal_map(rgba),
+    }
+}
+
+impl ContentAnalysis {
+    // The smallest format that keeps this content, before checking compression error
+    pub fn compact_format(&self) -> VtfFormat {
+        if self.grayscale && !self.normal_map {
+            return if self.alpha == AlphaContent::None {
+                VtfFormat::I8
+            } else {
+                VtfFormat::Ia88
+            };
+        }
+        match self.alpha {
+            AlphaContent::None => VtfFormat::Dxt1,
+            AlphaContent::OneBit => VtfFormat::Dxt1OneBitAlpha,
+            AlphaContent::Full => VtfFormat::Dxt5,
+        }
+    }
+
+    // What to store when compression loses too much
+    pub fn uncompressed_format(&self) -> VtfFormat {
+        if self.alpha == AlphaContent::None {
+            VtfFormat::Bgr888
+        } else {
+            VtfFormat::Bgra8888
+        }
+    }
+}
+
+// Root mean square error of a DXT round trip. Color is weighted by alpha, so
+// transparent texels that DXT1A blacks out don't count against it.
+pub fn compression_error(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    format: VtfFormat,
+    quality: DxtQuality,
+) -> VtfResult<f32> {
+    let compressed = compress_dxt(rgba, width, height, format, quality)?;
+    let decoded = convert_to_rgba(&compressed, format, width, height)?;
+
+    let mut total = 0.0f64;
+    for (source, result) in rgba.chunks_exact(4).zip(decoded.chunks_exact(4)) {
+        let weight = source[3] as f64 / 255.0;
+        for channel in 0..3 {
+            let diff = source[channel] as f64 - result[channel] as f64;
+            total += diff * diff * weight;
+        }
+        if format.has_alpha_channel() {
+            let diff = source[3] as f64 - result[3] as f64;
+            total += diff * diff;
+        }
+    }
+    let channels = if format.has_alpha_channel() { 4 } else { 3 };
+    Ok((total / (rgba.len() / 4 * channels).max(1) as f64).sqrt() as f32)
+}
+
+// Pick a format for a set of same-sized RGBA8 images: the compact format for the
+// content, or an uncompressed one if DXT misses any image by more than `max_error`
+pub fn recommend_format(
+    images: &[&[u8]],
+    width: u32,
+    height: u32,
+    max_error: f32,
+    quality: DxtQuality,
+) -> VtfResult<(VtfFormat, ContentAnalysis)> {
+    let all: Vec<u8> = images.concat();
+    let analysis = analyze(&all);
+    let format = analysis.compact_format();
+    if !format.is_compressed() {
+        return Ok((format, analysis));
+    }
+
+    for image in images {
+        if compression_error(image, width, height, format, quality)? > max_error {
+            return Ok((analysis.uncompressed_format(), analysis));
+        }
+    }
+    Ok((format, analysis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alpha_content_and_flags() {
+        assert_eq!(alpha_content(&[9, 9, 9, 255]), AlphaContent::None);
+        assert_eq!(
+            alpha_content(&[9, 9, 9, 255, 1, 2, 3, 0]),
+            AlphaContent::OneBit
+        );
+        assert_eq!(
+            alpha_content(&[9, 9, 9, 0, 1, 2, 3, 128]),
+            AlphaContent::Full
+        );
+
+        assert_eq!(
+            AlphaContent::Full.flags(VtfFormat::Dxt5),
+            VtfFlags::EIGHTBITALPHA
+        );
+        assert_eq!(
+            AlphaContent::Full.flags(VtfFormat::Dxt1OneBitAlpha),
+            VtfFlags::ONEBITALPHA
+        );
+        assert_eq!(
+            AlphaContent::OneBit.flags(VtfFormat::Bgra8888),
+            VtfFlags::ONEBITALPHA
+        );
+        assert!(AlphaContent::Full.flags(VtfFormat::Dxt1).is_empty());
+        assert!(AlphaContent::None.flags(VtfFormat::Dxt5).is_empty());
+    }
+
+    #[test]
+    fn test_compact_formats() {
+        let gray = [40u8, 40, 40, 255, 200, 200, 200, 255];
+        assert_eq!(analyze(&gray).compact_format(), VtfFormat::I8);
+        let gray_alpha = [40u8, 40, 40, 100, 200, 200, 200, 255];
+        assert_eq!(analyze(&gray_alpha).compact_format(), VtfFormat::Ia88);
+
+        let normals = [128u8, 128, 255, 255, 218, 128, 218, 255];
+        let analysis = analyze(&normals);
+        assert!(analysis.normal_map);
+        assert_eq!(analysis.compact_format(), VtfFormat::Dxt1);
+
+        let cutout = [255u8, 0, 0, 255, 0, 0, 0, 0];
+        assert_eq!(
+            analyze(&cutout).compact_format(),
+            VtfFormat::Dxt1OneBitAlpha
+        );
+    }
+
+    #[test]
+    fn test_recommend_falls_back_when_too_lossy() {
+        // Smooth color survives DXT1, per-texel noise does not
+        let smooth: Vec<u8> = (0..16 * 16)
+            .flat_map(|i| [(i % 16 * 16) as u8, 64, 32, 255])
+            .collect();
+        let noisy: Vec<u8> = (0..16 * 16u32)
+            .flat_map(|i| {
+                let v = i.wrapping_mul(2_654_435_761) >> 24;
+                [v as u8, (v * 7) as u8, (v * 13) as u8, 255]
+            })
+            .collect();
+
+        let (format, _) =
+            recommend_format(&[&smooth], 16, 16, DEFAULT_MAX_ERROR, DxtQuality::default()).unwrap();
+        assert_eq!(format, VtfFormat::Dxt1);
+        let (format, _) =
+            recommend_format(&[&noisy], 16, 16, DEFAULT_MAX_ERROR, DxtQuality::default()).unwrap();
+        assert_eq!(format, VtfFormat::Bgr888);
+    }
+}
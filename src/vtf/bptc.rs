@@ -0,0 +1,919 @@
+//! BPTC block compression: BC7 for RGBA8 and BC6H (unsigned) for HDR color.
+//! Decoding covers every mode. Encoding sticks to the single-subset modes, BC7
+//! mode 6 and BC6H mode 11, which handle smooth content well and keep the
+//! encoder small.
+
+use super::formats::{float_to_half, half_to_float};
+
+// Subset of each pixel for the 2-subset partitions, one bit per pixel
+const PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800,
+    0xFFE8, 0xFF00, 0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC,
+    0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718,
+    0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+// Subset of each pixel for the 3-subset partitions, two bits per pixel
+const PARTITIONS_3: [u32; 64] = [
+    0xAA685050, 0x6A5A5040, 0x5A5A4200, 0x5450A0A8, 0xA5A50000, 0xA0A05050, 0x5555A0A0, 0x5A5A5050,
+    0xAA550000, 0xAA555500, 0xAAAA5500, 0x90909090, 0x94949494, 0xA4A4A4A4, 0xA9A59450, 0x2A0A4250,
+    0xA5945040, 0x0A425054, 0xA5A5A500, 0x55A0A0A0, 0xA8A85454, 0x6A6A4040, 0xA4A45000, 0x1A1A0500,
+    0x0050A4A4, 0xAAA59090, 0x14696914, 0x69691400, 0xA08585A0, 0xAA821414, 0x50A4A450, 0x6A5A0200,
+    0xA9A58000, 0x5090A0A8, 0xA8A09050, 0x24242424, 0x00AA5500, 0x24924924, 0x24499224, 0x50A50A50,
+    0x500AA550, 0xAAAA4444, 0x66660000, 0xA5A0A5A0, 0x50A050A0, 0x69286928, 0x44AAAA44, 0x66666600,
+    0xAA444444, 0x54A854A8, 0x95809580, 0x96969600, 0xA85454A8, 0x80959580, 0xAA141414, 0x96960000,
+    0xAAAA1414, 0xA05050A0, 0xA0A5A5A0, 0x96000000, 0x40804080, 0xA9A8A9A8, 0xAAAAAA44, 0x2A4A5254,
+];
+
+// Pixel whose index drops its top bit: subset 1 of the 2-subset partitions
+#[rustfmt::skip]
+const ANCHORS_2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2, 2,
+    15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+// Subsets 1 and 2 of the 3-subset partitions
+#[rustfmt::skip]
+const ANCHORS_3: [[usize; 2]; 64] = [
+    [3, 15], [3, 8], [15, 8], [15, 3], [8, 15], [3, 15], [15, 3], [15, 8],
+    [8, 15], [8, 15], [6, 15], [6, 15], [6, 15], [5, 15], [3, 15], [3, 8],
+    [3, 15], [3, 8], [8, 15], [15, 3], [3, 15], [3, 8], [6, 15], [10, 8],
+    [5, 3], [8, 15], [8, 6], [6, 10], [8, 15], [5, 15], [15, 10], [15, 8],
+    [8, 15], [15, 3], [3, 15], [5, 10], [6, 10], [10, 8], [8, 9], [15, 10],
+    [15, 6], [3, 15], [15, 8], [5, 15], [15, 3], [15, 6], [15, 6], [15, 8],
+    [3, 15], [15, 3], [5, 15], [5, 15], [5, 15], [8, 15], [5, 15], [10, 15],
+    [5, 15], [10, 15], [8, 15], [13, 15], [15, 3], [12, 15], [3, 15], [3, 8],
+];
+
+// Interpolation weights out of 64 for 2, 3 and 4-bit indices
+const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn weights(index_bits: u32) -> &'static [u32] {
+    match index_bits {
+        2 => &WEIGHTS_2,
+        3 => &WEIGHTS_3,
+        _ => &WEIGHTS_4,
+    }
+}
+
+fn interpolate(e0: u32, e1: u32, weight: u32) -> u32 {
+    ((64 - weight) * e0 + weight * e1 + 32) >> 6
+}
+
+fn subset(subsets: usize, partition: usize, pixel: usize) -> usize {
+    match subsets {
+        2 => (PARTITIONS_2[partition] >> pixel) as usize & 1,
+        3 => (PARTITIONS_3[partition] >> (pixel * 2)) as usize & 3,
+        _ => 0,
+    }
+}
+
+fn is_anchor(subsets: usize, partition: usize, pixel: usize) -> bool {
+    pixel == 0
+        || match subsets {
+            2 => ANCHORS_2[partition] == pixel,
+            3 => ANCHORS_3[partition].contains(&pixel),
+            _ => false,
+        }
+}
+
+// Blocks are read and written least significant bit first
+struct BitReader {
+    bits: u128,
+    position: u32,
+}
+
+impl BitReader {
+    fn new(block: &[u8]) -> Self {
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&block[..16]);
+        Self {
+            bits: u128::from_le_bytes(bytes),
+            position: 0,
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        if count == 0 || self.position >= 128 {
+            return 0;
+        }
+        let value = (self.bits >> self.position) as u32 & ((1u64 << count) - 1) as u32;
+        self.position += count;
+        value
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bits: u128,
+    position: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        let mask = ((1u64 << count) - 1) as u128;
+        self.bits |= (value as u128 & mask) << self.position;
+        self.position += count;
+    }
+
+    fn finish(self) -> [u8; 16] {
+        self.bits.to_le_bytes()
+    }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    // One P-bit per endpoint, or one shared by both endpoints of a subset
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    // Separate alpha (or, with index selection, color) indices of modes 4 and 5
+    index_bits_2: u32,
+}
+
+#[rustfmt::skip]
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 3, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_pbits: false, shared_pbits: true, index_bits: 3, index_bits_2: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index_bits_2: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits_2: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits_2: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_pbits: true, shared_pbits: false, index_bits: 4, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index_bits_2: 0 },
+];
+
+// Widen an n-bit endpoint to 8 bits by repeating its top bits
+fn expand_bits(value: u32, bits: u32) -> u32 {
+    (value << (8 - bits)) | (value >> (2 * bits - 8))
+}
+
+// Decode one 16-byte BC7 block to 16 RGBA pixels
+pub(super) fn decode_bc7_block(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut bits = BitReader::new(block);
+    let mut mode_index = 0;
+    while mode_index < 8 && bits.read(1) == 0 {
+        mode_index += 1;
+    }
+    // Reserved mode, decodes to transparent black
+    if mode_index == 8 {
+        return [[0; 4]; 16];
+    }
+    let mode = &BC7_MODES[mode_index];
+
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // Endpoints are stored channel by channel
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for endpoint in &mut endpoints[..endpoint_count] {
+        endpoint[3] = bits.read(mode.alpha_bits);
+    }
+
+    let channels = if mode.alpha_bits > 0 { 4 } else { 3 };
+    let (mut color_bits, mut alpha_bits) = (mode.color_bits, mode.alpha_bits);
+    if mode.endpoint_pbits || mode.shared_pbits {
+        for endpoint in 0..endpoint_count {
+            // Shared P-bits are read once per subset
+            let pbit = if mode.endpoint_pbits || endpoint % 2 == 0 {
+                bits.read(1)
+            } else {
+                endpoints[endpoint - 1][0] & 1
+            };
+            for value in &mut endpoints[endpoint][..channels] {
+                *value = (*value << 1) | pbit;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+    for endpoint in &mut endpoints[..endpoint_count] {
+        for value in &mut endpoint[..3] {
+            *value = expand_bits(*value, color_bits);
+        }
+        endpoint[3] = if alpha_bits > 0 {
+            expand_bits(endpoint[3], alpha_bits)
+        } else {
+            255
+        };
+    }
+
+    let mut indices = [0u32; 16];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        let anchor = is_anchor(mode.subsets, partition, pixel);
+        *index = bits.read(mode.index_bits - anchor as u32);
+    }
+    let mut indices_2 = [0u32; 16];
+    if mode.index_bits_2 > 0 {
+        for (pixel, index) in indices_2.iter_mut().enumerate() {
+            *index = bits.read(mode.index_bits_2 - (pixel == 0) as u32);
+        }
+    }
+
+    let mut output = [[0u8; 4]; 16];
+    for (pixel, color) in output.iter_mut().enumerate() {
+        let s = subset(mode.subsets, partition, pixel);
+        let (e0, e1) = (endpoints[s * 2], endpoints[s * 2 + 1]);
+
+        let ((color_index, color_index_bits), (alpha_index, alpha_index_bits)) =
+            if mode.index_bits_2 == 0 {
+                let index = (indices[pixel], mode.index_bits);
+                (index, index)
+            } else if index_selection == 0 {
+                (
+                    (indices[pixel], mode.index_bits),
+                    (indices_2[pixel], mode.index_bits_2),
+                )
+            } else {
+                (
+                    (indices_2[pixel], mode.index_bits_2),
+                    (indices[pixel], mode.index_bits),
+                )
+            };
+
+        let color_weight = weights(color_index_bits)[color_index as usize];
+        let alpha_weight = weights(alpha_index_bits)[alpha_index as usize];
+        for channel in 0..3 {
+            color[channel] = interpolate(e0[channel], e1[channel], color_weight) as u8;
+        }
+        color[3] = interpolate(e0[3], e1[3], alpha_weight) as u8;
+
+        // Modes 4 and 5 can store one color channel in alpha's place
+        if rotation > 0 {
+            color.swap(3, rotation as usize - 1);
+        }
+    }
+    output
+}
+
+// Endpoint fields of a BC6H block: (r, g, b) for endpoints 0 and 1 of region 0,
+// then of region 1
+const R0: u8 = 0;
+const G0: u8 = 1;
+const B0: u8 = 2;
+const R1: u8 = 3;
+const G1: u8 = 4;
+const B1: u8 = 5;
+const R2: u8 = 6;
+const G2: u8 = 7;
+const B2: u8 = 8;
+const R3: u8 = 9;
+const G3: u8 = 10;
+const B3: u8 = 11;
+
+struct Bc6hMode {
+    // Mode bits as read, 2 bits for the first two modes and 5 for the rest
+    value: u32,
+    two_regions: bool,
+    // Other endpoints are stored as signed deltas from the first
+    transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    // Endpoint bits in stream order: (field, a, b) stores bits a down to b of the
+    // field, read starting at b. a < b reads them in reverse.
+    layout: &'static [(u8, u32, u32)],
+}
+
+#[rustfmt::skip]
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode { value: 0x00, two_regions: true, transformed: true, endpoint_bits: 10, delta_bits: [5, 5, 5], layout: &[
+        (G2, 4, 4), (B2, 4, 4), (B3, 4, 4), (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 4, 0), (G3, 4, 4),
+        (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0),
+        (B3, 2, 2), (R3, 4, 0), (B3, 3, 3),
+    ] },
+    Bc6hMode { value: 0x01, two_regions: true, transformed: true, endpoint_bits: 7, delta_bits: [6, 6, 6], layout: &[
+        (G2, 5, 5), (G3, 4, 4), (G3, 5, 5), (R0, 6, 0), (B3, 0, 0), (B3, 1, 1), (B2, 4, 4), (G0, 6, 0),
+        (B2, 5, 5), (B3, 2, 2), (G2, 4, 4), (B0, 6, 0), (B3, 3, 3), (B3, 5, 5), (B3, 4, 4), (R1, 5, 0),
+        (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 5, 0), (B2, 3, 0), (R2, 5, 0), (R3, 5, 0),
+    ] },
+    Bc6hMode { value: 0x02, two_regions: true, transformed: true, endpoint_bits: 11, delta_bits: [5, 4, 4], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 4, 0), (R0, 10, 10), (G2, 3, 0), (G1, 3, 0), (G0, 10, 10),
+        (B3, 0, 0), (G3, 3, 0), (B1, 3, 0), (B0, 10, 10), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0), (B3, 2, 2),
+        (R3, 4, 0), (B3, 3, 3),
+    ] },
+    Bc6hMode { value: 0x06, two_regions: true, transformed: true, endpoint_bits: 11, delta_bits: [4, 5, 4], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 10), (G3, 4, 4), (G2, 3, 0), (G1, 4, 0),
+        (G0, 10, 10), (G3, 3, 0), (B1, 3, 0), (B0, 10, 10), (B3, 1, 1), (B2, 3, 0), (R2, 3, 0), (B3, 0, 0),
+        (B3, 2, 2), (R3, 3, 0), (G2, 4, 4), (B3, 3, 3),
+    ] },
+    Bc6hMode { value: 0x0A, two_regions: true, transformed: true, endpoint_bits: 11, delta_bits: [4, 4, 5], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 10), (B2, 4, 4), (G2, 3, 0), (G1, 3, 0),
+        (G0, 10, 10), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B0, 10, 10), (B2, 3, 0), (R2, 3, 0), (B3, 1, 1),
+        (B3, 2, 2), (R3, 3, 0), (B3, 4, 4), (B3, 3, 3),
+    ] },
+    Bc6hMode { value: 0x0E, two_regions: true, transformed: true, endpoint_bits: 9, delta_bits: [5, 5, 5], layout: &[
+        (R0, 8, 0), (B2, 4, 4), (G0, 8, 0), (G2, 4, 4), (B0, 8, 0), (B3, 4, 4), (R1, 4, 0), (G3, 4, 4),
+        (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0),
+        (B3, 2, 2), (R3, 4, 0), (B3, 3, 3),
+    ] },
+    Bc6hMode { value: 0x12, two_regions: true, transformed: true, endpoint_bits: 8, delta_bits: [6, 5, 5], layout: &[
+        (R0, 7, 0), (G3, 4, 4), (B2, 4, 4), (G0, 7, 0), (B3, 2, 2), (G2, 4, 4), (B0, 7, 0), (B3, 3, 3),
+        (B3, 4, 4), (R1, 5, 0), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1),
+        (B2, 3, 0), (R2, 5, 0), (R3, 5, 0),
+    ] },
+    Bc6hMode { value: 0x16, two_regions: true, transformed: true, endpoint_bits: 8, delta_bits: [5, 6, 5], layout: &[
+        (R0, 7, 0), (B3, 0, 0), (B2, 4, 4), (G0, 7, 0), (G2, 5, 5), (G2, 4, 4), (B0, 7, 0), (G3, 5, 5),
+        (B3, 4, 4), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1),
+        (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3),
+    ] },
+    Bc6hMode { value: 0x1A, two_regions: true, transformed: true, endpoint_bits: 8, delta_bits: [5, 5, 6], layout: &[
+        (R0, 7, 0), (B3, 1, 1), (B2, 4, 4), (G0, 7, 0), (B2, 5, 5), (G2, 4, 4), (B0, 7, 0), (B3, 5, 5),
+        (B3, 4, 4), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 5, 0),
+        (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3),
+    ] },
+    Bc6hMode { value: 0x1E, two_regions: true, transformed: false, endpoint_bits: 6, delta_bits: [6, 6, 6], layout: &[
+        (R0, 5, 0), (G3, 4, 4), (B3, 0, 0), (B3, 1, 1), (B2, 4, 4), (G0, 5, 0), (G2, 5, 5), (B2, 5, 5),
+        (B3, 2, 2), (G2, 4, 4), (B0, 5, 0), (G3, 5, 5), (B3, 3, 3), (B3, 5, 5), (B3, 4, 4), (R1, 5, 0),
+        (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 5, 0), (B2, 3, 0), (R2, 5, 0), (R3, 5, 0),
+    ] },
+    Bc6hMode { value: 0x03, two_regions: false, transformed: false, endpoint_bits: 10, delta_bits: [10, 10, 10], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 9, 0), (G1, 9, 0), (B1, 9, 0),
+    ] },
+    Bc6hMode { value: 0x07, two_regions: false, transformed: true, endpoint_bits: 11, delta_bits: [9, 9, 9], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 8, 0), (R0, 10, 10), (G1, 8, 0), (G0, 10, 10), (B1, 8, 0),
+        (B0, 10, 10),
+    ] },
+    Bc6hMode { value: 0x0B, two_regions: false, transformed: true, endpoint_bits: 12, delta_bits: [8, 8, 8], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 7, 0), (R0, 10, 11), (G1, 7, 0), (G0, 10, 11), (B1, 7, 0),
+        (B0, 10, 11),
+    ] },
+    Bc6hMode { value: 0x0F, two_regions: false, transformed: true, endpoint_bits: 16, delta_bits: [4, 4, 4], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 15), (G1, 3, 0), (G0, 10, 15), (B1, 3, 0),
+        (B0, 10, 15),
+    ] },
+];
+
+// Mode 11: one region, plain 10-bit endpoints
+const BC6H_ENCODE_MODE: usize = 10;
+
+// Bit numbers of a layout entry in stream order
+fn layout_bits(a: u32, b: u32) -> impl Iterator<Item = u32> {
+    (0..=a.abs_diff(b)).map(move |i| if a >= b { b + i } else { b - i })
+}
+
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+// Endpoint to the 16-bit range the weights interpolate in
+fn unquantize_bc6h(value: u32, bits: u32) -> u32 {
+    if bits >= 15 {
+        value
+    } else if value == 0 {
+        0
+    } else if value == (1 << bits) - 1 {
+        0xFFFF
+    } else {
+        ((value << 16) + 0x8000) >> bits
+    }
+}
+
+// Interpolated value to the bits of a positive half float
+fn finish_bc6h(value: u32) -> u16 {
+    ((value * 31) >> 6) as u16
+}
+
+// Decode one 16-byte BC6H block to 16 linear RGBA pixels (alpha is 1)
+pub(super) fn decode_bc6h_block(block: &[u8]) -> [[f32; 4]; 16] {
+    let mut bits = BitReader::new(block);
+    let mut value = bits.read(2);
+    if value > 1 {
+        value |= bits.read(3) << 2;
+    }
+    // Reserved modes decode to black
+    let Some(mode) = BC6H_MODES.iter().find(|mode| mode.value == value) else {
+        return [[0.0, 0.0, 0.0, 1.0]; 16];
+    };
+
+    let mut fields = [0u32; 12];
+    for &(field, a, b) in mode.layout {
+        for bit in layout_bits(a, b) {
+            fields[field as usize] |= bits.read(1) << bit;
+        }
+    }
+
+    let regions = if mode.two_regions { 2 } else { 1 };
+    let partition = if mode.two_regions {
+        bits.read(5) as usize
+    } else {
+        0
+    };
+
+    let mask = (1u32 << mode.endpoint_bits) - 1;
+    if mode.transformed {
+        for field in 3..regions * 6 {
+            let channel = field % 3;
+            let delta = sign_extend(fields[field], mode.delta_bits[channel]);
+            fields[field] = (fields[channel] as i32).wrapping_add(delta) as u32 & mask;
+        }
+    }
+    let endpoints = fields.map(|value| unquantize_bc6h(value, mode.endpoint_bits));
+
+    let index_bits = if mode.two_regions { 3 } else { 4 };
+    let subsets = regions;
+    let mut output = [[0.0f32; 4]; 16];
+    for (pixel, color) in output.iter_mut().enumerate() {
+        let anchor = is_anchor(subsets, partition, pixel);
+        let index = bits.read(index_bits - anchor as u32);
+        let weight = weights(index_bits)[index as usize];
+        let s = subset(subsets, partition, pixel);
+        for channel in 0..3 {
+            let e0 = endpoints[s * 6 + channel];
+            let e1 = endpoints[s * 6 + 3 + channel];
+            color[channel] = half_to_float(finish_bc6h(interpolate(e0, e1, weight)));
+        }
+        color[3] = 1.0;
+    }
+    output
+}
+
+// Copy a 4x4 block out of RGBA pixels, clamping to the edge for partial blocks
+fn extract_block<T: Copy + Default>(
+    pixels: &[T],
+    width: u32,
+    height: u32,
+    bx: u32,
+    by: u32,
+) -> [[T; 4]; 16] {
+    std::array::from_fn(|i| {
+        let x = (bx * 4 + i as u32 % 4).min(width - 1);
+        let y = (by * 4 + i as u32 / 4).min(height - 1);
+        let index = ((y * width + x) * 4) as usize;
+        std::array::from_fn(|channel| pixels[index + channel])
+    })
+}
+
+fn compress_blocks<T: Copy + Default>(
+    pixels: &[T],
+    width: u32,
+    height: u32,
+    encode: impl Fn(&[[T; 4]; 16]) -> [u8; 16],
+) -> Vec<u8> {
+    let (block_width, block_height) = (width.div_ceil(4), height.div_ceil(4));
+    let mut output = Vec::with_capacity((block_width * block_height * 16) as usize);
+    for by in 0..block_height {
+        for bx in 0..block_width {
+            output.extend_from_slice(&encode(&extract_block(pixels, width, height, bx, by)));
+        }
+    }
+    output
+}
+
+// Compress RGBA8 pixels into BC7 blocks
+pub(super) fn compress_bc7(rgba: &[u8], width: u32, height: u32) -> Vec<u8> {
+    compress_blocks(rgba, width, height, encode_bc7_block)
+}
+
+// Compress linear RGBA32F pixels into BC6H blocks, alpha is dropped
+pub(super) fn compress_bc6h(rgba: &[f32], width: u32, height: u32) -> Vec<u8> {
+    compress_blocks(rgba, width, height, encode_bc6h_block)
+}
+
+// Mean, then the direction of greatest spread by power iteration
+fn principal_axis(points: &[[f32; 4]; 16]) -> ([f32; 4], [f32; 4]) {
+    let mut mean = [0.0f32; 4];
+    for point in points {
+        for channel in 0..4 {
+            mean[channel] += point[channel] / 16.0;
+        }
+    }
+
+    let mut covariance = [[0.0f32; 4]; 4];
+    for point in points {
+        for i in 0..4 {
+            for j in 0..4 {
+                covariance[i][j] += (point[i] - mean[i]) * (point[j] - mean[j]);
+            }
+        }
+    }
+
+    let mut axis = [1.0f32; 4];
+    for _ in 0..8 {
+        let mut next = [0.0f32; 4];
+        for i in 0..4 {
+            for j in 0..4 {
+                next[i] += covariance[i][j] * axis[j];
+            }
+        }
+        let length = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if length < 1e-6 {
+            break;
+        }
+        axis = next.map(|v| v / length);
+    }
+    (mean, axis)
+}
+
+// Endpoint pairs worth trying: the extremes along the principal axis, and the
+// corners of the bounding box
+fn candidate_endpoints(points: &[[f32; 4]; 16]) -> [([f32; 4], [f32; 4]); 2] {
+    let (mean, axis) = principal_axis(points);
+    let project =
+        |point: &[f32; 4]| -> f32 { (0..4).map(|i| (point[i] - mean[i]) * axis[i]).sum() };
+    let (mut low, mut high) = (f32::MAX, f32::MIN);
+    for point in points {
+        let t = project(point);
+        low = low.min(t);
+        high = high.max(t);
+    }
+    let along = |t: f32| -> [f32; 4] { std::array::from_fn(|i| mean[i] + axis[i] * t) };
+
+    let mut min = [f32::MAX; 4];
+    let mut max = [f32::MIN; 4];
+    for point in points {
+        for channel in 0..4 {
+            min[channel] = min[channel].min(point[channel]);
+            max[channel] = max[channel].max(point[channel]);
+        }
+    }
+    [(along(low), along(high)), (min, max)]
+}
+
+// Pick the closest of 16 palette entries for every pixel, returns (indices, error)
+fn fit_indices<const N: usize>(
+    points: &[[f32; 4]; 16],
+    palette: &[[f32; 4]; 16],
+) -> ([u32; 16], f32) {
+    let mut indices = [0u32; 16];
+    let mut total = 0.0;
+    for (index, point) in indices.iter_mut().zip(points) {
+        let (best, error) = palette
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let error: f32 = (0..N).map(|c| (entry[c] - point[c]).powi(2)).sum();
+                (i, error)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, 0.0));
+        *index = best as u32;
+        total += error;
+    }
+    (indices, total)
+}
+
+// Endpoints and palette indices of the best candidate so far
+type Fit<E> = Option<(E, E, [u32; 16], f32)>;
+
+// The first pixel's index has no top bit, so swap the endpoints if it needs one
+fn fix_anchor<T>(endpoints: &mut (T, T), indices: &mut [u32; 16]) {
+    if indices[0] >= 8 {
+        std::mem::swap(&mut endpoints.0, &mut endpoints.1);
+        for index in indices.iter_mut() {
+            *index = 15 - *index;
+        }
+    }
+}
+
+// 7-bit endpoint for a given P-bit
+fn quantize_bc7_endpoint(color: [f32; 4], pbit: u32) -> ([u32; 4], u32) {
+    let value = color.map(|v| ((v - pbit as f32) / 2.0).round().clamp(0.0, 127.0) as u32);
+    (value, pbit)
+}
+
+// Encode 16 RGBA pixels as a mode 6 BC7 block
+fn encode_bc7_block(block: &[[u8; 4]; 16]) -> [u8; 16] {
+    let points: [[f32; 4]; 16] = block.map(|pixel| pixel.map(|v| v as f32));
+
+    // Each endpoint has its own P-bit, so all four pairings are tried
+    let mut best: Fit<([u32; 4], u32)> = None;
+    for (start, end) in candidate_endpoints(&points) {
+        for pbits in 0..4 {
+            let e0 = quantize_bc7_endpoint(start, pbits & 1);
+            let e1 = quantize_bc7_endpoint(end, pbits >> 1);
+            let full = |(value, pbit): ([u32; 4], u32)| value.map(|v| v * 2 + pbit);
+            let (full0, full1) = (full(e0), full(e1));
+            let palette: [[f32; 4]; 16] = std::array::from_fn(|i| {
+                std::array::from_fn(|c| interpolate(full0[c], full1[c], WEIGHTS_4[i]) as f32)
+            });
+            let (indices, error) = fit_indices::<4>(&points, &palette);
+            if best.as_ref().is_none_or(|best| error < best.3) {
+                best = Some((e0, e1, indices, error));
+            }
+        }
+    }
+    let Some((e0, e1, mut indices, _)) = best else {
+        return [0; 16];
+    };
+    let mut endpoints = (e0, e1);
+    fix_anchor(&mut endpoints, &mut indices);
+    let ((value0, pbit0), (value1, pbit1)) = endpoints;
+
+    let mut bits = BitWriter::default();
+    bits.write(1 << 6, 7);
+    for channel in 0..4 {
+        bits.write(value0[channel], 7);
+        bits.write(value1[channel], 7);
+    }
+    bits.write(pbit0, 1);
+    bits.write(pbit1, 1);
+    for (pixel, index) in indices.iter().enumerate() {
+        bits.write(*index, if pixel == 0 { 3 } else { 4 });
+    }
+    bits.finish()
+}
+
+// Encode 16 linear RGB pixels as a mode 11 BC6H block. Negative values clamp to 0.
+fn encode_bc6h_block(block: &[[f32; 4]; 16]) -> [u8; 16] {
+    // Fit in the space the decoder interpolates in: half float bits scaled up to 16
+    let to_unquantized = |v: f32| {
+        let v = if v.is_nan() {
+            0.0
+        } else {
+            v.clamp(0.0, 65504.0)
+        };
+        float_to_half(v) as f32 * 64.0 / 31.0
+    };
+    let points: [[f32; 4]; 16] = block.map(|pixel| {
+        [
+            to_unquantized(pixel[0]),
+            to_unquantized(pixel[1]),
+            to_unquantized(pixel[2]),
+            0.0,
+        ]
+    });
+
+    let mode = &BC6H_MODES[BC6H_ENCODE_MODE];
+    let max = (1u32 << mode.endpoint_bits) - 1;
+    let quantize = |color: [f32; 4]| -> [u32; 3] {
+        std::array::from_fn(|c| ((color[c] - 32.0) / 64.0).round().clamp(0.0, max as f32) as u32)
+    };
+
+    let mut best: Fit<[u32; 3]> = None;
+    for (start, end) in candidate_endpoints(&points) {
+        let (e0, e1) = (quantize(start), quantize(end));
+        let palette: [[f32; 4]; 16] = std::array::from_fn(|i| {
+            let mut entry = [0.0; 4];
+            for c in 0..3 {
+                let a = unquantize_bc6h(e0[c], mode.endpoint_bits);
+                let b = unquantize_bc6h(e1[c], mode.endpoint_bits);
+                entry[c] = finish_bc6h(interpolate(a, b, WEIGHTS_4[i])) as f32 * 64.0 / 31.0;
+            }
+            entry
+        });
+        let (indices, error) = fit_indices::<3>(&points, &palette);
+        if best.as_ref().is_none_or(|best| error < best.3) {
+            best = Some((e0, e1, indices, error));
+        }
+    }
+    let Some((e0, e1, mut indices, _)) = best else {
+        return [0; 16];
+    };
+    let mut endpoints = (e0, e1);
+    fix_anchor(&mut endpoints, &mut indices);
+
+    let mut fields = [0u32; 12];
+    fields[..3].copy_from_slice(&endpoints.0);
+    fields[3..6].copy_from_slice(&endpoints.1);
+
+    let mut bits = BitWriter::default();
+    bits.write(mode.value, 5);
+    for &(field, a, b) in mode.layout {
+        for bit in layout_bits(a, b) {
+            bits.write(fields[field as usize] >> bit, 1);
+        }
+    }
+    for (pixel, index) in indices.iter().enumerate() {
+        bits.write(*index, if pixel == 0 { 3 } else { 4 });
+    }
+    bits.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchors_are_in_their_subset() {
+        for partition in 0..64 {
+            assert_eq!(subset(2, partition, 0), 0);
+            assert_eq!(subset(2, partition, ANCHORS_2[partition]), 1);
+            assert_eq!(subset(3, partition, 0), 0);
+            assert_eq!(subset(3, partition, ANCHORS_3[partition][0]), 1);
+            assert_eq!(subset(3, partition, ANCHORS_3[partition][1]), 2);
+        }
+    }
+
+    #[test]
+    fn test_bc6h_layouts_cover_every_bit_once() {
+        for mode in &BC6H_MODES {
+            let mut seen = [0u32; 12];
+            let mut count = 0;
+            for &(field, a, b) in mode.layout {
+                for bit in layout_bits(a, b) {
+                    assert_eq!(seen[field as usize] & (1 << bit), 0);
+                    seen[field as usize] |= 1 << bit;
+                    count += 1;
+                }
+            }
+
+            let mode_bits = if mode.value < 2 { 2 } else { 5 };
+            let partition_bits = if mode.two_regions { 5 } else { 0 };
+            let header_bits = if mode.two_regions { 82 } else { 65 };
+            assert_eq!(mode_bits + count + partition_bits, header_bits);
+
+            let fields = if mode.two_regions { 12 } else { 6 };
+            for (field, bits) in seen.iter().enumerate().take(fields) {
+                let width = if field < 3 {
+                    mode.endpoint_bits
+                } else {
+                    mode.delta_bits[field % 3]
+                };
+                assert_eq!(*bits, (1u32 << width) - 1, "field {}", field);
+            }
+        }
+    }
+
+    // Known answers: blocks put together field by field from the D3D11 BC7 and BC6H
+    // format spec, with the pixels worked out from the spec rather than by this
+    // decoder. They cover every BC7 mode and the BC6H base, delta and two-region
+    // modes, including anchors that aren't the first pixel of their subset.
+    // (block, decoded RGBA)
+    #[rustfmt::skip]
+    const BC7_BLOCKS: [([u8; 16], [[u8; 4]; 16]); 9] = [
+        // Mode 0, partition 0: three subsets, a P-bit per endpoint
+        ([0x21, 0xBC, 0x1E, 0x45, 0x3A, 0x01, 0x77, 0x18, 0x0E, 0xAD, 0x8D, 0xB7, 0x98, 0xC3, 0xAB, 0x98],
+         [[53, 65, 77, 255], [144, 141, 139, 255], [255, 8, 123, 255], [136, 114, 40, 255],
+          [173, 165, 158, 255], [24, 41, 57, 255], [160, 94, 57, 255], [232, 29, 107, 255],
+          [53, 65, 77, 255], [79, 165, 118, 255], [41, 189, 107, 255], [136, 114, 40, 255],
+          [67, 173, 114, 255], [132, 132, 132, 255], [94, 156, 121, 255], [106, 148, 125, 255]]),
+        // Mode 1, partition 17: shared P-bits, anchor 2
+        ([0x46, 0xCA, 0x0D, 0xFC, 0xE8, 0x10, 0xFA, 0x3F, 0x15, 0x05, 0x79, 0xE2, 0xA1, 0x3A, 0xE3, 0xA1],
+         [[93, 121, 206, 255], [253, 249, 4, 255], [0, 133, 68, 255], [36, 149, 59, 255],
+          [198, 35, 106, 255], [118, 100, 182, 255], [42, 163, 255, 255], [182, 216, 22, 255],
+          [93, 121, 206, 255], [223, 14, 82, 255], [147, 77, 155, 255], [67, 142, 231, 255],
+          [198, 35, 106, 255], [118, 100, 182, 255], [42, 163, 255, 255], [172, 56, 131, 255]]),
+        // Mode 2, partition 1: three subsets, no P-bits, anchors 3 and 8
+        ([0x0C, 0xBE, 0x80, 0x20, 0x27, 0xD0, 0xE1, 0x3F, 0x93, 0x44, 0x88, 0xFC, 0x48, 0x26, 0x93, 0x93],
+         [[177, 78, 68, 255], [255, 0, 33, 255], [177, 78, 68, 255], [132, 132, 132, 255],
+          [16, 239, 140, 255], [255, 0, 33, 255], [89, 172, 110, 255], [43, 215, 88, 255],
+          [163, 89, 174, 255], [206, 57, 247, 255], [89, 172, 110, 255], [43, 215, 88, 255],
+          [74, 156, 24, 255], [206, 57, 247, 255], [163, 89, 174, 255], [43, 215, 88, 255]]),
+        // Mode 3, partition 13: 7-bit color, a P-bit per endpoint
+        ([0xD8, 0xFC, 0x07, 0x32, 0x3C, 0x80, 0x3C, 0x33, 0x00, 0x9B, 0xB4, 0xB1, 0xD9, 0xD8, 0xD8, 0xD8],
+         [[254, 0, 128, 255], [7, 201, 155, 255], [88, 135, 146, 255], [173, 66, 137, 255],
+          [254, 0, 128, 255], [7, 201, 155, 255], [88, 135, 146, 255], [173, 66, 137, 255],
+          [101, 103, 105, 255], [240, 12, 198, 255], [194, 42, 167, 255], [147, 73, 136, 255],
+          [101, 103, 105, 255], [240, 12, 198, 255], [194, 42, 167, 255], [147, 73, 136, 255]]),
+        // Mode 4, rotation 1, 3-bit color indices
+        ([0xB0, 0x84, 0x53, 0xF1, 0x93, 0xC2, 0xCB, 0xC9, 0xC9, 0xC9, 0xE3, 0x55, 0xCC, 0xE1, 0x55, 0xCC],
+         [[40, 144, 230, 61], [107, 79, 150, 147], [176, 16, 74, 231], [243, 123, 204, 89],
+          [40, 58, 125, 175], [107, 165, 255, 33], [176, 102, 179, 117], [243, 37, 99, 203],
+          [40, 144, 230, 61], [107, 79, 150, 147], [176, 16, 74, 231], [243, 123, 204, 89],
+          [40, 58, 125, 175], [107, 165, 255, 33], [176, 102, 179, 117], [243, 37, 99, 203]]),
+        // Mode 4, no rotation, 2-bit color indices
+        ([0x10, 0x1F, 0x80, 0xCF, 0x32, 0xF0, 0x67, 0x63, 0x63, 0x63, 0x3D, 0xE3, 0xA1, 0x3A, 0xE3, 0xA1],
+         [[171, 84, 134, 72], [255, 0, 99, 255], [0, 255, 206, 147], [84, 171, 171, 36],
+          [171, 84, 134, 219], [255, 0, 99, 108], [0, 255, 206, 0], [84, 171, 171, 183],
+          [171, 84, 134, 72], [255, 0, 99, 255], [0, 255, 206, 147], [84, 171, 171, 36],
+          [171, 84, 134, 219], [255, 0, 99, 108], [0, 255, 206, 0], [84, 171, 171, 183]]),
+        // Mode 5, rotation 2
+        ([0xA0, 0xF8, 0x43, 0x61, 0x2C, 0x04, 0xFC, 0x43, 0x74, 0x72, 0x72, 0x72, 0x6C, 0x6C, 0x6C, 0x6C],
+         [[167, 255, 89, 72], [88, 16, 44, 137], [14, 94, 0, 199], [241, 177, 133, 10],
+          [167, 255, 89, 72], [88, 16, 44, 137], [14, 94, 0, 199], [241, 177, 133, 10],
+          [167, 255, 89, 72], [88, 16, 44, 137], [14, 94, 0, 199], [241, 177, 133, 10],
+          [167, 255, 89, 72], [88, 16, 44, 137], [14, 94, 0, 199], [241, 177, 133, 10]]),
+        // Mode 6: 4-bit indices
+        ([0x40, 0x72, 0x81, 0xA2, 0x05, 0xFC, 0xFF, 0x9E, 0xA6, 0x81, 0x6F, 0x4D, 0x2B, 0x09, 0xE7, 0xC5],
+         [[162, 69, 52, 215], [73, 134, 171, 124], [189, 50, 17, 243], [100, 115, 135, 151],
+          [10, 180, 254, 60], [123, 97, 104, 176], [37, 160, 218, 87], [150, 78, 68, 203],
+          [61, 143, 187, 112], [174, 61, 37, 228], [88, 124, 151, 139], [201, 41, 1, 255],
+          [111, 106, 120, 164], [22, 171, 238, 72], [138, 87, 84, 191], [49, 152, 203, 100]]),
+        // Mode 7, partition 21: alpha, anchor 8
+        ([0x80, 0xD5, 0x07, 0x6F, 0x00, 0xFA, 0xB8, 0xFA, 0xC2, 0x7E, 0x02, 0x72, 0x9A, 0x9C, 0x4C, 0x4E],
+         [[255, 4, 85, 255], [0, 162, 251, 32], [255, 4, 85, 255], [171, 56, 139, 182],
+          [59, 195, 128, 139], [0, 162, 251, 32], [255, 4, 85, 255], [171, 56, 139, 182],
+          [121, 121, 16, 0], [28, 231, 182, 207], [255, 4, 85, 255], [171, 56, 139, 182],
+          [59, 195, 128, 139], [28, 231, 182, 207], [121, 121, 16, 0], [171, 56, 139, 182]]),
+    ];
+
+    // (block, decoded RGB as half float bits)
+    #[rustfmt::skip]
+    const BC6H_BLOCKS: [([u8; 16], [[u16; 3]; 16]); 7] = [
+        // Mode 1, partition 13: 10-bit base, signed 5-bit deltas
+        ([0x04, 0x4B, 0x06, 0xD0, 0xFF, 0xB8, 0x10, 0xF8, 0x87, 0xB8, 0xC5, 0xAB, 0x98, 0xC3, 0xAB, 0x98],
+         [[0x48B3, 0x0199, 0x78E1], [0x48A5, 0x01DD, 0x7808], [0x4898, 0x021E, 0x7737], [0x48AE, 0x01AF, 0x789C],
+          [0x48A1, 0x01F2, 0x77C3], [0x48B7, 0x0183, 0x7927], [0x48AA, 0x01C4, 0x7856], [0x489C, 0x0208, 0x777D],
+          [0x48C6, 0x0121, 0x7AE2], [0x47D1, 0x0173, 0x7A9E], [0x46E6, 0x01C1, 0x7A5D], [0x4877, 0x013B, 0x7ACC],
+          [0x4783, 0x018D, 0x7A89], [0x4914, 0x0107, 0x7AF8], [0x4829, 0x0155, 0x7AB7], [0x4877, 0x013B, 0x7ACC]]),
+        // Mode 2, partition 17: 7-bit base, deltas wrap around
+        ([0x85, 0x8C, 0x81, 0xFE, 0x07, 0xF1, 0x8B, 0xE2, 0x7E, 0x35, 0x52, 0xCF, 0x78, 0xA8, 0xCE, 0x78],
+         [[0x615C, 0x0364, 0x7BFF], [0x51C4, 0x206C, 0x57C3], [0x5AAB, 0x4C13, 0x262C], [0x4C0C, 0x045C, 0x77A4],
+          [0x4F70, 0x14C0, 0x36D5], [0x5D00, 0x079D, 0x6B2C], [0x46B8, 0x1D32, 0x152F], [0x57CE, 0x3E0B, 0x361D],
+          [0x615C, 0x0364, 0x7BFF], [0x4B14, 0x18F9, 0x2602], [0x58A4, 0x0BD6, 0x5A59], [0x425C, 0x216C, 0x045C],
+          [0x4F70, 0x14C0, 0x36D5], [0x5D00, 0x079D, 0x6B2C], [0x46B8, 0x1D32, 0x152F], [0x5448, 0x100F, 0x4986]]),
+        // Mode 3, partition 21: 11-bit base, 5.4.4 deltas
+        ([0x82, 0x3B, 0xE8, 0x23, 0x78, 0x07, 0xF7, 0x83, 0xA1, 0xA4, 0x3A, 0xD6, 0x47, 0x1C, 0xEB, 0x23],
+         [[0x5B1B, 0x78FC, 0x012D], [0x5B3C, 0x78EB, 0x013D], [0x5B60, 0x78D8, 0x014E], [0x5B80, 0x78C6, 0x015D],
+          [0x5B2F, 0x790B, 0x0114], [0x5BC2, 0x78A3, 0x017B], [0x5AD9, 0x791F, 0x010F], [0x5AFA, 0x790E, 0x011E],
+          [0x5A4E, 0x7938, 0x00E7], [0x5A85, 0x792D, 0x00F2], [0x5B60, 0x78D8, 0x014E], [0x5B80, 0x78C6, 0x015D],
+          [0x5B2F, 0x790B, 0x0114], [0x5B65, 0x7900, 0x011E], [0x59E1, 0x794E, 0x00D1], [0x5AFA, 0x790E, 0x011E]]),
+        // Mode 10, partition 0: plain 6-bit endpoints
+        ([0xFE, 0x47, 0x60, 0x29, 0x08, 0xC4, 0xBF, 0xF0, 0x15, 0x16, 0x58, 0xCC, 0xE1, 0x55, 0xCC, 0xE1],
+         [[0x59F0, 0x220E, 0x2ECD], [0x24F6, 0x5709, 0x39D2], [0x1458, 0x61D8, 0x7BFF], [0x3022, 0x3EB2, 0x47AF],
+          [0x13EF, 0x6810, 0x3D5D], [0x6AF8, 0x1107, 0x2B42], [0x3A6D, 0x31AD, 0x3450], [0x5638, 0x0E88, 0x0000],
+          [0x59F0, 0x220E, 0x2ECD], [0x24F6, 0x5709, 0x39D2], [0x1458, 0x61D8, 0x7BFF], [0x3022, 0x3EB2, 0x47AF],
+          [0x13EF, 0x6810, 0x3D5D], [0x6AF8, 0x1107, 0x2B42], [0x3A6D, 0x31AD, 0x3450], [0x3022, 0x3EB2, 0x47AF]]),
+        // Mode 11: plain 10-bit endpoints
+        ([0x03, 0x80, 0xFF, 0x59, 0xFA, 0xBF, 0x00, 0x5E, 0x83, 0x6F, 0x4D, 0x2B, 0x09, 0xE7, 0xC5, 0xA3],
+         [[0x07C0, 0x744A, 0x276A], [0x41DF, 0x3A7A, 0x3E1F], [0x7BFF, 0x00AA, 0x54D3], [0x3260, 0x49E4, 0x3811],
+          [0x6A8F, 0x1202, 0x4E03], [0x20F0, 0x5B3D, 0x3141], [0x5B0F, 0x216C, 0x47F5], [0x1170, 0x6AA7, 0x2B33],
+          [0x499F, 0x32C5, 0x4126], [0x0000, 0x7BFF, 0x2463], [0x3A20, 0x422F, 0x3B18], [0x743F, 0x085F, 0x51CC],
+          [0x28B0, 0x5387, 0x3448], [0x62CF, 0x19B7, 0x4AFC], [0x1930, 0x62F2, 0x2E3A], [0x534F, 0x2922, 0x44EE]]),
+        // Mode 12: 11-bit base, 9-bit deltas
+        ([0x07, 0x61, 0x14, 0x00, 0x00, 0x18, 0x99, 0xFF, 0x8B, 0xEB, 0x41, 0xA7, 0x0D, 0x63, 0xC9, 0x2F],
+         [[0x67ED, 0x066C, 0x3E02], [0x64C7, 0x08E2, 0x3DFF], [0x61A1, 0x0B58, 0x3DFC], [0x5E7B, 0x0DCE, 0x3DF9],
+          [0x6C0B, 0x0335, 0x3E06], [0x68E5, 0x05AB, 0x3E03], [0x65BF, 0x0820, 0x3E00], [0x6299, 0x0A96, 0x3DFD],
+          [0x5FB1, 0x0CDB, 0x3DFA], [0x6D03, 0x0273, 0x3E07], [0x69DD, 0x04E9, 0x3E04], [0x66B7, 0x075F, 0x3E01],
+          [0x63CF, 0x09A4, 0x3DFE], [0x60A9, 0x0C1A, 0x3DFB], [0x5D83, 0x0E8F, 0x3DF8], [0x6AD5, 0x0427, 0x3E05]]),
+        // Mode 14: 16-bit base with reversed top bits, 4-bit deltas
+        ([0xAF, 0x79, 0x00, 0x46, 0x3A, 0x2D, 0xF9, 0x07, 0xD8, 0xF6, 0x18, 0x3A, 0x5C, 0x7E, 0x90, 0xB2],
+         [[0x2C78, 0x1D0F, 0x008C], [0x2C7A, 0x1D0D, 0x008C], [0x2C78, 0x1D0E, 0x008C], [0x2C7A, 0x1D0C, 0x008C],
+          [0x2C79, 0x1D0E, 0x008C], [0x2C77, 0x1D10, 0x008C], [0x2C79, 0x1D0D, 0x008C], [0x2C77, 0x1D0F, 0x008C],
+          [0x2C7A, 0x1D0D, 0x008C], [0x2C78, 0x1D0F, 0x008C], [0x2C7A, 0x1D0C, 0x008C], [0x2C78, 0x1D0E, 0x008C],
+          [0x2C77, 0x1D10, 0x008C], [0x2C79, 0x1D0E, 0x008C], [0x2C77, 0x1D0F, 0x008C], [0x2C79, 0x1D0D, 0x008C]]),
+    ];
+
+    #[test]
+    fn test_bc7_known_blocks() {
+        for (index, (block, expected)) in BC7_BLOCKS.iter().enumerate() {
+            assert_eq!(&decode_bc7_block(block), expected, "block {}", index);
+        }
+        // Mode 8 is reserved
+        assert_eq!(decode_bc7_block(&[0; 16]), [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn test_bc6h_known_blocks() {
+        for (index, (block, expected)) in BC6H_BLOCKS.iter().enumerate() {
+            for (pixel, (decoded, expected)) in
+                decode_bc6h_block(block).iter().zip(expected).enumerate()
+            {
+                let expected = expected.map(half_to_float);
+                assert_eq!(decoded[..3], expected, "block {} pixel {}", index, pixel);
+                assert_eq!(decoded[3], 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bc7_round_trip() {
+        // Mode 6 shares a P-bit across channels, so mixed parity is off by one
+        let solid = [[200u8, 17, 90, 255]; 16];
+        for pixel in decode_bc7_block(&encode_bc7_block(&solid)) {
+            for channel in 0..4 {
+                assert!(pixel[channel].abs_diff(solid[0][channel]) <= 1);
+            }
+        }
+        let even = [[200u8, 16, 90, 254]; 16];
+        assert_eq!(decode_bc7_block(&encode_bc7_block(&even)), even);
+
+        let ramp: [[u8; 4]; 16] =
+            std::array::from_fn(|i| [(i * 16) as u8, 255 - (i * 16) as u8, 64, (i * 8) as u8]);
+        let decoded = decode_bc7_block(&encode_bc7_block(&ramp));
+        for (a, b) in ramp.iter().zip(&decoded) {
+            for channel in 0..4 {
+                assert!(a[channel].abs_diff(b[channel]) <= 3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bc6h_round_trip() {
+        // Within one exponent, where half floats are evenly spaced
+        let ramp: [[f32; 4]; 16] =
+            std::array::from_fn(|i| [4.0 + i as f32 * 0.25, 5.0, 8.0 - i as f32 * 0.25, 1.0]);
+        let decoded = decode_bc6h_block(&encode_bc6h_block(&ramp));
+        for (a, b) in ramp.iter().zip(&decoded) {
+            for channel in 0..3 {
+                let tolerance = 0.05 * a[channel].max(0.25);
+                assert!(
+                    (a[channel] - b[channel]).abs() <= tolerance,
+                    "{:?} {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Compressed image data of Strata-style 7.6 files: every mip, frame and face is
+//! its own deflate or zstd stream, sized by the "AXC" resource
+
+use super::header::VtfHeader;
+use super::{VtfError, VtfResult};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::io::{Read, Write};
+
+// How the image data chunks are compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMethod {
+    Deflate,
+    Zstd,
+}
+
+impl CompressionMethod {
+    fn id(self) -> u16 {
+        match self {
+            CompressionMethod::Deflate => 8,
+            CompressionMethod::Zstd => 93,
+        }
+    }
+
+    fn from_id(id: u16) -> VtfResult<Self> {
+        match id {
+            // Files written before zstd support leave the method at 0
+            0 | 8 => Ok(CompressionMethod::Deflate),
+            93 => Ok(CompressionMethod::Zstd),
+            _ => Err(VtfError::DecompressionError(format!(
+                "Unknown compression method {}",
+                id
+            ))),
+        }
+    }
+}
+
+// Contents of the AXC resource: the level and method packed into one value, then
+// the compressed size of every mip, frame and face in file order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct AuxCompressionInfo {
+    pub method: CompressionMethod,
+    // 0 stores the chunks uncompressed, -1 is the method's default
+    pub level: i16,
+    pub sizes: Vec<u32>,
+}
+
+impl AuxCompressionInfo {
+    pub fn read(data: &[u8]) -> VtfResult<Self> {
+        if data.len() < 4 || !data.len().is_multiple_of(4) {
+            return Err(VtfError::DecompressionError(
+                "Compression info resource is truncated".into(),
+            ));
+        }
+        let mut values = data
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+        let packed = values.next().unwrap_or(0);
+        Ok(Self {
+            method: CompressionMethod::from_id((packed >> 16) as u16)?,
+            level: packed as u16 as i16,
+            sizes: values.collect(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let packed = (self.method.id() as u32) << 16 | self.level as u16 as u32;
+        let mut output = Vec::with_capacity(4 + self.sizes.len() * 4);
+        output.extend_from_slice(&packed.to_le_bytes());
+        for size in &self.sizes {
+            output.extend_from_slice(&size.to_le_bytes());
+        }
+        output
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.level != 0
+    }
+}
+
+fn compress(data: &[u8], method: CompressionMethod, level: i16) -> VtfResult<Vec<u8>> {
+    match method {
+        CompressionMethod::Deflate => {
+            let level = if level < 0 {
+                Compression::default()
+            } else {
+                Compression::new(level.min(9) as u32)
+            };
+            let mut encoder = ZlibEncoder::new(Vec::new(), level);
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        // zstd takes 0 as its default level
+        CompressionMethod::Zstd => Ok(zstd::stream::encode_all(data, level.max(0) as i32)?),
+    }
+}
+
+fn decompress(data: &[u8], method: CompressionMethod, expected_size: usize) -> VtfResult<Vec<u8>> {
+    let mut output = Vec::with_capacity(expected_size);
+    let result = match method {
+        CompressionMethod::Deflate => ZlibDecoder::new(data).read_to_end(&mut output),
+        CompressionMethod::Zstd => zstd::stream::Decoder::new(data)
+            .and_then(|mut decoder| decoder.read_to_end(&mut output)),
+    };
+    result.map_err(|e| VtfError::DecompressionError(e.to_string()))?;
+
+    if output.len() != expected_size {
+        return Err(VtfError::DecompressionError(format!(
+            "Expected {} bytes of image data, got {}",
+            expected_size,
+            output.len()
+        )));
+    }
+    Ok(output)
+}
+
+// Compress each chunk of image data on its own and join them. Chunks are the
+// mips, frames and faces in file order.
+pub(super) fn compress_image_data(
+    chunks: &[Vec<u8>],
+    method: CompressionMethod,
+    level: i16,
+) -> VtfResult<(Vec<u8>, AuxCompressionInfo)> {
+    let mut output = Vec::new();
+    let mut sizes = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        let start = output.len();
+        if level == 0 {
+            output.extend_from_slice(chunk);
+        } else {
+            output.extend(compress(chunk, method, level)?);
+        }
+        sizes.push((output.len() - start) as u32);
+    }
+    Ok((
+        output,
+        AuxCompressionInfo {
+            method,
+            level,
+            sizes,
+        },
+    ))
+}
+
+// One compressed mip of one frame and face, with every slice of a volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Chunk {
+    // Where the chunk would start in the file if the image data were uncompressed
+    pub data_offset: usize,
+    // Inflated size
+    pub size: usize,
+    // Where the compressed stream actually starts in the file
+    pub file_offset: usize,
+    pub compressed_size: usize,
+}
+
+// Where every chunk of compressed high-res image data is, so a decode only reads and
+// inflates the chunk it needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct CompressedChunks {
+    method: CompressionMethod,
+    // In file order: smallest mip first, then frames, then faces
+    chunks: Vec<Chunk>,
+}
+
+impl CompressedChunks {
+    pub fn new(header: &VtfHeader, info: &AuxCompressionInfo) -> VtfResult<Self> {
+        let layers = header.frames as usize * header.face_count() as usize;
+        let chunk_count = header.mipmap_count as usize * layers;
+        if info.sizes.len() != chunk_count {
+            return Err(VtfError::DecompressionError(format!(
+                "Compression info lists {} chunks, the texture has {}",
+                info.sizes.len(),
+                chunk_count
+            )));
+        }
+
+        let mut chunks = Vec::with_capacity(chunk_count);
+        let mut data_offset = header.image_data_offset() as usize;
+        let mut file_offset = data_offset;
+        let mut sizes = info.sizes.iter();
+        for mip in (0..header.mipmap_count).rev() {
+            let size = (header.mipmap_data_size(mip) * header.mipmap_depth(mip)) as usize;
+            for &compressed_size in sizes.by_ref().take(layers) {
+                chunks.push(Chunk {
+                    data_offset,
+                    size,
+                    file_offset,
+                    compressed_size: compressed_size as usize,
+                });
+                data_offset += size;
+                file_offset += compressed_size as usize;
+            }
+        }
+        Ok(Self {
+            method: info.method,
+            chunks,
+        })
+    }
+
+    // The chunk holding offset..offset + size of the uncompressed layout
+    pub fn find(&self, offset: usize, size: usize) -> Option<&Chunk> {
+        let index = self
+            .chunks
+            .partition_point(|chunk| chunk.data_offset + chunk.size <= offset);
+        self.chunks.get(index).filter(|chunk| {
+            chunk.data_offset <= offset && offset + size <= chunk.data_offset + chunk.size
+        })
+    }
+
+    pub fn inflate(&self, chunk: &Chunk, data: &[u8]) -> VtfResult<Vec<u8>> {
+        decompress(data, self.method, chunk.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_info_round_trip() {
+        let info = AuxCompressionInfo {
+            method: CompressionMethod::Zstd,
+            level: -1,
+            sizes: vec![12, 34, 5678],
+        };
+        let bytes = info.to_bytes();
+        assert_eq!(&bytes[..4], &[0xFF, 0xFF, 93, 0]);
+        assert_eq!(AuxCompressionInfo::read(&bytes).unwrap(), info);
+    }
+
+    #[test]
+    fn test_chunks_round_trip() {
+        let chunks = vec![vec![7u8; 64], (0..=255).collect::<Vec<u8>>()];
+        for method in [CompressionMethod::Deflate, CompressionMethod::Zstd] {
+            let (data, info) = compress_image_data(&chunks, method, 6).unwrap();
+            assert_eq!(info.sizes.iter().sum::<u32>() as usize, data.len());
+            let first = info.sizes[0] as usize;
+            assert_eq!(decompress(&data[..first], method, 64).unwrap(), chunks[0]);
+            assert!(matches!(
+                decompress(&data[first..], method, 64),
+                Err(VtfError::DecompressionError(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,686 @@
+//! Console VTFs (VTFX) from the Xbox 360 and PS3 releases: a big-endian header,
+//! image data in its own order and byte layout, optionally LZMA compressed.
+//! They are read by rewriting them as PC 7.5 files.
+
+use super::decoder::{align16, write_resource_entry};
+use super::dxt::{DxtQuality, compress_dxt};
+use super::header::{
+    RESOURCE_FLAG_NO_DATA_CHUNK, VtfFlags, VtfFormat, VtfResourceEntry, VtfResourceTag,
+};
+use super::{VtfError, VtfResult};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use std::io::{Cursor, Read};
+
+pub const VTFX_SIGNATURE: [u8; 4] = *b"VTFX";
+
+// Valve's LZMA wrapper: "LZMA", unpacked and packed size (little endian), then the
+// 5 property bytes and the raw stream
+const LZMA_SIGNATURE: [u8; 4] = *b"LZMA";
+const LZMA_HEADER_SIZE: usize = 17;
+
+// Fixed part of the header, the resource directory follows
+const VTFX_HEADER_SIZE: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsolePlatform {
+    Xbox360,
+    Ps3,
+}
+
+impl ConsolePlatform {
+    // The major version says which console the file was built for
+    fn from_version(major: u32) -> Option<Self> {
+        match major {
+            0x360 => Some(ConsolePlatform::Xbox360),
+            0x333 => Some(ConsolePlatform::Ps3),
+            _ => None,
+        }
+    }
+}
+
+// VTFX file header
+#[derive(Debug, Clone)]
+pub struct VtfxHeader {
+    pub platform: ConsolePlatform,
+    pub version: (u32, u32),
+    pub header_size: u32,
+    pub flags: VtfFlags,
+    pub width: u16,
+    pub height: u16,
+    pub depth: u16,
+    pub frames: u16,
+    // Bytes the engine loads up front, before streaming the rest
+    pub preload_size: u16,
+    // Top mips dropped when the file was built. Only 0 is supported, it's unclear
+    // whether width x height is then the stored top mip or the dropped one.
+    pub mip_skip_count: u8,
+    pub reflectivity: [f32; 3],
+    pub bumpmap_scale: f32,
+    pub format: VtfFormat,
+    // Average color, stored instead of a thumbnail
+    pub low_res_sample: [u8; 4],
+    // Size of the LZMA-compressed image data, 0 when it's stored as is
+    pub compressed_size: u32,
+    // Directory entries with inline values already in native order
+    pub resources: Vec<VtfResourceEntry>,
+}
+
+impl VtfxHeader {
+    pub fn read(data: &[u8]) -> VtfResult<Self> {
+        let mut cursor = Cursor::new(data);
+
+        let mut signature = [0u8; 4];
+        cursor.read_exact(&mut signature)?;
+        if signature != VTFX_SIGNATURE {
+            return Err(VtfError::InvalidSignature);
+        }
+
+        let major = cursor.read_u32::<BigEndian>()?;
+        let minor = cursor.read_u32::<BigEndian>()?;
+        let platform = ConsolePlatform::from_version(major)
+            .ok_or(VtfError::UnsupportedVersion(major, minor))?;
+
+        let header_size = cursor.read_u32::<BigEndian>()?;
+        let flags = VtfFlags::from_bits_truncate(cursor.read_u32::<BigEndian>()?);
+        let width = cursor.read_u16::<BigEndian>()?;
+        let height = cursor.read_u16::<BigEndian>()?;
+        let depth = cursor.read_u16::<BigEndian>()?;
+        let frames = cursor.read_u16::<BigEndian>()?;
+        let preload_size = cursor.read_u16::<BigEndian>()?;
+        let mip_skip_count = cursor.read_u8()?;
+        let resource_count = cursor.read_u8()?;
+        let reflectivity = [
+            cursor.read_f32::<BigEndian>()?,
+            cursor.read_f32::<BigEndian>()?,
+            cursor.read_f32::<BigEndian>()?,
+        ];
+        let bumpmap_scale = cursor.read_f32::<BigEndian>()?;
+        let format = VtfFormat::try_from(cursor.read_i32::<BigEndian>()?)?;
+        let mut low_res_sample = [0u8; 4];
+        cursor.read_exact(&mut low_res_sample)?;
+        let compressed_size = cursor.read_u32::<BigEndian>()?;
+
+        cursor.set_position(VTFX_HEADER_SIZE);
+        let mut resources = Vec::with_capacity(resource_count as usize);
+        for _ in 0..resource_count {
+            let mut tag = [0u8; 3];
+            cursor.read_exact(&mut tag)?;
+            let flags = cursor.read_u8()?;
+            let data = cursor.read_u32::<BigEndian>()?;
+            resources.push(VtfResourceEntry {
+                tag: VtfResourceTag::from_bytes(tag),
+                flags,
+                data,
+            });
+        }
+
+        Ok(Self {
+            platform,
+            version: (major, minor),
+            header_size,
+            flags,
+            width,
+            height,
+            depth: depth.max(1),
+            frames: frames.max(1),
+            preload_size,
+            mip_skip_count,
+            reflectivity,
+            bumpmap_scale,
+            format,
+            low_res_sample,
+            compressed_size,
+            resources,
+        })
+    }
+
+    // The mip count isn't stored: full chains unless NOMIP is set
+    pub fn mipmap_count(&self) -> u8 {
+        if self.flags.contains(VtfFlags::NOMIP) {
+            1
+        } else {
+            let largest = self.width.max(self.height).max(self.depth).max(1);
+            (u16::BITS - largest.leading_zeros()) as u8
+        }
+    }
+
+    pub fn face_count(&self) -> u32 {
+        if self.flags.contains(VtfFlags::ENVMAP) {
+            6
+        } else {
+            1
+        }
+    }
+
+    // (width, height, depth) of a mip level
+    fn mipmap_size(&self, level: u8) -> (u32, u32, u32) {
+        (
+            (self.width as u32 >> level).max(1),
+            (self.height as u32 >> level).max(1),
+            (self.depth as u32 >> level).max(1),
+        )
+    }
+
+    // Bytes of every slice of one face at a mip level
+    fn layer_size(&self, level: u8) -> usize {
+        let (width, height, depth) = self.mipmap_size(level);
+        (self.format.compute_image_size(width, height) * depth) as usize
+    }
+
+    fn resource_data<'a>(&self, data: &'a [u8], entry: &VtfResourceEntry) -> Option<&'a [u8]> {
+        let start = entry.offset()? as usize;
+        let size = data.get(start..start + 4)?;
+        let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        data.get(start + 4..start + 4 + size)
+    }
+
+    // Image data as stored on the console, decompressed
+    fn console_image_data(&self, data: &[u8]) -> VtfResult<Vec<u8>> {
+        let offset = self
+            .resources
+            .iter()
+            .find(|entry| entry.tag == VtfResourceTag::HighResImage)
+            .and_then(|entry| entry.offset())
+            .ok_or_else(|| VtfError::InvalidData("VTFX has no image data".into()))?
+            as usize;
+
+        let out_of_bounds = || VtfError::InvalidData("VTFX image data out of bounds".into());
+        if self.compressed_size > 0 {
+            let end = offset + self.compressed_size as usize;
+            decompress_lzma(data.get(offset..end).ok_or_else(out_of_bounds)?)
+        } else {
+            let size = self.console_offsets().1;
+            Ok(data
+                .get(offset..offset + size)
+                .ok_or_else(out_of_bounds)?
+                .to_vec())
+        }
+    }
+
+    // Offset of each face's mip in the console layout, indexed by [mip][frame * faces + face],
+    // and the total size. Faces hold their mips largest first, frames hold their faces,
+    // and 360 cubemap faces start on 512-byte boundaries.
+    fn console_offsets(&self) -> (Vec<Vec<usize>>, usize) {
+        let faces = self.face_count() as usize;
+        let mipmap_count = self.mipmap_count();
+        let padded = self.platform == ConsolePlatform::Xbox360 && faces > 1;
+
+        let mut offsets = vec![Vec::new(); mipmap_count as usize];
+        let mut offset = 0;
+        for _ in 0..self.frames {
+            for face in 0..faces {
+                for (mip, mip_offsets) in offsets.iter_mut().enumerate() {
+                    mip_offsets.push(offset);
+                    offset += self.layer_size(mip as u8);
+                }
+                if padded && face + 1 < faces {
+                    offset = offset.next_multiple_of(512);
+                }
+            }
+        }
+        (offsets, offset)
+    }
+
+    // Image data reordered to the PC layout (smallest mip first, then frames, faces,
+    // slices) with pixels in PC byte order
+    fn pc_image_data(&self, data: &[u8]) -> VtfResult<Vec<u8>> {
+        if self.mip_skip_count != 0 {
+            return Err(VtfError::InvalidData(format!(
+                "VTFX files with {} skipped mips aren't supported",
+                self.mip_skip_count
+            )));
+        }
+        let console = self.console_image_data(data)?;
+        let (offsets, total) = self.console_offsets();
+        if console.len() < total {
+            return Err(VtfError::InvalidData(format!(
+                "VTFX image data is {} bytes, expected {}",
+                console.len(),
+                total
+            )));
+        }
+
+        let mut output = Vec::with_capacity(total);
+        for mip in (0..self.mipmap_count()).rev() {
+            let size = self.layer_size(mip);
+            for &offset in &offsets[mip as usize] {
+                let start = output.len();
+                output.extend_from_slice(&console[offset..offset + size]);
+                swap_to_pc(&mut output[start..], self.format, self.platform);
+            }
+        }
+        Ok(output)
+    }
+}
+
+// Undo the byte swapping the console tools applied to each pixel format
+fn swap_to_pc(data: &mut [u8], format: VtfFormat, platform: ConsolePlatform) {
+    let word = match format {
+        VtfFormat::Rgba8888
+        | VtfFormat::Abgr8888
+        | VtfFormat::Argb8888
+        | VtfFormat::Bgra8888
+        | VtfFormat::Bgrx8888
+        | VtfFormat::Uvwq8888
+        | VtfFormat::Uvlx8888 => 4,
+        VtfFormat::Rgb565
+        | VtfFormat::Bgr565
+        | VtfFormat::Bgrx5551
+        | VtfFormat::Bgra5551
+        | VtfFormat::Bgra4444
+        | VtfFormat::Ia88
+        | VtfFormat::Uv88
+        | VtfFormat::Rgba16161616F
+        | VtfFormat::Rgba16161616 => 2,
+        // The 360 reads block data as 16-bit words, the PS3 as the PC does
+        _ if format.is_compressed() && platform == ConsolePlatform::Xbox360 => 2,
+        _ => return,
+    };
+    for chunk in data.chunks_exact_mut(word) {
+        chunk.reverse();
+    }
+}
+
+fn decompress_lzma(data: &[u8]) -> VtfResult<Vec<u8>> {
+    if data.len() < LZMA_HEADER_SIZE || data[..4] != LZMA_SIGNATURE {
+        return Err(VtfError::DecompressionError(
+            "Image data has no LZMA header".into(),
+        ));
+    }
+    let mut cursor = Cursor::new(&data[4..]);
+    let unpacked_size = cursor.read_u32::<LittleEndian>()? as usize;
+
+    // The property bytes are a standard .lzma header minus the size
+    let options = lzma_rs::decompress::Options {
+        unpacked_size: lzma_rs::decompress::UnpackedSize::UseProvided(Some(unpacked_size as u64)),
+        ..Default::default()
+    };
+    let mut output = Vec::with_capacity(unpacked_size);
+    lzma_rs::lzma_decompress_with_options(&mut &data[12..], &mut output, &options)
+        .map_err(|e| VtfError::DecompressionError(e.to_string()))?;
+    if output.len() != unpacked_size {
+        return Err(VtfError::DecompressionError(format!(
+            "Expected {} bytes of image data, got {}",
+            unpacked_size,
+            output.len()
+        )));
+    }
+    Ok(output)
+}
+
+// Check for the VTFX signature
+pub fn is_vtfx(data: &[u8]) -> bool {
+    data.starts_with(&VTFX_SIGNATURE)
+}
+
+// Rewrite a VTFX file as a PC 7.5 VTF. The thumbnail is made from the stored
+// average color; other resources carry over.
+pub fn vtfx_to_vtf(data: &[u8]) -> VtfResult<Vec<u8>> {
+    let header = VtfxHeader::read(data)?;
+    let image_data = header.pc_image_data(data)?;
+    let thumbnail = compress_dxt(
+        &header.low_res_sample,
+        1,
+        1,
+        VtfFormat::Dxt1,
+        DxtQuality::default(),
+    )?;
+
+    let mut inline = Vec::new();
+    let mut chunks = Vec::new();
+    for entry in &header.resources {
+        if matches!(
+            entry.tag,
+            VtfResourceTag::LowResImage | VtfResourceTag::HighResImage
+        ) {
+            continue;
+        }
+        if !entry.has_data_chunk() {
+            inline.push((entry.tag, entry.data));
+        } else if let Some(chunk) = header.resource_data(data, entry) {
+            chunks.push((entry.tag, chunk));
+        }
+    }
+
+    let resource_count = (2 + inline.len() + chunks.len()) as u32;
+    let header_size = align16(80 + resource_count * 8);
+    let thumb_offset = header_size;
+    let image_offset = thumb_offset + thumbnail.len() as u32;
+
+    let mut output = Vec::with_capacity(header_size as usize + image_data.len());
+    output.extend_from_slice(b"VTF\0");
+    output.extend_from_slice(&7u32.to_le_bytes());
+    output.extend_from_slice(&5u32.to_le_bytes());
+    output.extend_from_slice(&header_size.to_le_bytes());
+    output.extend_from_slice(&header.width.to_le_bytes());
+    output.extend_from_slice(&header.height.to_le_bytes());
+    output.extend_from_slice(&header.flags.bits().to_le_bytes());
+    output.extend_from_slice(&header.frames.to_le_bytes());
+    output.extend_from_slice(&0u16.to_le_bytes());
+    output.extend_from_slice(&[0u8; 4]);
+    for value in header.reflectivity {
+        output.extend_from_slice(&value.to_le_bytes());
+    }
+    output.extend_from_slice(&[0u8; 4]);
+    output.extend_from_slice(&header.bumpmap_scale.to_le_bytes());
+    output.extend_from_slice(&(header.format as i32).to_le_bytes());
+    output.push(header.mipmap_count());
+    output.extend_from_slice(&(VtfFormat::Dxt1 as i32).to_le_bytes());
+    output.push(1);
+    output.push(1);
+    output.extend_from_slice(&header.depth.to_le_bytes());
+    output.extend_from_slice(&[0u8; 3]);
+    output.extend_from_slice(&resource_count.to_le_bytes());
+    output.extend_from_slice(&[0u8; 8]);
+
+    write_resource_entry(&mut output, VtfResourceTag::LowResImage, 0, thumb_offset);
+    write_resource_entry(&mut output, VtfResourceTag::HighResImage, 0, image_offset);
+    for &(tag, value) in &inline {
+        write_resource_entry(&mut output, tag, RESOURCE_FLAG_NO_DATA_CHUNK, value);
+    }
+    let mut chunk_offset = image_offset + image_data.len() as u32;
+    for &(tag, chunk) in &chunks {
+        write_resource_entry(&mut output, tag, 0, chunk_offset);
+        chunk_offset += 4 + chunk.len() as u32;
+    }
+
+    output.resize(header_size as usize, 0);
+    output.extend_from_slice(&thumbnail);
+    output.extend(image_data);
+    for (_, chunk) in chunks {
+        output.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        output.extend_from_slice(chunk);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vtf::formats::convert_to_rgba;
+    use crate::vtf::{CubemapFace, VtfBuilder, VtfDecoder, VtfImage};
+
+    // Lay a PC texture out the way the console tools do
+    fn to_vtfx(vtf: &VtfImage, platform: ConsolePlatform, compress: bool) -> Vec<u8> {
+        let pc = &vtf.header;
+        let mut header = VtfxHeader {
+            platform,
+            version: (
+                if platform == ConsolePlatform::Xbox360 {
+                    0x360
+                } else {
+                    0x333
+                },
+                8,
+            ),
+            header_size: 76,
+            flags: pc.flags,
+            width: pc.width,
+            height: pc.height,
+            depth: pc.depth.max(1),
+            frames: pc.frames,
+            preload_size: 0,
+            mip_skip_count: 0,
+            reflectivity: pc.reflectivity,
+            bumpmap_scale: pc.bumpmap_scale,
+            format: pc.high_res_format,
+            low_res_sample: [200, 100, 50, 255],
+            compressed_size: 0,
+            resources: Vec::new(),
+        };
+
+        let faces = header.face_count() as usize;
+        let (offsets, total) = header.console_offsets();
+        let mut image = vec![0u8; total];
+        for (mip, mip_offsets) in offsets.iter().enumerate() {
+            let size = header.layer_size(mip as u8);
+            for (index, &offset) in mip_offsets.iter().enumerate() {
+                let frame = (index / faces) as u16;
+                let start = pc.mipmap_offset(mip as u8, frame) as usize + size * (index % faces);
+                let layer = &mut image[offset..offset + size];
+                layer.copy_from_slice(&vtf.read_bytes(start, size).unwrap());
+                // Swapping is its own inverse
+                swap_to_pc(layer, header.format, platform);
+            }
+        }
+
+        if compress {
+            let options = lzma_rs::compress::Options {
+                unpacked_size: lzma_rs::compress::UnpackedSize::SkipWritingToHeader,
+            };
+            let mut stream = Vec::new();
+            lzma_rs::lzma_compress_with_options(&mut image.as_slice(), &mut stream, &options)
+                .unwrap();
+            let mut wrapped = LZMA_SIGNATURE.to_vec();
+            wrapped.extend_from_slice(&(image.len() as u32).to_le_bytes());
+            wrapped.extend_from_slice(&(stream.len() as u32 - 5).to_le_bytes());
+            wrapped.extend(stream);
+            header.compressed_size = wrapped.len() as u32;
+            image = wrapped;
+        }
+
+        let mut output = Vec::new();
+        output.extend_from_slice(&VTFX_SIGNATURE);
+        output.extend_from_slice(&header.version.0.to_be_bytes());
+        output.extend_from_slice(&header.version.1.to_be_bytes());
+        output.extend_from_slice(&header.header_size.to_be_bytes());
+        output.extend_from_slice(&header.flags.bits().to_be_bytes());
+        for value in [header.width, header.height, header.depth, header.frames, 0] {
+            output.extend_from_slice(&value.to_be_bytes());
+        }
+        output.extend_from_slice(&[0, 2]);
+        for value in header.reflectivity {
+            output.extend_from_slice(&value.to_be_bytes());
+        }
+        output.extend_from_slice(&header.bumpmap_scale.to_be_bytes());
+        output.extend_from_slice(&(header.format as i32).to_be_bytes());
+        output.extend_from_slice(&header.low_res_sample);
+        output.extend_from_slice(&header.compressed_size.to_be_bytes());
+        output.extend_from_slice(b"CRC\x02");
+        output.extend_from_slice(&0x12345678u32.to_be_bytes());
+        output.extend_from_slice(b"\x30\0\0\0");
+        output.extend_from_slice(&header.header_size.to_be_bytes());
+        output.extend(image);
+        output
+    }
+
+    // A VTFX written field by field, for fixtures that don't go through the layout
+    // code they test. One image resource, nothing else.
+    fn vtfx_bytes(
+        major: u32,
+        flags: VtfFlags,
+        size: (u16, u16),
+        frames: u16,
+        format: VtfFormat,
+        image: &[u8],
+    ) -> Vec<u8> {
+        let mut output = b"VTFX".to_vec();
+        output.extend_from_slice(&major.to_be_bytes());
+        output.extend_from_slice(&8u32.to_be_bytes());
+        output.extend_from_slice(&68u32.to_be_bytes());
+        output.extend_from_slice(&flags.bits().to_be_bytes());
+        for value in [size.0, size.1, 1, frames, 0] {
+            output.extend_from_slice(&value.to_be_bytes());
+        }
+        // No skipped mips, one resource
+        output.extend_from_slice(&[0, 1]);
+        for value in [0.5f32, 0.5, 0.5, 1.0] {
+            output.extend_from_slice(&value.to_be_bytes());
+        }
+        output.extend_from_slice(&(format as i32).to_be_bytes());
+        output.extend_from_slice(&[128, 128, 128, 255]);
+        output.extend_from_slice(&0u32.to_be_bytes());
+        assert_eq!(output.len(), 60);
+        output.extend_from_slice(b"\x30\0\0\0");
+        output.extend_from_slice(&68u32.to_be_bytes());
+        output.extend_from_slice(image);
+        output
+    }
+
+    #[test]
+    fn test_vtfx_360_cubemap_fixture() {
+        // Pixel p of mip m of face f, as RGBA
+        let pixel =
+            |face: u8, mip: u8, p: u8| [face * 16 + mip * 8 + p, 100 + face, 200 + mip, 255 - p];
+
+        // A 2x2 cubemap with its 1x1 mip. Each face holds its mips largest first,
+        // every face but the last is padded to 512 bytes, and each pixel is one
+        // byte-reversed 32-bit word.
+        let mut image = Vec::new();
+        for face in 0..6 {
+            for (mip, pixels) in [(0, 4), (1, 1)] {
+                for p in 0..pixels {
+                    let [r, g, b, a] = pixel(face, mip, p);
+                    image.extend_from_slice(&[a, b, g, r]);
+                }
+            }
+            if face < 5 {
+                image.resize(512 * (face as usize + 1), 0xEE);
+            }
+        }
+        assert_eq!(image.len(), 5 * 512 + 20);
+
+        let vtfx = vtfx_bytes(
+            0x360,
+            VtfFlags::ENVMAP,
+            (2, 2),
+            1,
+            VtfFormat::Rgba8888,
+            &image,
+        );
+        let vtf = VtfDecoder::load_from_memory(&vtfx).unwrap();
+        assert!(vtf.is_envmap());
+        assert_eq!(vtf.mipmap_count(), 2);
+        for (face, &cube_face) in CubemapFace::CUBE.iter().enumerate() {
+            for (mip, pixels) in [(0, 4), (1, 1)] {
+                let expected: Vec<u8> = (0..pixels)
+                    .flat_map(|p| pixel(face as u8, mip, p))
+                    .collect();
+                assert_eq!(
+                    vtf.decode_face(mip, 0, cube_face).unwrap().data,
+                    expected,
+                    "face {} mip {}",
+                    face,
+                    mip
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_vtfx_dxt1_frames_fixture() {
+        // One 4x4 DXT1 block per frame: two 5:6:5 colors, then 2-bit indices
+        let blocks: [[u8; 8]; 2] = [
+            [0x00, 0xF8, 0x1F, 0x00, 0x00, 0x55, 0xAA, 0xFF],
+            [0xE0, 0x07, 0x00, 0x00, 0x1B, 0xE4, 0x1B, 0xE4],
+        ];
+        // The 360 stores blocks as big-endian 16-bit words, frames one after another
+        let xbox: Vec<u8> = blocks
+            .iter()
+            .flat_map(|block| block.chunks(2).flat_map(|word| [word[1], word[0]]))
+            .collect();
+        let ps3 = blocks.concat();
+
+        let flags = VtfFlags::NOMIP;
+        for (major, image) in [(0x360, xbox), (0x333, ps3)] {
+            let vtfx = vtfx_bytes(major, flags, (4, 4), 2, VtfFormat::Dxt1, &image);
+            let vtf = VtfDecoder::load_from_memory(&vtfx).unwrap();
+            assert_eq!(vtf.frame_count(), 2);
+            for (frame, block) in blocks.iter().enumerate() {
+                let expected = convert_to_rgba(block, VtfFormat::Dxt1, 4, 4).unwrap();
+                assert_eq!(
+                    vtf.decode(0, frame as u16).unwrap().data,
+                    expected,
+                    "{:#x} frame {}",
+                    major,
+                    frame
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_vtfx_rejects_skipped_mips() {
+        let image = [0u8; 8];
+        let mut vtfx = vtfx_bytes(0x333, VtfFlags::NOMIP, (4, 4), 1, VtfFormat::Dxt1, &image);
+        assert!(VtfDecoder::load_from_memory(&vtfx).is_ok());
+        // mip_skip_count
+        vtfx[30] = 1;
+        assert!(VtfxHeader::read(&vtfx).is_ok());
+        assert!(matches!(
+            VtfDecoder::load_from_memory(&vtfx),
+            Err(VtfError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn test_vtfx_animated() {
+        let frames: Vec<Vec<u8>> = (0..2u8)
+            .map(|frame| {
+                (0..16 * 8)
+                    .flat_map(|i| [(i * 2) as u8, frame * 100, 30, 200])
+                    .collect()
+            })
+            .collect();
+        let pc = VtfBuilder::from_frames(16, 8, frames)
+            .unwrap()
+            .format(VtfFormat::Bgra8888)
+            .reflectivity([0.25, 0.5, 0.75])
+            .build()
+            .unwrap();
+        let pc = VtfDecoder::load_from_memory(&pc).unwrap();
+
+        for compress in [false, true] {
+            let vtfx = to_vtfx(&pc, ConsolePlatform::Ps3, compress);
+            let header = VtfxHeader::read(&vtfx).unwrap();
+            assert_eq!(header.platform, ConsolePlatform::Ps3);
+            assert_eq!(header.mipmap_count(), pc.mipmap_count());
+
+            let vtf = VtfDecoder::load_from_memory(&vtfx).unwrap();
+            assert_eq!(vtf.header.reflectivity, [0.25, 0.5, 0.75]);
+            assert_eq!(vtf.crc(), Some(0x12345678));
+            // DXT1 keeps the average color to 5:6:5 precision
+            let thumbnail = vtf.decode_thumbnail().unwrap().data;
+            for (a, b) in thumbnail.iter().zip([200u8, 100, 50, 255]) {
+                assert!(a.abs_diff(b) <= 4);
+            }
+            for mip in 0..pc.mipmap_count() {
+                for frame in 0..2 {
+                    assert_eq!(
+                        vtf.decode(mip, frame).unwrap().data,
+                        pc.decode(mip, frame).unwrap().data
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_vtfx_cubemap_is_padded_and_swapped() {
+        let faces: Vec<Vec<u8>> = (0..6u8)
+            .map(|face| {
+                (0..8 * 8)
+                    .flat_map(|i| [face * 40, (i * 4) as u8, 255 - face * 40, 255])
+                    .collect()
+            })
+            .collect();
+        let pc = VtfBuilder::from_cube_faces(8, faces)
+            .unwrap()
+            .format(VtfFormat::Dxt1)
+            .version(crate::vtf::VtfVersion::new(7, 5))
+            .build()
+            .unwrap();
+        let pc = VtfDecoder::load_from_memory(&pc).unwrap();
+
+        let vtfx = to_vtfx(&pc, ConsolePlatform::Xbox360, true);
+        let converted = vtfx_to_vtf(&vtfx).unwrap();
+        let vtf = VtfDecoder::load_from_memory(&converted).unwrap();
+        assert!(vtf.is_envmap());
+        for face in CubemapFace::CUBE {
+            assert_eq!(
+                vtf.decode_face(0, 0, face).unwrap().data,
+                pc.decode_face(0, 0, face).unwrap().data
+            );
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! Cubemap faces, cross layouts and panorama/spheremap projections
+
+use super::decoder::DecodedFrame;
+use super::{VtfError, VtfResult};
+use std::f32::consts::PI;
+
+// Faces in the order they're stored in the file. Source samples envmaps with
+// world-space vectors through the D3D cube convention, so Up is +Z.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubemapFace {
+    Right = 0,
+    Left = 1,
+    Back = 2,
+    Front = 3,
+    Up = 4,
+    Down = 5,
+    // Pre-7.5 files store a spheremap after the six cube faces
+    SphereMap = 6,
+}
+
+impl CubemapFace {
+    pub const CUBE: [CubemapFace; 6] = [
+        CubemapFace::Right,
+        CubemapFace::Left,
+        CubemapFace::Back,
+        CubemapFace::Front,
+        CubemapFace::Up,
+        CubemapFace::Down,
+    ];
+
+    pub fn from_index(index: u32) -> Option<Self> {
+        match index {
+            0 => Some(CubemapFace::Right),
+            1 => Some(CubemapFace::Left),
+            2 => Some(CubemapFace::Back),
+            3 => Some(CubemapFace::Front),
+            4 => Some(CubemapFace::Up),
+            5 => Some(CubemapFace::Down),
+            6 => Some(CubemapFace::SphereMap),
+            _ => None,
+        }
+    }
+
+    // File name suffix, same as skybox materials use
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            CubemapFace::Right => "rt",
+            CubemapFace::Left => "lf",
+            CubemapFace::Back => "bk",
+            CubemapFace::Front => "ft",
+            CubemapFace::Up => "up",
+            CubemapFace::Down => "dn",
+            CubemapFace::SphereMap => "sph",
+        }
+    }
+}
+
+// How cube faces are laid out when exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CubemapLayout {
+    // 4x3 cells: Back on top, Left/Up/Right/Down across, Front below
+    #[default]
+    HorizontalCross,
+    // 3x4 cells: like the horizontal cross with Down rotated 180 degrees under Front
+    VerticalCross,
+    // One image per face
+    Separate,
+}
+
+impl CubemapLayout {
+    // Size of the layout in face cells
+    fn cells(&self) -> (u32, u32) {
+        match self {
+            CubemapLayout::HorizontalCross => (4, 3),
+            CubemapLayout::VerticalCross => (3, 4),
+            CubemapLayout::Separate => (1, 1),
+        }
+    }
+
+    // Cell (column, row) of a face, and whether it's rotated 180 degrees
+    fn cell(&self, face: CubemapFace) -> Option<(u32, u32, bool)> {
+        let cell = match (self, face) {
+            (CubemapLayout::Separate, _) | (_, CubemapFace::SphereMap) => return None,
+            (_, CubemapFace::Back) => (1, 0, false),
+            (_, CubemapFace::Left) => (0, 1, false),
+            (_, CubemapFace::Up) => (1, 1, false),
+            (_, CubemapFace::Right) => (2, 1, false),
+            (_, CubemapFace::Front) => (1, 2, false),
+            (CubemapLayout::HorizontalCross, CubemapFace::Down) => (3, 1, false),
+            (CubemapLayout::VerticalCross, CubemapFace::Down) => (1, 3, true),
+        };
+        Some(cell)
+    }
+}
+
+// Put the six cube faces into a cross. Cells without a face stay transparent.
+pub(super) fn assemble_cross(
+    faces: &[DecodedFrame],
+    layout: CubemapLayout,
+) -> VtfResult<DecodedFrame> {
+    if layout == CubemapLayout::Separate {
+        return Err(VtfError::InvalidData(
+            "Separate faces have no cross layout".into(),
+        ));
+    }
+    if faces.len() < 6 {
+        return Err(VtfError::InvalidData(format!(
+            "A cubemap needs 6 faces, got {}",
+            faces.len()
+        )));
+    }
+
+    let size = faces[0].width;
+    let (columns, rows) = layout.cells();
+    let width = size * columns;
+    let height = size * rows;
+    let mut data = vec![0u8; (width * height * 4) as usize];
+
+    for (face, decoded) in CubemapFace::CUBE.iter().zip(faces) {
+        if decoded.width != size || decoded.height != size {
+            return Err(VtfError::InvalidData("Cubemap faces must be square".into()));
+        }
+        let Some((column, row, rotated)) = layout.cell(*face) else {
+            continue;
+        };
+
+        for y in 0..size {
+            for x in 0..size {
+                let (src_x, src_y) = if rotated {
+                    (size - 1 - x, size - 1 - y)
+                } else {
+                    (x, y)
+                };
+                let src = ((src_y * size + src_x) * 4) as usize;
+                let dst = (((row * size + y) * width + column * size + x) * 4) as usize;
+                data[dst..dst + 4].copy_from_slice(&decoded.data[src..src + 4]);
+            }
+        }
+    }
+
+    Ok(DecodedFrame {
+        data,
+        width,
+        height,
+        mipmap_level: faces[0].mipmap_level,
+        frame: faces[0].frame,
+    })
+}
+
+// Direction through a point on a face, a/b in -1..1 across/down the face
+fn face_direction(face: usize, a: f32, b: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -b, -a],
+        1 => [-1.0, -b, a],
+        2 => [a, 1.0, b],
+        3 => [a, -1.0, -b],
+        4 => [a, -b, 1.0],
+        _ => [-a, -b, -1.0],
+    }
+}
+
+// Face and 0..1 texture coordinates hit by a direction
+fn direction_to_face(direction: [f32; 3]) -> (usize, f32, f32) {
+    let [x, y, z] = direction;
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+
+    let (face, sc, tc, major) = if ax >= ay && ax >= az {
+        if x > 0.0 {
+            (0, -z, -y, ax)
+        } else {
+            (1, z, -y, ax)
+        }
+    } else if ay >= az {
+        if y > 0.0 {
+            (2, x, z, ay)
+        } else {
+            (3, x, -z, ay)
+        }
+    } else if z > 0.0 {
+        (4, x, -y, az)
+    } else {
+        (5, -x, -y, az)
+    };
+
+    let major = major.max(f32::EPSILON);
+    (face, (sc / major + 1.0) * 0.5, (tc / major + 1.0) * 0.5)
+}
+
+// Bilinear sample of an RGBA image, wrapping or clamping horizontally
+fn sample_bilinear(rgba: &[u8], width: u32, height: u32, u: f32, v: f32, wrap_x: bool) -> [f32; 4] {
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let column = |x: i64| -> u32 {
+        if wrap_x {
+            x.rem_euclid(width as i64) as u32
+        } else {
+            x.clamp(0, width as i64 - 1) as u32
+        }
+    };
+    let (x0i, x1i) = (column(x0 as i64), column(x0 as i64 + 1));
+    let y0i = y0 as u32;
+    let y1i = (y0i + 1).min(height - 1);
+
+    let pixel = |x: u32, y: u32| -> &[u8] {
+        let offset = ((y * width + x) * 4) as usize;
+        &rgba[offset..offset + 4]
+    };
+    let (p00, p10) = (pixel(x0i, y0i), pixel(x1i, y0i));
+    let (p01, p11) = (pixel(x0i, y1i), pixel(x1i, y1i));
+
+    let mut out = [0.0; 4];
+    for c in 0..4 {
+        let top = p00[c] as f32 * (1.0 - fx) + p10[c] as f32 * fx;
+        let bottom = p01[c] as f32 * (1.0 - fx) + p11[c] as f32 * fx;
+        out[c] = top * (1.0 - fy) + bottom * fy;
+    }
+    out
+}
+
+fn to_rgba8(color: [f32; 4]) -> [u8; 4] {
+    color.map(|c| c.round().clamp(0.0, 255.0) as u8)
+}
+
+// Sample six square faces stored back to back in file order
+fn sample_cube(cube: &[u8], size: u32, direction: [f32; 3]) -> [u8; 4] {
+    let (face, u, v) = direction_to_face(direction);
+    let face_len = (size * size * 4) as usize;
+    let face_data = &cube[face * face_len..(face + 1) * face_len];
+    to_rgba8(sample_bilinear(face_data, size, size, u, v, false))
+}
+
+// Project an equirectangular panorama onto six faces, back to back in file order.
+// The middle of the panorama looks down +X and its top row is +Z.
+pub(super) fn equirect_to_cube(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    face_size: u32,
+) -> VtfResult<Vec<u8>> {
+    if width == 0 || height == 0 || face_size == 0 {
+        return Err(VtfError::InvalidData("Empty panorama".into()));
+    }
+    if rgba.len() != (width * height * 4) as usize {
+        return Err(VtfError::InvalidData("Panorama size mismatch".into()));
+    }
+
+    let mut cube = Vec::with_capacity((face_size * face_size * 4 * 6) as usize);
+    for face in 0..6 {
+        for y in 0..face_size {
+            for x in 0..face_size {
+                let a = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+                let b = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+                let [dx, dy, dz] = face_direction(face, a, b);
+                let length = (dx * dx + dy * dy + dz * dz).sqrt();
+
+                let longitude = dy.atan2(dx);
+                let latitude = (dz / length).clamp(-1.0, 1.0).asin();
+                // Turning right (towards -Y) moves right across the panorama
+                let u = (0.5 - longitude / (2.0 * PI)).rem_euclid(1.0);
+                let v = 0.5 - latitude / PI;
+
+                let color = sample_bilinear(rgba, width, height, u, v, true);
+                cube.extend_from_slice(&to_rgba8(color));
+            }
+        }
+    }
+
+    Ok(cube)
+}
+
+// Render the spheremap face from six faces stored back to back. The sphere is
+// seen from above, with +X to the right and +Y up in the image.
+pub(super) fn generate_spheremap(cube: &[u8], size: u32) -> Vec<u8> {
+    let mut sphere = Vec::with_capacity((size * size * 4) as usize);
+
+    for py in 0..size {
+        for px in 0..size {
+            let mut x = 2.0 * (px as f32 + 0.5) / size as f32 - 1.0;
+            let mut y = 1.0 - 2.0 * (py as f32 + 0.5) / size as f32;
+
+            // Outside the sphere, repeat its rim so filtering doesn't pull in black
+            let radius_squared = x * x + y * y;
+            if radius_squared > 1.0 {
+                let radius = radius_squared.sqrt();
+                x /= radius;
+                y /= radius;
+            }
+            let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+
+            // Reflect the view vector (0, 0, 1) about the sphere normal
+            let reflected = [2.0 * z * x, 2.0 * z * y, 2.0 * z * z - 1.0];
+            sphere.extend_from_slice(&sample_cube(cube, size, reflected));
+        }
+    }
+
+    sphere
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_face(size: u32, color: [u8; 4]) -> Vec<u8> {
+        color.repeat((size * size) as usize)
+    }
+
+    #[test]
+    fn test_face_direction_round_trip() {
+        for face in 0..6 {
+            for &(a, b) in &[(0.0, 0.0), (-0.5, 0.25), (0.75, -0.9)] {
+                let (hit, u, v) = direction_to_face(face_direction(face, a, b));
+                assert_eq!(hit, face);
+                assert!((u * 2.0 - 1.0 - a).abs() < 1e-5);
+                assert!((v * 2.0 - 1.0 - b).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_assemble_cross() {
+        let size = 4;
+        let faces: Vec<DecodedFrame> = (0..6)
+            .map(|face| DecodedFrame {
+                data: solid_face(size, [face as u8 * 40, 0, 0, 255]),
+                width: size,
+                height: size,
+                mipmap_level: 0,
+                frame: 0,
+            })
+            .collect();
+
+        let cross = assemble_cross(&faces, CubemapLayout::HorizontalCross).unwrap();
+        assert_eq!((cross.width, cross.height), (16, 12));
+        let red_at = |cross: &DecodedFrame, column: u32, row: u32| {
+            let offset = (((row * size + 1) * cross.width + column * size + 1) * 4) as usize;
+            (cross.data[offset], cross.data[offset + 3])
+        };
+        assert_eq!(red_at(&cross, 1, 0), (2 * 40, 255)); // Back
+        assert_eq!(red_at(&cross, 3, 1), (5 * 40, 255)); // Down
+        assert_eq!(red_at(&cross, 0, 0), (0, 0)); // Empty cell
+
+        let cross = assemble_cross(&faces, CubemapLayout::VerticalCross).unwrap();
+        assert_eq!((cross.width, cross.height), (12, 16));
+        assert_eq!(red_at(&cross, 1, 3), (5 * 40, 255));
+
+        assert!(assemble_cross(&faces, CubemapLayout::Separate).is_err());
+    }
+
+    #[test]
+    fn test_equirect_to_cube() {
+        // Top half red, bottom half blue
+        let (width, height) = (64usize, 32usize);
+        let mut panorama = [255, 0, 0, 255].repeat(width * height / 2);
+        panorama.extend([0, 0, 255, 255].repeat(width * height / 2));
+
+        let size = 8;
+        let cube = equirect_to_cube(&panorama, width as u32, height as u32, size).unwrap();
+        let face_len = (size * size * 4) as usize;
+        assert_eq!(cube.len(), face_len * 6);
+
+        let up = &cube[4 * face_len..5 * face_len];
+        let down = &cube[5 * face_len..6 * face_len];
+        assert!(up.chunks(4).all(|p| p == [255, 0, 0, 255]));
+        assert!(down.chunks(4).all(|p| p == [0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_generate_spheremap() {
+        let size = 8;
+        let cube: Vec<u8> = (0..6)
+            .flat_map(|face| solid_face(size, [face as u8 * 40, 0, 0, 255]))
+            .collect();
+        let sphere = generate_spheremap(&cube, size);
+        assert_eq!(sphere.len(), (size * size * 4) as usize);
+
+        // The middle of the sphere reflects straight up
+        let center = ((size / 2 * size + size / 2) * 4) as usize;
+        assert_eq!(sphere[center], 4 * 40);
+    }
+}
@@ -1,14 +1,47 @@
 //! VTF decoder
 
-use super::formats::convert_to_rgba;
-use super::header::{VtfFormat, VtfHeader};
+use super::analyze::{alpha_content, recommend_format};
+use super::compression::{
+    AuxCompressionInfo, CompressedChunks, CompressionMethod, compress_image_data,
+};
+use super::console::{is_vtfx, vtfx_to_vtf};
+use super::cubemap::{
+    CubemapFace, CubemapLayout, assemble_cross, equirect_to_cube, generate_spheremap,
+};
+use super::dilate::dilate;
+use super::dxt::{DxtQuality, compress_dxt};
+use super::formats::{
+    Dither, convert_from_rgba, convert_from_rgba_f32, convert_to_rgba, convert_to_rgba_f32,
+    convert_to_rgba16,
+};
+use super::frame_cache::{FrameCache, FrameKey};
+use super::hdr::{linear_to_srgb, srgb_to_linear};
+use super::header::{
+    RESOURCE_FLAG_NO_DATA_CHUNK, VtfFlags, VtfFormat, VtfHeader, VtfResourceData, VtfResourceEntry,
+    VtfResourceTag, VtfVersion,
+};
+use super::heightmap::{HeightToNormal, height_to_normal};
+use super::mipmap::{MipFilter, coverage, preserve_alpha_coverage, renormalize, resample};
+use super::reflectivity::{average_reflectivity, linearize};
+use super::source::VtfSource;
+use super::spray::{SprayGame, candidate_sizes, image_data_size};
+use super::ssbump::{HeightToSsbump, SsbumpSource, height_to_ssbump, normal_to_ssbump};
 use super::{VtfError, VtfResult};
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+// Raw image data to RGBA pixels, one of the convert_to_rgba* functions
+type PixelConverter<T> = fn(&[u8], VtfFormat, u32, u32) -> VtfResult<Vec<T>>;
+
+// Decoded RGBA pixels. u8 for display, f32 or u16 to keep HDR and 16-bit data.
 #[derive(Debug, Clone)]
-pub struct DecodedFrame {
-    pub data: Vec<u8>,
+pub struct DecodedFrame<T = u8> {
+    pub data: Vec<T>,
     pub width: u32,
     pub height: u32,
     pub mipmap_level: u8,
@@ -30,7 +63,12 @@ impl DecodedFrame {
 #[derive(Debug, Clone)]
 pub struct VtfImage {
     pub header: VtfHeader,
-    raw_data: Vec<u8>,
+    // The whole file, in memory, mapped or behind a reader
+    source: VtfSource,
+    // Where the compressed chunks of 7.6 image data are, None when it's stored as is
+    compressed: Option<CompressedChunks>,
+    // Recently decoded frames, see decode_cached
+    frame_cache: FrameCache,
     pub file_path: Option<String>,
 }
 
@@ -51,6 +89,23 @@ impl VtfImage {
         self.header.mipmap_count
     }
 
+    pub fn depth(&self) -> u32 {
+        self.header.mipmap_depth(0)
+    }
+
+    pub fn is_volume(&self) -> bool {
+        self.header.is_volume()
+    }
+
+    // Faces per frame: 1, 6 for cubemaps, or 7 with the pre-7.5 spheremap
+    pub fn face_count(&self) -> u32 {
+        self.header.face_count()
+    }
+
+    pub fn is_envmap(&self) -> bool {
+        self.header.is_envmap()
+    }
+
     pub fn has_alpha(&self) -> bool {
         self.header.has_alpha()
     }
@@ -71,16 +126,17 @@ impl VtfImage {
             return Err(VtfError::InvalidData("No thumbnail present".into()));
         }
 
-        let data_size = self.header.low_res_format.compute_image_size(width, height) as usize;
-        let data_start = self.header.header_size as usize;
-        let data_end = data_start + data_size;
-
-        if data_end > self.raw_data.len() {
-            return Err(VtfError::InvalidData("Thumbnail data out of bounds".into()));
-        }
-
-        let raw_data = &self.raw_data[data_start..data_end];
-        let rgba_data = convert_to_rgba(raw_data, self.header.low_res_format, width, height)?;
+        let data_size = self.header.thumbnail_data_size() as usize;
+        let data_start = self
+            .header
+            .thumbnail_offset()
+            .ok_or_else(|| VtfError::InvalidData("No thumbnail present".into()))?
+            as usize;
+        let raw_data = self
+            .source
+            .read(data_start, data_size)
+            .map_err(|_| VtfError::InvalidData("Thumbnail data out of bounds".into()))?;
+        let rgba_data = convert_to_rgba(&raw_data, self.header.low_res_format, width, height)?;
 
         Ok(DecodedFrame {
             data: rgba_data,
@@ -92,6 +148,138 @@ impl VtfImage {
     }
 
     pub fn decode(&self, mipmap_level: u8, frame: u16) -> VtfResult<DecodedFrame> {
+        self.decode_slice(mipmap_level, frame, 0)
+    }
+
+    // Decode one depth slice of a volume texture. Depth halves with every mip.
+    pub fn decode_slice(
+        &self,
+        mipmap_level: u8,
+        frame: u16,
+        slice: u32,
+    ) -> VtfResult<DecodedFrame> {
+        self.decode_image(mipmap_level, frame, 0, slice)
+    }
+
+    // Decode one face of a cubemap
+    pub fn decode_face(
+        &self,
+        mipmap_level: u8,
+        frame: u16,
+        face: CubemapFace,
+    ) -> VtfResult<DecodedFrame> {
+        self.decode_image(mipmap_level, frame, face as u32, 0)
+    }
+
+    // Decode the six cube faces of a frame, in file order
+    pub fn decode_cube_faces(&self, mipmap_level: u8, frame: u16) -> VtfResult<Vec<DecodedFrame>> {
+        if self.face_count() < 6 {
+            return Err(VtfError::InvalidData("Texture is not a cubemap".into()));
+        }
+
+        CubemapFace::CUBE
+            .iter()
+            .map(|face| self.decode_face(mipmap_level, frame, *face))
+            .collect()
+    }
+
+    // Decode a frame of a cubemap laid out as a horizontal or vertical cross
+    pub fn decode_cross(
+        &self,
+        mipmap_level: u8,
+        frame: u16,
+        layout: CubemapLayout,
+    ) -> VtfResult<DecodedFrame> {
+        assemble_cross(&self.decode_cube_faces(mipmap_level, frame)?, layout)
+    }
+
+    // Save a cubemap frame as a cross, or as six images named <stem>_<suffix>.<ext>.
+    // Returns the written paths.
+    pub fn export_cubemap<P: AsRef<Path>>(
+        &self,
+        path: P,
+        mipmap_level: u8,
+        frame: u16,
+        layout: CubemapLayout,
+    ) -> VtfResult<Vec<PathBuf>> {
+        let path = path.as_ref();
+        let save = |decoded: &DecodedFrame, path: &Path| {
+            decoded
+                .save(path)
+                .map_err(|e| VtfError::InvalidData(format!("Failed to save image: {}", e)))
+        };
+
+        if layout != CubemapLayout::Separate {
+            save(&self.decode_cross(mipmap_level, frame, layout)?, path)?;
+            return Ok(vec![path.to_path_buf()]);
+        }
+
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "png".into());
+
+        let faces = self.decode_cube_faces(mipmap_level, frame)?;
+        let mut written = Vec::with_capacity(faces.len());
+        for (face, decoded) in CubemapFace::CUBE.iter().zip(&faces) {
+            let face_path =
+                path.with_file_name(format!("{}_{}.{}", stem, face.suffix(), extension));
+            save(decoded, &face_path)?;
+            written.push(face_path);
+        }
+        Ok(written)
+    }
+
+    // Decode to linear floats without clamping, for HDR formats
+    pub fn decode_hdr(&self, mipmap_level: u8, frame: u16) -> VtfResult<DecodedFrame<f32>> {
+        self.decode_slice_hdr(mipmap_level, frame, 0)
+    }
+
+    pub fn decode_slice_hdr(
+        &self,
+        mipmap_level: u8,
+        frame: u16,
+        slice: u32,
+    ) -> VtfResult<DecodedFrame<f32>> {
+        self.decode_image_as(mipmap_level, frame, 0, slice, convert_to_rgba_f32)
+    }
+
+    pub fn decode_face_hdr(
+        &self,
+        mipmap_level: u8,
+        frame: u16,
+        face: CubemapFace,
+    ) -> VtfResult<DecodedFrame<f32>> {
+        self.decode_image_as(mipmap_level, frame, face as u32, 0, convert_to_rgba_f32)
+    }
+
+    // Decode to 16 bits per channel, lossless for Rgba16161616
+    pub fn decode_rgba16(&self, mipmap_level: u8, frame: u16) -> VtfResult<DecodedFrame<u16>> {
+        self.decode_image_as(mipmap_level, frame, 0, 0, convert_to_rgba16)
+    }
+
+    fn decode_image(
+        &self,
+        mipmap_level: u8,
+        frame: u16,
+        face: u32,
+        slice: u32,
+    ) -> VtfResult<DecodedFrame> {
+        self.decode_image_as(mipmap_level, frame, face, slice, convert_to_rgba)
+    }
+
+    fn decode_image_as<T>(
+        &self,
+        mipmap_level: u8,
+        frame: u16,
+        face: u32,
+        slice: u32,
+        convert: PixelConverter<T>,
+    ) -> VtfResult<DecodedFrame<T>> {
         if mipmap_level >= self.header.mipmap_count {
             return Err(VtfError::InvalidMipmap(mipmap_level as u32));
         }
@@ -100,25 +288,22 @@ impl VtfImage {
             return Err(VtfError::InvalidFrame(frame));
         }
 
+        if face >= self.header.face_count() {
+            return Err(VtfError::InvalidFace(face));
+        }
+
+        if slice >= self.header.mipmap_depth(mipmap_level) {
+            return Err(VtfError::InvalidSlice(slice));
+        }
+
         let (width, height) = self.header.mipmap_size(mipmap_level);
         let data_size = self
             .header
             .high_res_format
             .compute_image_size(width, height) as usize;
-        let data_offset = self.calculate_data_offset(mipmap_level, frame);
-        let data_end = data_offset + data_size;
-
-        if data_end > self.raw_data.len() {
-            return Err(VtfError::InvalidData(format!(
-                "Image data out of bounds: offset {} + size {} > file size {}",
-                data_offset,
-                data_size,
-                self.raw_data.len()
-            )));
-        }
-
-        let raw_data = &self.raw_data[data_offset..data_end];
-        let rgba_data = convert_to_rgba(raw_data, self.header.high_res_format, width, height)?;
+        let data_offset = self.calculate_data_offset(mipmap_level, frame, face, slice);
+        let raw_data = self.read_image_data(data_offset, data_size)?;
+        let rgba_data = convert(&raw_data, self.header.high_res_format, width, height)?;
 
         Ok(DecodedFrame {
             data: rgba_data,
@@ -130,7 +315,13 @@ impl VtfImage {
     }
 
     pub fn decode_main(&self) -> VtfResult<DecodedFrame> {
-        self.decode(0, self.header.first_frame)
+        // Cubemaps without a spheremap mark the first frame as 0xFFFF
+        let first_frame = if self.header.first_frame < self.header.frames {
+            self.header.first_frame
+        } else {
+            0
+        };
+        self.decode(0, first_frame)
     }
 
     pub fn decode_all_frames(&self, mipmap_level: u8) -> VtfResult<Vec<DecodedFrame>> {
@@ -141,32 +332,224 @@ impl VtfImage {
         Ok(frames)
     }
 
-    fn calculate_data_offset(&self, mipmap_level: u8, frame: u16) -> usize {
-        let mut offset = self.header.header_size as usize;
+    fn calculate_data_offset(&self, mipmap_level: u8, frame: u16, face: u32, slice: u32) -> usize {
+        // Start of this frame, mips are stored smallest to largest
+        let mut offset = self.header.mipmap_offset(mipmap_level, frame) as usize;
+
+        // Then previous faces in this frame, then previous slices in this face
+        let slice_size = self.header.mipmap_data_size(mipmap_level) as usize;
+        let depth = self.header.mipmap_depth(mipmap_level) as usize;
+        offset += slice_size * face as usize * depth;
+        offset += slice_size * slice as usize;
+
+        offset
+    }
+
+    // Read high-res image data by its offset in the file. Only these bytes are read
+    // for mapped and streamed files. Compressed files read and inflate the one chunk
+    // holding them, offset is where they'd be if the file were uncompressed.
+    fn read_image_data(&self, offset: usize, size: usize) -> VtfResult<Cow<'_, [u8]>> {
+        let Some(compressed) = &self.compressed else {
+            if offset + size > self.source.len() {
+                return Err(VtfError::InvalidData(format!(
+                    "Image data out of bounds: offset {} + size {} > data size {}",
+                    offset,
+                    size,
+                    self.source.len()
+                )));
+            }
+            return self.source.read(offset, size);
+        };
 
-        // Add thumbnail size
-        offset += self.header.low_res_format.compute_image_size(
-            self.header.low_res_width as u32,
-            self.header.low_res_height as u32,
-        ) as usize;
+        let chunk = compressed.find(offset, size).ok_or_else(|| {
+            VtfError::InvalidData(format!(
+                "Image data out of bounds: offset {} + size {} isn't inside one compressed chunk",
+                offset, size
+            ))
+        })?;
+        let data = self
+            .source
+            .read(chunk.file_offset, chunk.compressed_size)
+            .map_err(|_| {
+                VtfError::DecompressionError("Compressed image data out of bounds".into())
+            })?;
+        let mut data = compressed.inflate(chunk, &data)?;
+        let start = offset - chunk.data_offset;
+        data.truncate(start + size);
+        data.drain(..start);
+        Ok(Cow::Owned(data))
+    }
 
-        // VTF stores mipmaps from smallest to largest
-        // Add all smaller mipmaps (all frames)
-        for mip in ((mipmap_level + 1)..self.header.mipmap_count).rev() {
-            let size = self.header.mipmap_data_size(mip) as usize;
-            offset += size * self.header.frames as usize * self.header.depth as usize;
+    // Decode a slice through the frame cache. Repeat calls for the same mip, frame
+    // and slice share one decoded frame until it's evicted.
+    pub fn decode_cached(
+        &self,
+        mipmap_level: u8,
+        frame: u16,
+        slice: u32,
+    ) -> VtfResult<Arc<DecodedFrame>> {
+        let key: FrameKey = (mipmap_level, frame, slice);
+        if let Some(decoded) = self.frame_cache.get(key) {
+            return Ok(decoded);
         }
 
-        // Add previous frames at this mipmap level
-        offset += self.header.mipmap_data_size(mipmap_level) as usize
-            * frame as usize
-            * self.header.depth as usize;
+        let decoded = Arc::new(self.decode_slice(mipmap_level, frame, slice)?);
+        self.frame_cache.insert(key, Arc::clone(&decoded));
+        Ok(decoded)
+    }
 
-        offset
+    // Bytes of decoded pixels the frame cache may hold, 0 turns it off
+    pub fn set_frame_cache_size(&self, bytes: usize) {
+        self.frame_cache.set_capacity(bytes);
+    }
+
+    // Decode part of a mip of the first face and slice. Only the rows (or block rows)
+    // covering the region are read and decoded.
+    pub fn decode_region(
+        &self,
+        mipmap_level: u8,
+        frame: u16,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> VtfResult<DecodedFrame> {
+        if mipmap_level >= self.header.mipmap_count {
+            return Err(VtfError::InvalidMipmap(mipmap_level as u32));
+        }
+
+        if frame >= self.header.frames {
+            return Err(VtfError::InvalidFrame(frame));
+        }
+
+        let (mip_width, mip_height) = self.header.mipmap_size(mipmap_level);
+        let outside = |start: u32, len: u32, size: u32| {
+            len == 0 || start.checked_add(len).is_none_or(|end| end > size)
+        };
+        if outside(x, width, mip_width) || outside(y, height, mip_height) {
+            return Err(VtfError::InvalidData(format!(
+                "Region {}x{} at {},{} is outside the {}x{} mip",
+                width, height, x, y, mip_width, mip_height
+            )));
+        }
+
+        // Compressed formats store rows of 4x4 blocks
+        let format = self.header.high_res_format;
+        let row_height = if format.is_compressed() { 4 } else { 1 };
+        let first_row = y / row_height * row_height;
+        let end_row = (y + height).div_ceil(row_height) * row_height;
+        let band_height = end_row.min(mip_height) - first_row;
+
+        let offset = self.calculate_data_offset(mipmap_level, frame, 0, 0)
+            + format.compute_image_size(mip_width, first_row) as usize;
+        let size = format.compute_image_size(mip_width, band_height) as usize;
+        let band = convert_to_rgba(
+            &self.read_image_data(offset, size)?,
+            format,
+            mip_width,
+            band_height,
+        )?;
+
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        for row in y - first_row..y - first_row + height {
+            let start = ((row * mip_width + x) * 4) as usize;
+            data.extend_from_slice(&band[start..start + (width * 4) as usize]);
+        }
+
+        Ok(DecodedFrame {
+            data,
+            width,
+            height,
+            mipmap_level,
+            frame,
+        })
+    }
+
+    // The largest mip that fits in max_size on both sides, or the smallest one, for
+    // previewing big textures before decoding the top mip
+    pub fn preview_mip(&self, max_size: u32) -> u8 {
+        (0..self.header.mipmap_count)
+            .find(|&mip| {
+                let (width, height) = self.header.mipmap_size(mip);
+                width.max(height) <= max_size
+            })
+            .unwrap_or(self.header.mipmap_count.saturating_sub(1))
+    }
+
+    // Size of the file the image was loaded from
+    pub fn file_size(&self) -> usize {
+        self.source.len()
+    }
+
+    // Read bytes of the file, for data the decode functions don't cover
+    pub fn read_bytes(&self, offset: usize, len: usize) -> VtfResult<Cow<'_, [u8]>> {
+        self.source.read(offset, len)
+    }
+
+    // Resource directory entries (empty for 7.2 and older)
+    pub fn resources(&self) -> &[VtfResourceEntry] {
+        &self.header.resources
+    }
+
+    // Get the data chunk of a non-image resource (the bytes after its size prefix)
+    pub fn resource_data(&self, tag: VtfResourceTag) -> Option<Cow<'_, [u8]>> {
+        if matches!(
+            tag,
+            VtfResourceTag::LowResImage | VtfResourceTag::HighResImage
+        ) {
+            return None;
+        }
+
+        let start = self.header.resource(tag)?.offset()? as usize;
+        let size_bytes = self.source.read(start, 4).ok()?;
+        let size = u32::from_le_bytes([size_bytes[0], size_bytes[1], size_bytes[2], size_bytes[3]]);
+        self.source.read(start + 4, size as usize).ok()
+    }
+
+    // CRC of the source image
+    pub fn crc(&self) -> Option<u32> {
+        self.header
+            .resource(VtfResourceTag::Crc)
+            .and_then(|entry| entry.inline_value())
+    }
+
+    // LOD clamp resolution as (u, v)
+    pub fn lod_control(&self) -> Option<(u8, u8)> {
+        let value = self
+            .header
+            .resource(VtfResourceTag::LodControl)?
+            .inline_value()?;
+        let bytes = value.to_le_bytes();
+        Some((bytes[0], bytes[1]))
+    }
+
+    // Extended texture settings flags (TSO)
+    pub fn extended_flags(&self) -> Option<u32> {
+        self.header
+            .resource(VtfResourceTag::ExtendedFlags)
+            .and_then(|entry| entry.inline_value())
+    }
+
+    // KeyValues text attached to the texture
+    pub fn key_values(&self) -> Option<String> {
+        self.resource_data(VtfResourceTag::KeyValues).map(|data| {
+            String::from_utf8_lossy(&data)
+                .trim_end_matches('\0')
+                .to_string()
+        })
     }
 
-    pub fn raw_data(&self) -> &[u8] {
-        &self.raw_data
+    // Raw particle sheet data
+    pub fn particle_sheet(&self) -> Option<Cow<'_, [u8]>> {
+        self.resource_data(VtfResourceTag::ParticleSheet)
+    }
+
+    // Method and level the image data is stored with, None when it's uncompressed
+    pub fn compression(&self) -> Option<(CompressionMethod, i16)> {
+        let info = AuxCompressionInfo::read(&self.resource_data(VtfResourceTag::AuxCompression)?);
+        info.ok()
+            .filter(|info| info.is_compressed())
+            .map(|info| (info.method, info.level))
     }
 }
 
@@ -185,6 +568,11 @@ impl VtfDecoder {
             return Err(VtfError::InvalidData("File too small".into()));
         }
 
+        // Console files are read through their PC equivalent
+        if is_vtfx(data) {
+            return Self::load_from_memory(&vtfx_to_vtf(data)?);
+        }
+
         let header = VtfHeader::read(data)?;
 
         let expected_size = header.header_size + header.total_data_size();
@@ -192,21 +580,100 @@ impl VtfDecoder {
             // Some VTFs are truncated but still loadable
         }
 
-        Ok(VtfImage {
+        Self::with_source(header, VtfSource::Memory(data.to_vec()))
+    }
+
+    // Map the file instead of reading it, decoding only touches the pages of the
+    // mips and frames asked for. Console files are converted into memory.
+    // Meant for short-lived images like thumbnails: reading a mapping after another
+    // process truncates the file kills the process with SIGBUS, and Windows won't
+    // let others save over a mapped file. Keep long-lived images on from_reader.
+    pub fn open_mapped<P: AsRef<Path>>(path: P) -> VtfResult<VtfImage> {
+        let file = fs::File::open(path.as_ref())?;
+        // SAFETY: the map is only read from, and callers only keep it for as long as
+        // a single decode, per the comment above
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < 16 {
+            return Err(VtfError::InvalidData("File too small".into()));
+        }
+
+        let mut image = if is_vtfx(&map) {
+            Self::load_from_memory(&vtfx_to_vtf(&map)?)?
+        } else {
+            let header = VtfHeader::read(&map)?;
+            Self::with_source(header, VtfSource::Mapped(Arc::new(map)))?
+        };
+        image.file_path = Some(path.as_ref().to_string_lossy().to_string());
+        Ok(image)
+    }
+
+    // Read only the header up front and seek to the rest as it's decoded
+    pub fn from_reader<R: Read + Seek + Send + 'static>(mut reader: R) -> VtfResult<VtfImage> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut header_data = vec![0u8; 16];
+        reader.read_exact(&mut header_data)?;
+
+        if is_vtfx(&header_data) {
+            reader.read_to_end(&mut header_data)?;
+            return Self::load_from_memory(&header_data);
+        }
+
+        let header = Self::read_header_rest(&mut reader, header_data)?;
+        Self::with_source(header, VtfSource::reader(reader)?)
+    }
+
+    fn with_source(header: VtfHeader, source: VtfSource) -> VtfResult<VtfImage> {
+        let mut image = VtfImage {
             header,
-            raw_data: data.to_vec(),
+            source,
+            compressed: None,
+            frame_cache: FrameCache::default(),
             file_path: None,
-        })
+        };
+        // 7.6 files may store the image data compressed. Only the chunk sizes are read
+        // here, decoding inflates the chunk it reads from and the frame cache keeps
+        // the result.
+        let info = match image.resource_data(VtfResourceTag::AuxCompression) {
+            Some(chunk) => Some(AuxCompressionInfo::read(&chunk)?),
+            None => None,
+        };
+        if let Some(info) = info.filter(|info| info.is_compressed()) {
+            image.compressed = Some(CompressedChunks::new(&image.header, &info)?);
+        }
+        Ok(image)
     }
 
     pub fn probe<P: AsRef<Path>>(path: P) -> VtfResult<VtfHeader> {
-        let mut file = fs::File::open(path)?;
-        let mut header_data = vec![0u8; 80];
-        std::io::Read::read(&mut file, &mut header_data)?;
+        let mut file = fs::File::open(path.as_ref())?;
+        let mut header_data = vec![0u8; 16];
+        file.read_exact(&mut header_data)?;
+
+        // The PC header of a console file only exists after converting all of it
+        if is_vtfx(&header_data) {
+            return Ok(Self::load_file(path)?.header);
+        }
+
+        Self::read_header_rest(&mut file, header_data)
+    }
+
+    // Read the header after its first 16 bytes. 7.3+ headers grow with the resource
+    // directory, so read all of it.
+    fn read_header_rest<R: Read>(reader: &mut R, mut header_data: Vec<u8>) -> VtfResult<VtfHeader> {
+        let header_size = u32::from_le_bytes([
+            header_data[12],
+            header_data[13],
+            header_data[14],
+            header_data[15],
+        ])
+        .max(80) as u64;
+        reader
+            .take(header_size - 16)
+            .read_to_end(&mut header_data)?;
         VtfHeader::read(&header_data)
     }
 }
 
+#[derive(Clone)]
 pub struct VtfBuilder {
     width: u32,
     height: u32,
@@ -216,12 +683,57 @@ pub struct VtfBuilder {
     clamp_s: bool,
     clamp_t: bool,
     no_lod: bool,
+    dxt_quality: DxtQuality,
+    dither: Dither,
+    version: VtfVersion,
+    // Extra 7.3+ resources, written in insertion order
+    resources: Vec<(VtfResourceTag, VtfResourceData)>,
+    // Volume texture depth. Each face holds this many slices back to back.
+    depth: u32,
+    // 6 for cubemaps. Each frame holds this many faces back to back.
+    faces: u32,
+    // Render the spheremap face for cubemaps written as 7.4 or older
+    spheremap: bool,
     // Support multiple frames for animated textures. Each entry is RGBA8 bytes for a single frame.
     frames: Vec<Vec<u8>>,
+    mip_filter: MipFilter,
+    // Color is sRGB encoded, filter mips in linear light
+    srgb: bool,
+    // Keep alpha test coverage at this $alphatestreference constant across mips
+    alpha_test_reference: Option<f32>,
+    // Flood color into fully transparent texels before mipping and compression
+    dilate: bool,
+    // Treat the frames as height maps and turn them into normal maps
+    height_to_normal: Option<HeightToNormal>,
+    // Convert the frames to a self-shadowing bump map
+    ssbump: Option<SsbumpSource>,
+    // Written on top of the flags derived from the settings above
+    extra_flags: VtfFlags,
+    // Hand-made RGBA8 images keyed by (frame, mip level), used instead of filtering
+    custom_mips: HashMap<(usize, u8), Vec<u8>>,
+    // Fit size and format to this game's spray limits at build time
+    spray: Option<SprayGame>,
+    // Pick the format from the content at build time, going uncompressed when DXT
+    // error is above this
+    auto_format: Option<f32>,
+    // Written as is instead of the reflectivity computed from the frames
+    reflectivity: Option<[f32; 3]>,
+    // Pre-compressed DXT1 thumbnail, used when it matches the low-res size
+    thumbnail: Option<Vec<u8>>,
+    // Store the image data compressed with this method and level (7.6 only)
+    compression: Option<(CompressionMethod, i16)>,
+    // Linear RGBA32F copies of the frames for HDR sources, empty otherwise.
+    // The 16-bit formats are encoded from these instead of the clamped RGBA8 frames.
+    hdr_frames: Vec<Vec<f32>>,
 }
 
 impl VtfBuilder {
     pub fn new(width: u32, height: u32, rgba_data: Vec<u8>) -> Self {
+        Self::with_frames(width, height, vec![rgba_data])
+    }
+
+    // Default settings shared by every constructor
+    fn with_frames(width: u32, height: u32, frames: Vec<Vec<u8>>) -> Self {
         Self {
             width,
             height,
@@ -231,8 +743,49 @@ impl VtfBuilder {
             clamp_s: false,
             clamp_t: false,
             no_lod: false,
-            frames: vec![rgba_data],
+            dxt_quality: DxtQuality::default(),
+            dither: Dither::default(),
+            version: VtfVersion::new(7, 2),
+            resources: Vec::new(),
+            depth: 1,
+            faces: 1,
+            spheremap: false,
+            mip_filter: MipFilter::default(),
+            srgb: true,
+            alpha_test_reference: None,
+            dilate: false,
+            height_to_normal: None,
+            ssbump: None,
+            extra_flags: VtfFlags::empty(),
+            custom_mips: HashMap::new(),
+            spray: None,
+            auto_format: None,
+            reflectivity: None,
+            thumbnail: None,
+            compression: None,
+            frames,
+            hdr_frames: Vec::new(),
+        }
+    }
+
+    // Frames already laid out as faces of slices, for rebuilding an existing texture.
+    // `hdr_frames` holds linear copies of the frames, or is empty.
+    pub(super) fn from_layout(
+        width: u32,
+        height: u32,
+        depth: u32,
+        faces: u32,
+        frames: Vec<Vec<u8>>,
+        hdr_frames: Vec<Vec<f32>>,
+    ) -> Self {
+        let mut builder = Self::with_frames(width, height, frames);
+        builder.depth = depth;
+        builder.faces = faces;
+        if !hdr_frames.is_empty() {
+            builder.format = VtfFormat::Rgba16161616F;
+            builder.hdr_frames = hdr_frames;
         }
+        builder
     }
 
     pub fn from_image_file<P: AsRef<Path>>(path: P) -> VtfResult<Self> {
@@ -279,17 +832,7 @@ impl VtfBuilder {
                     frames_data.push(rgba_buf.into_raw());
                 }
 
-                return Ok(Self {
-                    width,
-                    height,
-                    format: VtfFormat::Dxt5,
-                    generate_mipmaps: true,
-                    is_normal_map: false,
-                    clamp_s: false,
-                    clamp_t: false,
-                    no_lod: false,
-                    frames: frames_data,
-                });
+                return Ok(Self::with_frames(width, height, frames_data));
             }
         }
 
@@ -297,6 +840,26 @@ impl VtfBuilder {
         let img = image::open(path)
             .map_err(|e| VtfError::InvalidData(format!("Failed to load image: {}", e)))?;
 
+        // EXR/HDR and 16-bit images keep their precision
+        use image::ColorType;
+        let hdr_format = match img.color() {
+            ColorType::Rgb32F | ColorType::Rgba32F => Some(VtfFormat::Rgba16161616F),
+            ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+                Some(VtfFormat::Rgba16161616)
+            }
+            _ => None,
+        };
+        if let Some(format) = hdr_format {
+            let rgba = img.into_rgba32f();
+            let (width, height) = rgba.dimensions();
+            let mut rgba = rgba.into_raw();
+            // 16-bit images are sRGB encoded, the HDR formats hold linear light
+            if format == VtfFormat::Rgba16161616 {
+                linearize(&mut rgba);
+            }
+            return Ok(Self::from_hdr_frames(width, height, vec![rgba])?.format(format));
+        }
+
         let rgba = img.to_rgba8();
         let (width, height) = rgba.dimensions();
 
@@ -317,17 +880,104 @@ impl VtfBuilder {
             }
         }
 
-        Ok(Self {
-            width,
-            height,
-            format: VtfFormat::Dxt5,
-            generate_mipmaps: true,
-            is_normal_map: false,
-            clamp_s: false,
-            clamp_t: false,
-            no_lod: false,
-            frames,
-        })
+        Ok(Self::with_frames(width, height, frames))
+    }
+
+    /// Create an HDR texture from linear RGBA32F frames. Defaults to Rgba16161616F.
+    pub fn from_hdr_frames(width: u32, height: u32, frames: Vec<Vec<f32>>) -> VtfResult<Self> {
+        if frames.is_empty() {
+            return Err(VtfError::InvalidData("No frames provided".into()));
+        }
+
+        let expected_len = (width * height * 4) as usize;
+        if frames.iter().any(|frame| frame.len() != expected_len) {
+            return Err(VtfError::InvalidData("Frame size mismatch".into()));
+        }
+
+        // Clamped copies for the thumbnail and 8-bit formats
+        let clamped = frames
+            .iter()
+            .map(|frame| {
+                frame
+                    .iter()
+                    .map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+                    .collect()
+            })
+            .collect();
+
+        let mut builder = Self::with_frames(width, height, clamped);
+        builder.format = VtfFormat::Rgba16161616F;
+        builder.hdr_frames = frames;
+        Ok(builder)
+    }
+
+    /// Create a volume texture from a stack of RGBA slices, front to back.
+    pub fn from_slices(width: u32, height: u32, slices: Vec<Vec<u8>>) -> VtfResult<Self> {
+        if slices.is_empty() {
+            return Err(VtfError::InvalidData("No slices provided".into()));
+        }
+
+        let expected_len = (width * height * 4) as usize;
+        if slices.iter().any(|slice| slice.len() != expected_len) {
+            return Err(VtfError::InvalidData("Slice size mismatch".into()));
+        }
+
+        let depth = slices.len() as u32;
+        let mut builder = Self::with_frames(width, height, vec![slices.concat()]);
+        builder.depth = depth;
+        Ok(builder)
+    }
+
+    /// Create a cubemap from six square RGBA faces in file order (right, left, back, front, up, down).
+    pub fn from_cube_faces(size: u32, faces: Vec<Vec<u8>>) -> VtfResult<Self> {
+        if faces.len() != 6 {
+            return Err(VtfError::InvalidData(format!(
+                "A cubemap needs 6 faces, got {}",
+                faces.len()
+            )));
+        }
+
+        let expected_len = (size * size * 4) as usize;
+        if faces.iter().any(|face| face.len() != expected_len) {
+            return Err(VtfError::InvalidData("Face size mismatch".into()));
+        }
+
+        let mut builder = Self::with_frames(size, size, vec![faces.concat()]);
+        builder.faces = 6;
+        Ok(builder)
+    }
+
+    /// Create a cubemap from six image files in file order (right, left, back, front, up, down).
+    pub fn from_cube_face_files<P: AsRef<Path>>(paths: &[P]) -> VtfResult<Self> {
+        let mut faces = Vec::with_capacity(paths.len());
+        let mut size = None;
+        for path in paths {
+            let img = image::open(path)
+                .map_err(|e| VtfError::InvalidData(format!("Failed to load image: {}", e)))?
+                .to_rgba8();
+            if img.width() != img.height() || size.is_some_and(|size| size != img.width()) {
+                return Err(VtfError::InvalidData(
+                    "Cubemap faces must be square and the same size".into(),
+                ));
+            }
+            size = Some(img.width());
+            faces.push(img.into_raw());
+        }
+
+        Self::from_cube_faces(size.unwrap_or(0), faces)
+    }
+
+    /// Create a cubemap by projecting an equirectangular panorama onto faces of `face_size`.
+    pub fn from_equirectangular(
+        width: u32,
+        height: u32,
+        rgba_data: &[u8],
+        face_size: u32,
+    ) -> VtfResult<Self> {
+        let cube = equirect_to_cube(rgba_data, width, height, face_size)?;
+        let mut builder = Self::with_frames(face_size, face_size, vec![cube]);
+        builder.faces = 6;
+        Ok(builder)
     }
 
     pub fn format(mut self, format: VtfFormat) -> Self {
@@ -351,169 +1001,861 @@ impl VtfBuilder {
         self
     }
 
+    pub(super) fn clamp_axes(mut self, clamp_s: bool, clamp_t: bool) -> Self {
+        self.clamp_s = clamp_s;
+        self.clamp_t = clamp_t;
+        self
+    }
+
     pub fn no_lod(mut self, no_lod: bool) -> Self {
         self.no_lod = no_lod;
         self
     }
 
-    fn calculate_mipmap_count(width: u32, height: u32) -> u8 {
-        let max_dim = width.max(height);
-        (max_dim as f32).log2().floor() as u8 + 1
+    /// Speed/quality trade-off for DXT compressed formats
+    pub fn dxt_quality(mut self, quality: DxtQuality) -> Self {
+        self.dxt_quality = quality;
+        self
     }
 
-    pub fn build(self) -> VtfResult<Vec<u8>> {
-        let mipmap_count = if self.generate_mipmaps {
-            Self::calculate_mipmap_count(self.width, self.height)
-        } else {
-            1
-        };
+    /// Dithering used when writing formats with less than 8 bits per channel
+    pub fn dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
 
-        let format = VtfFormat::Bgra8888;
-        let header_size: u32 = 80;
-        let mut output = Vec::new();
+    /// Filter used to downsample each mip from the one above it
+    pub fn mip_filter(mut self, filter: MipFilter) -> Self {
+        self.mip_filter = filter;
+        self
+    }
 
-        output.extend_from_slice(b"VTF\0");
-        output.extend_from_slice(&7u32.to_le_bytes());
-        output.extend_from_slice(&2u32.to_le_bytes());
-        output.extend_from_slice(&header_size.to_le_bytes());
-        output.extend_from_slice(&(self.width as u16).to_le_bytes());
-        output.extend_from_slice(&(self.height as u16).to_le_bytes());
+    /// Whether color is sRGB encoded. sRGB mips are filtered in linear light so they
+    /// don't darken; normal maps and HDR sources are always filtered as-is.
+    pub fn srgb(mut self, srgb: bool) -> Self {
+        self.srgb = srgb;
+        self
+    }
 
-    let mut flags: u32 = 0;
-        if self.is_normal_map {
-            flags |= 0x00000080; // TEXTUREFLAGS_NORMAL
-        }
-        if self.clamp_s {
-            flags |= 0x00000004; // TEXTUREFLAGS_CLAMPS
-        }
-        if self.clamp_t {
-            flags |= 0x00000008; // TEXTUREFLAGS_CLAMPT
-        }
-        if self.no_lod {
-            flags |= 0x00000200; // TEXTUREFLAGS_NOLOD
-        }
-        flags |= 0x00002000;
-        output.extend_from_slice(&flags.to_le_bytes());
-    // Number of frames for animated textures
-    let frame_count_u16: u16 = self.frames.len() as u16;
-    output.extend_from_slice(&frame_count_u16.to_le_bytes());
-        output.extend_from_slice(&0u16.to_le_bytes());
-        output.extend_from_slice(&[0u8; 4]);
-        output.extend_from_slice(&0.5f32.to_le_bytes());
-        output.extend_from_slice(&0.5f32.to_le_bytes());
-        output.extend_from_slice(&0.5f32.to_le_bytes());
-        output.extend_from_slice(&[0u8; 4]);
-        output.extend_from_slice(&1.0f32.to_le_bytes());
-        output.extend_from_slice(&(format as i32).to_le_bytes());
-        output.push(mipmap_count);
-        output.extend_from_slice(&(VtfFormat::Dxt1 as i32).to_le_bytes());
-        output.push(4);
-        output.push(4);
-        output.extend_from_slice(&1u16.to_le_bytes());
-        output.extend_from_slice(&[0u8; 3]);
-        output.extend_from_slice(&0u32.to_le_bytes());
+    /// Scale alpha in every mip so the same share of texels passes the material's
+    /// `$alphatestreference` as in the top mip
+    pub fn alpha_test_reference(mut self, reference: f32) -> Self {
+        self.alpha_test_reference = Some(reference.clamp(0.0, 1.0));
+        self
+    }
 
-        while output.len() < header_size as usize {
-            output.push(0);
-        }
+    /// Fill fully transparent texels with the color of the nearest visible ones so
+    /// DXT blocks and smaller mips don't bleed a dark or junk fringe into the edges
+    pub fn dilate(mut self, dilate: bool) -> Self {
+        self.dilate = dilate;
+        self
+    }
 
-        let avg_color = self.calculate_average_color();
-        let thumb_data = self.create_dxt1_solid_block(avg_color);
-        output.extend_from_slice(&thumb_data);
+    /// Treat the source as a height map and write the tangent-space normal map
+    /// generated from it, flagged as a normal map
+    pub fn normal_from_height(mut self, settings: HeightToNormal) -> Self {
+        self.height_to_normal = Some(settings);
+        self.ssbump = None;
+        self.is_normal_map = true;
+        self
+    }
 
-        // For each mipmap level (smallest-to-largest), write image data for all frames.
-        for mip in (0..mipmap_count).rev() {
-            let mip_width = (self.width >> mip).max(1);
-            let mip_height = (self.height >> mip).max(1);
+    /// Treat the source as a tangent-space normal map and write it as a
+    /// self-shadowing bump map for `$ssbump` materials
+    pub fn ssbump_from_normal(mut self) -> Self {
+        self.ssbump = Some(SsbumpSource::Normal);
+        self.height_to_normal = None;
+        self.is_normal_map = false;
+        self
+    }
 
-            for frame in &self.frames {
-                let mip_data = if mip == 0 {
-                    let mut bgra = frame.clone();
-                    for i in (0..bgra.len()).step_by(4) {
-                        if i + 2 < bgra.len() {
-                            bgra.swap(i, i + 2);
-                        }
-                    }
-                    bgra
-                } else {
-                    let img = image::RgbaImage::from_raw(self.width, self.height, frame.clone())
-                        .ok_or(VtfError::InvalidData("Invalid image data".into()))?;
+    /// Treat the source as a height map and bake a self-shadowing bump map from it
+    pub fn ssbump_from_height(mut self, settings: HeightToSsbump) -> Self {
+        self.ssbump = Some(SsbumpSource::Height(settings));
+        self.height_to_normal = None;
+        self.is_normal_map = false;
+        self
+    }
 
-                    let resized = image::imageops::resize(
-                        &img,
-                        mip_width,
-                        mip_height,
-                        image::imageops::FilterType::Lanczos3,
-                    );
+    /// Extra header flags to write, e.g. to carry over the settings of an existing
+    /// texture. ENVMAP still follows whether the builder was given cube faces, and
+    /// the alpha flags follow the frames and format.
+    pub fn flags(mut self, flags: VtfFlags) -> Self {
+        self.extra_flags =
+            flags - VtfFlags::ENVMAP - VtfFlags::ONEBITALPHA - VtfFlags::EIGHTBITALPHA;
+        self
+    }
 
-                    let mut bgra = resized.into_raw();
-                    for i in (0..bgra.len()).step_by(4) {
-                        if i + 2 < bgra.len() {
-                            bgra.swap(i, i + 2);
-                        }
-                    }
-                    bgra
-                };
+    /// Choose the format from what the frames contain instead of `format`: DXT1,
+    /// DXT1 with 1-bit alpha or DXT5 by alpha use, I8 or IA88 for grayscale, and
+    /// an uncompressed format when DXT's RMS error (in 0-255 steps) is above
+    /// `max_error`. HDR sources keep their format.
+    pub fn auto_format(mut self, max_error: f32) -> Self {
+        self.auto_format = Some(max_error);
+        self
+    }
+
+    /// Make a player spray for a game. The largest power-of-two size and format
+    /// that fit its limits are picked at build time, replacing `format`. Animated
+    /// sprays lose their mipmaps, single frames keep them as a fade spray.
+    pub fn spray(mut self, game: SprayGame) -> Self {
+        self.spray = Some(game);
+        self
+    }
+
+    /// Write this reflectivity vector instead of the one computed from the frames.
+    /// VRAD bounces light off the surface with this linear color.
+    pub fn reflectivity(mut self, reflectivity: [f32; 3]) -> Self {
+        self.reflectivity = Some(reflectivity);
+        self
+    }
+
+    /// Use a hand-made RGBA8 image for one mip level of a frame instead of filtering
+    /// it from the level above, e.g. for fade sprays. Smaller levels that aren't
+    /// given are filtered from this one. Cubemaps take all six faces back to back;
+    /// volume textures don't support this.
+    pub fn mip_image(mut self, frame: usize, level: u8, rgba: Vec<u8>) -> VtfResult<Self> {
+        let mipmap_count = Self::calculate_mipmap_count(self.width, self.height);
+        // Level 0 is the frame itself
+        if level == 0 || level >= mipmap_count {
+            return Err(VtfError::InvalidMipmap(level as u32));
+        }
+        if frame >= self.frames.len() {
+            return Err(VtfError::InvalidFrame(frame as u16));
+        }
+
+        let (mip_width, mip_height) = ((self.width >> level).max(1), (self.height >> level).max(1));
+        if rgba.len() != (mip_width * mip_height * 4 * self.faces) as usize {
+            return Err(VtfError::InvalidData(format!(
+                "Mip {} must be {} images of {}x{}",
+                level, self.faces, mip_width, mip_height
+            )));
+        }
+
+        self.custom_mips.insert((frame, level), rgba);
+        Ok(self)
+    }
+
+    /// Load a mip level image from a file, see `mip_image`
+    pub fn mip_image_file<P: AsRef<Path>>(
+        self,
+        frame: usize,
+        level: u8,
+        path: P,
+    ) -> VtfResult<Self> {
+        let img = image::open(path)
+            .map_err(|e| VtfError::InvalidData(format!("Failed to load image: {}", e)))?;
+        self.mip_image(frame, level, img.to_rgba8().into_raw())
+    }
+
+    // Write this DXT1 data as the thumbnail instead of compressing a new one, as
+    // long as it is the size the builder picks
+    pub(super) fn thumbnail_data(mut self, dxt1: Vec<u8>) -> Self {
+        self.thumbnail = Some(dxt1);
+        self
+    }
+
+    /// Write the spheremap face for cubemaps. Only 7.4 and older store it.
+    pub fn spheremap(mut self, generate: bool) -> Self {
+        self.spheremap = generate;
+        self
+    }
+
+    /// File version to write. 7.3+ gets a resource directory.
+    pub fn version(mut self, version: VtfVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Compress the image data of each mip, frame and face with `method` (7.6 only).
+    /// Level -1 is the method's default and 0 stores the data uncompressed.
+    pub fn compression(mut self, method: CompressionMethod, level: i16) -> Self {
+        self.compression = Some((method, level));
+        self
+    }
+
+    /// Add a resource (7.3+ only), replacing any earlier one with the same tag.
+    /// The thumbnail and image data entries are managed by the builder.
+    pub fn resource(mut self, tag: VtfResourceTag, data: VtfResourceData) -> Self {
+        if matches!(
+            tag,
+            VtfResourceTag::LowResImage | VtfResourceTag::HighResImage
+        ) {
+            return self;
+        }
+
+        self.resources.retain(|(existing, _)| *existing != tag);
+        self.resources.push((tag, data));
+        self
+    }
+
+    /// CRC resource (the engine stores the source image CRC here)
+    pub fn crc(self, crc: u32) -> Self {
+        self.resource(VtfResourceTag::Crc, VtfResourceData::Inline(crc))
+    }
+
+    /// LOD control resource, clamps the resolution the engine will load
+    pub fn lod_control(self, clamp_u: u8, clamp_v: u8) -> Self {
+        let value = u32::from_le_bytes([clamp_u, clamp_v, 0, 0]);
+        self.resource(VtfResourceTag::LodControl, VtfResourceData::Inline(value))
+    }
+
+    /// Extended texture settings (TSO) resource
+    pub fn extended_flags(self, flags: u32) -> Self {
+        self.resource(
+            VtfResourceTag::ExtendedFlags,
+            VtfResourceData::Inline(flags),
+        )
+    }
+
+    /// KeyValues data resource
+    pub fn key_values(self, text: &str) -> Self {
+        self.resource(
+            VtfResourceTag::KeyValues,
+            VtfResourceData::Chunk(text.as_bytes().to_vec()),
+        )
+    }
+
+    fn calculate_mipmap_count(width: u32, height: u32) -> u8 {
+        let max_dim = width.max(height);
+        (max_dim as f32).log2().floor() as u8 + 1
+    }
+
+    pub fn build(mut self) -> VtfResult<Vec<u8>> {
+        if !self.version.is_supported() {
+            return Err(VtfError::UnsupportedVersion(
+                self.version.major,
+                self.version.minor,
+            ));
+        }
+
+        if let Some(game) = self.spray.take() {
+            return self.build_spray(game);
+        }
+
+        if !self.custom_mips.is_empty() {
+            if self.depth != 1 {
+                return Err(VtfError::InvalidData(
+                    "Custom mip images can't be used with volume textures".into(),
+                ));
+            }
+            if !self.generate_mipmaps {
+                return Err(VtfError::InvalidData(
+                    "Custom mip images need mipmaps enabled".into(),
+                ));
+            }
+        }
+
+        if self.compression.is_some() && self.version.minor < 6 {
+            return Err(VtfError::InvalidData(format!(
+                "Compressed image data needs VTF 7.6, building {}",
+                self.version
+            )));
+        }
+
+        let has_resource_directory = self.version.minor >= 3;
+        if !has_resource_directory && !self.resources.is_empty() {
+            return Err(VtfError::InvalidData(format!(
+                "Resources need VTF 7.3 or newer, building {}",
+                self.version
+            )));
+        }
+
+        if let Some(settings) = self.height_to_normal {
+            let (width, height) = (self.width, self.height);
+            let slice_len = (width * height * 4) as usize;
+            for frame in &mut self.frames {
+                *frame = frame
+                    .chunks_exact(slice_len)
+                    .flat_map(|slice| height_to_normal(slice, width, height, &settings))
+                    .collect();
+            }
+            // Normals are plain 8-bit data, drop any float copy of the heights
+            self.hdr_frames.clear();
+        }
+
+        if let Some(source) = self.ssbump {
+            let (width, height) = (self.width, self.height);
+            let slice_len = (width * height * 4) as usize;
+            for frame in &mut self.frames {
+                *frame = frame
+                    .chunks_exact(slice_len)
+                    .flat_map(|slice| match &source {
+                        SsbumpSource::Normal => normal_to_ssbump(slice),
+                        SsbumpSource::Height(settings) => {
+                            height_to_ssbump(slice, width, height, settings)
+                        }
+                    })
+                    .collect();
+            }
+            self.hdr_frames.clear();
+        }
+
+        if self.dilate {
+            self.dilate_frames();
+        }
+
+        if let Some(max_error) = self.auto_format
+            && self.hdr_frames.is_empty()
+        {
+            let slice_len = (self.width * self.height * 4) as usize;
+            let slices: Vec<&[u8]> = self
+                .frames
+                .iter()
+                .flat_map(|frame| frame.chunks_exact(slice_len))
+                .collect();
+            let (format, _) = recommend_format(
+                &slices,
+                self.width,
+                self.height,
+                max_error,
+                self.dxt_quality,
+            )?;
+            self.format = format;
+        }
+
+        // Before the spheremap is added, it isn't part of the texture's color
+        let reflectivity = match self.reflectivity {
+            Some(reflectivity) => reflectivity,
+            None => self.compute_reflectivity(),
+        };
+
+        // Cubemaps older than 7.5 carry a spheremap, or mark its absence with
+        // a first frame of 0xFFFF
+        let is_cubemap = self.faces == 6;
+        let mut first_frame = 0u16;
+        if is_cubemap && self.version.minor < 5 {
+            if self.spheremap {
+                let face_len = (self.width * self.height * 4) as usize;
+                for frame in &mut self.frames {
+                    let sphere = generate_spheremap(&frame[..6 * face_len], self.width);
+                    frame.extend(sphere);
+                }
+                self.faces = 7;
+            } else {
+                first_frame = 0xFFFF;
+            }
+        }
+
+        let mipmap_count = if self.generate_mipmaps {
+            // Volume textures keep mipping until the depth is 1 as well
+            Self::calculate_mipmap_count(self.width, self.height.max(self.depth))
+        } else {
+            1
+        };
 
-                output.extend(mip_data);
+        let format = self.format;
+        let (low_res_width, low_res_height) = Self::low_res_size(self.width, self.height);
+        let thumb_data = self.build_thumbnail(low_res_width, low_res_height)?;
+        let chunks = self.build_image_data(format, mipmap_count)?;
+        let image_data = match self.compression {
+            Some((method, level)) => {
+                let (data, info) = compress_image_data(&chunks, method, level)?;
+                self = self.resource(
+                    VtfResourceTag::AuxCompression,
+                    VtfResourceData::Chunk(info.to_bytes()),
+                );
+                data
+            }
+            None => chunks.concat(),
+        };
+
+        // Directory: thumbnail, image data, then whatever extra resources were added
+        let resource_count = if has_resource_directory {
+            2 + self.resources.len() as u32
+        } else {
+            0
+        };
+        let base_header_size: u32 = if self.version.minor >= 2 { 80 } else { 64 };
+        let header_size = align16(base_header_size + resource_count * 8);
+
+        let mut output = Vec::new();
+
+        output.extend_from_slice(b"VTF\0");
+        output.extend_from_slice(&self.version.major.to_le_bytes());
+        output.extend_from_slice(&self.version.minor.to_le_bytes());
+        output.extend_from_slice(&header_size.to_le_bytes());
+        output.extend_from_slice(&(self.width as u16).to_le_bytes());
+        output.extend_from_slice(&(self.height as u16).to_le_bytes());
+
+        let mut flags: u32 = 0;
+        if self.is_normal_map {
+            flags |= 0x00000080; // TEXTUREFLAGS_NORMAL
+        }
+        if self.clamp_s {
+            flags |= 0x00000004; // TEXTUREFLAGS_CLAMPS
+        }
+        if self.clamp_t {
+            flags |= 0x00000008; // TEXTUREFLAGS_CLAMPT
+        }
+        if self.no_lod {
+            flags |= 0x00000200; // TEXTUREFLAGS_NOLOD
+        }
+        if is_cubemap {
+            flags |= 0x00004000; // TEXTUREFLAGS_ENVMAP
+        }
+        if self.ssbump.is_some() {
+            flags |= 0x08000000; // TEXTUREFLAGS_SSBUMP
+        }
+        let alpha = self.frames.iter().map(|frame| alpha_content(frame)).max();
+        if let Some(alpha) = alpha {
+            flags |= alpha.flags(format).bits();
+        }
+        flags |= self.extra_flags.bits();
+        output.extend_from_slice(&flags.to_le_bytes());
+        // Number of frames for animated textures
+        let frame_count_u16: u16 = self.frames.len() as u16;
+        output.extend_from_slice(&frame_count_u16.to_le_bytes());
+        output.extend_from_slice(&first_frame.to_le_bytes());
+        output.extend_from_slice(&[0u8; 4]);
+        for value in reflectivity {
+            output.extend_from_slice(&value.to_le_bytes());
+        }
+        output.extend_from_slice(&[0u8; 4]);
+        output.extend_from_slice(&1.0f32.to_le_bytes());
+        output.extend_from_slice(&(format as i32).to_le_bytes());
+        output.push(mipmap_count);
+        output.extend_from_slice(&(VtfFormat::Dxt1 as i32).to_le_bytes());
+        output.push(low_res_width as u8);
+        output.push(low_res_height as u8);
+        if self.version.minor >= 2 {
+            output.extend_from_slice(&(self.depth as u16).to_le_bytes());
+        } else if self.depth > 1 {
+            return Err(VtfError::InvalidData(
+                "Volume textures need VTF 7.2 or newer".into(),
+            ));
+        }
+
+        if has_resource_directory {
+            output.extend_from_slice(&[0u8; 3]);
+            output.extend_from_slice(&resource_count.to_le_bytes());
+            output.extend_from_slice(&[0u8; 8]);
+
+            // Data follows the header in the same order as the directory
+            let thumb_offset = header_size;
+            let image_offset = thumb_offset + thumb_data.len() as u32;
+            let mut chunk_offset = image_offset + image_data.len() as u32;
+
+            write_resource_entry(&mut output, VtfResourceTag::LowResImage, 0, thumb_offset);
+            write_resource_entry(&mut output, VtfResourceTag::HighResImage, 0, image_offset);
+
+            for (tag, data) in &self.resources {
+                match data {
+                    VtfResourceData::Inline(value) => {
+                        write_resource_entry(
+                            &mut output,
+                            *tag,
+                            RESOURCE_FLAG_NO_DATA_CHUNK,
+                            *value,
+                        );
+                    }
+                    VtfResourceData::Chunk(bytes) => {
+                        write_resource_entry(&mut output, *tag, 0, chunk_offset);
+                        chunk_offset += 4 + bytes.len() as u32;
+                    }
+                }
+            }
+        }
+
+        while output.len() < header_size as usize {
+            output.push(0);
+        }
+
+        output.extend_from_slice(&thumb_data);
+        output.extend(image_data);
+
+        for (_, data) in &self.resources {
+            if let VtfResourceData::Chunk(bytes) = data {
+                output.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                output.extend_from_slice(bytes);
             }
         }
 
         Ok(output)
     }
 
-    fn calculate_average_color(&self) -> [u8; 4] {
-        let pixel_count = (self.width * self.height) as usize;
-        // Use first frame existence as validation
-        if pixel_count == 0 || self.frames.is_empty() || self.frames[0].len() < 4 {
-            return [128, 128, 128, 255];
+    // Try spray sizes and formats from the largest down until the file fits
+    fn build_spray(mut self, game: SprayGame) -> VtfResult<Vec<u8>> {
+        let limits = game.limits();
+        if self.faces != 1 || self.depth != 1 {
+            return Err(VtfError::InvalidData(
+                "Sprays can't be cubemaps or volume textures".into(),
+            ));
+        }
+        if self.frames.len() > limits.max_frames as usize {
+            return Err(VtfError::InvalidData(format!(
+                "{} sprays can have at most {} frames",
+                game.name(),
+                limits.max_frames
+            )));
+        }
+
+        if self.frames.len() > 1 && !limits.animated_mipmaps {
+            self.generate_mipmaps = false;
+        }
+        // Sprays are plain 8-bit decals that shouldn't tile or drop detail
+        self.hdr_frames.clear();
+        // The format comes from the game's spray formats below, the analyser could
+        // pick one the game rejects
+        self.auto_format = None;
+        self.clamp_s = true;
+        self.clamp_t = true;
+        self.no_lod = true;
+
+        // Hand-made mips only line up at the size they were made for
+        let sizes = if self.custom_mips.is_empty() {
+            candidate_sizes(self.width, self.height, &limits)
+        } else if self.width.is_power_of_two()
+            && self.height.is_power_of_two()
+            && self.width.max(self.height) <= limits.max_size
+        {
+            vec![(self.width, self.height)]
+        } else {
+            return Err(VtfError::InvalidData(format!(
+                "Sprays with custom mips must be a power of two up to {}",
+                limits.max_size
+            )));
+        };
+
+        let has_alpha = self
+            .frames
+            .iter()
+            .any(|frame| frame.chunks_exact(4).any(|pixel| pixel[3] < 255));
+        for (width, height) in sizes {
+            let mipmap_count = if self.generate_mipmaps {
+                Self::calculate_mipmap_count(width, height)
+            } else {
+                1
+            };
+            for format in SprayGame::formats(has_alpha) {
+                let frames = self.frames.len() as u32;
+                // Skip building what can't fit even before the header
+                if image_data_size(width, height, format, frames, mipmap_count)
+                    > limits.max_file_size
+                {
+                    continue;
+                }
+                let built = self.resized(width, height)?.format(format).build()?;
+                if built.len() as u32 <= limits.max_file_size {
+                    return Ok(built);
+                }
+            }
+        }
+
+        Err(VtfError::InvalidData(format!(
+            "Nothing fits in the {} KB {} spray limit",
+            limits.max_file_size / 1024,
+            game.name()
+        )))
+    }
+
+    // A copy of a 2D builder with every frame resized
+    fn resized(&self, width: u32, height: u32) -> VtfResult<Self> {
+        let mut resized = self.clone();
+        if (width, height) == (self.width, self.height) {
+            return Ok(resized);
         }
 
-        let mut r_sum: u64 = 0;
-        let mut g_sum: u64 = 0;
-        let mut b_sum: u64 = 0;
-        let mut a_sum: u64 = 0;
-        let mut count: u64 = 0;
+        for frame in &mut resized.frames {
+            let img = image::RgbaImage::from_raw(self.width, self.height, std::mem::take(frame))
+                .ok_or(VtfError::InvalidData("Invalid image data".into()))?;
+            *frame =
+                image::imageops::resize(&img, width, height, image::imageops::FilterType::Lanczos3)
+                    .into_raw();
+        }
+        resized.width = width;
+        resized.height = height;
+        Ok(resized)
+    }
 
-        for frame in &self.frames {
-            for i in (0..frame.len()).step_by(4) {
-                if i + 3 < frame.len() {
-                    r_sum += frame[i] as u64;
-                    g_sum += frame[i + 1] as u64;
-                    b_sum += frame[i + 2] as u64;
-                    a_sum += frame[i + 3] as u64;
-                    count += 1;
+    // Encode every mip level (smallest-to-largest) of every frame, face and slice
+    // Encoded image data, one chunk per mip, frame and face in file order
+    fn build_image_data(&self, format: VtfFormat, mipmap_count: u8) -> VtfResult<Vec<Vec<u8>>> {
+        let use_hdr = !self.hdr_frames.is_empty()
+            && matches!(
+                format,
+                VtfFormat::Rgba16161616F | VtfFormat::Rgba16161616 | VtfFormat::Bc6h
+            );
+
+        // Encoded mips per frame and face, largest first
+        let mut layers = Vec::with_capacity(self.frames.len() * self.faces as usize);
+        for frame in 0..self.frames.len() {
+            for face in 0..self.faces {
+                let chain = self.mip_chain(frame, face, mipmap_count);
+                let mut encoded = Vec::with_capacity(chain.len());
+                for (mip, slices) in chain.iter().enumerate() {
+                    let mip_width = (self.width >> mip).max(1);
+                    let mip_height = (self.height >> mip).max(1);
+                    let mut data = Vec::new();
+                    for rgba in slices {
+                        data.extend(self.encode_mip(rgba, format, mip_width, mip_height, use_hdr)?);
+                    }
+                    encoded.push(data);
                 }
+                layers.push(encoded);
             }
         }
 
-        if count == 0 {
-            return [128, 128, 128, 255];
+        let mut output = Vec::with_capacity(mipmap_count as usize * layers.len());
+        for mip in (0..mipmap_count as usize).rev() {
+            for layer in &mut layers {
+                output.push(std::mem::take(&mut layer[mip]));
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn encode_mip(
+        &self,
+        rgba: &[f32],
+        format: VtfFormat,
+        width: u32,
+        height: u32,
+        use_hdr: bool,
+    ) -> VtfResult<Vec<u8>> {
+        if use_hdr {
+            return convert_from_rgba_f32(rgba, format, width, height, self.dither);
+        }
+
+        let rgba: Vec<u8> = rgba
+            .iter()
+            .map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+        // BC6H and BC7 have no quality setting of their own
+        if format.is_compressed() && !matches!(format, VtfFormat::Bc6h | VtfFormat::Bc7) {
+            compress_dxt(&rgba, width, height, format, self.dxt_quality)
+        } else {
+            convert_from_rgba(&rgba, format, width, height, self.dither)
+        }
+    }
+
+    // One source slice as RGBA32F, straight from the HDR frames or scaled from RGBA8
+    fn source_slice(&self, frame: usize, index: usize) -> Vec<f32> {
+        let slice_len = (self.width * self.height * 4) as usize;
+        let range = index * slice_len..(index + 1) * slice_len;
+        match self.hdr_frames.get(frame) {
+            Some(hdr) => hdr[range].to_vec(),
+            None => self.frames[frame][range]
+                .iter()
+                .map(|&v| v as f32 / 255.0)
+                .collect(),
+        }
+    }
+
+    // Every mip of one face of a frame, largest first, each as a list of slices.
+    // Each mip is filtered from the one above it; sRGB color is filtered in linear light.
+    fn mip_chain(&self, frame: usize, face: u32, mipmap_count: u8) -> Vec<Vec<Vec<f32>>> {
+        let depth = self.depth as usize;
+        let top: Vec<Vec<f32>> = (0..depth)
+            .map(|slice| self.source_slice(frame, face as usize * depth + slice))
+            .collect();
+
+        let linear_light =
+            self.srgb && !self.is_normal_map && self.ssbump.is_none() && self.hdr_frames.is_empty();
+        // Sources are RGBA8 when filtering in linear light, so a table covers every value
+        let table: Vec<f32> = (0..256).map(|v| srgb_to_linear(v as f32 / 255.0)).collect();
+        let to_linear = |slice: &Vec<f32>| -> Vec<f32> {
+            if !linear_light {
+                return slice.clone();
+            }
+            slice
+                .chunks_exact(4)
+                .flat_map(|p| {
+                    let lookup = |v: f32| table[(v * 255.0).round() as usize];
+                    [lookup(p[0]), lookup(p[1]), lookup(p[2]), p[3]]
+                })
+                .collect()
+        };
+
+        // Alpha test coverage of the top mip, which every smaller mip should match
+        let coverage_target = self.alpha_test_reference.map(|reference| {
+            top.iter()
+                .map(|slice| coverage(slice, reference))
+                .sum::<f32>()
+                / depth as f32
+        });
+        // Cubemap faces and clamped textures don't tile
+        let wrap = (
+            !self.clamp_s && self.faces == 1,
+            !self.clamp_t && self.faces == 1,
+        );
+
+        let mut working: Vec<Vec<f32>> = top.iter().map(to_linear).collect();
+        let mut chain = vec![top];
+        let (mut width, mut height) = (self.width, self.height);
+
+        for mip in 1..mipmap_count {
+            let mip_width = (self.width >> mip).max(1);
+            let mip_height = (self.height >> mip).max(1);
+            let mip_depth = (self.depth >> mip).max(1) as usize;
+
+            // Hand-made levels replace the filtered ones and seed the levels below
+            // The spheremap face has no hand-made levels and is always filtered
+            let face_len = (mip_width * mip_height * 4) as usize;
+            let face_range = face as usize * face_len..(face as usize + 1) * face_len;
+            if let Some(custom) = self
+                .custom_mips
+                .get(&(frame, mip))
+                .and_then(|custom| custom.get(face_range))
+            {
+                let slice: Vec<f32> = custom.iter().map(|&v| v as f32 / 255.0).collect();
+                working = vec![to_linear(&slice)];
+                (width, height) = (mip_width, mip_height);
+                chain.push(vec![slice]);
+                continue;
+            }
+
+            // Volume textures halve in depth too, like VtfHeader::mipmap_depth. Slice i
+            // averages slices 2i and 2i + 1, the last one also takes in an odd slice
+            // left over at the back.
+            if mip_depth < working.len() {
+                working = (0..mip_depth)
+                    .map(|i| {
+                        let end = if i + 1 == mip_depth {
+                            working.len()
+                        } else {
+                            2 * i + 2
+                        };
+                        let sources = &working[2 * i..end];
+                        (0..sources[0].len())
+                            .map(|v| {
+                                let sum: f32 = sources.iter().map(|slice| slice[v]).sum();
+                                sum / sources.len() as f32
+                            })
+                            .collect()
+                    })
+                    .collect();
+            }
+
+            working = working
+                .iter()
+                .map(|slice| {
+                    resample(
+                        slice,
+                        width,
+                        height,
+                        mip_width,
+                        mip_height,
+                        self.mip_filter,
+                        wrap,
+                    )
+                })
+                .collect();
+            (width, height) = (mip_width, mip_height);
+
+            let slices = working
+                .iter()
+                .map(|slice| {
+                    let mut slice = slice.clone();
+                    if let (Some(reference), Some(target)) =
+                        (self.alpha_test_reference, coverage_target)
+                    {
+                        preserve_alpha_coverage(&mut slice, reference, target);
+                    }
+                    if self.is_normal_map {
+                        renormalize(&mut slice);
+                    }
+                    if linear_light {
+                        for pixel in slice.chunks_exact_mut(4) {
+                            for value in &mut pixel[..3] {
+                                *value = linear_to_srgb(value.clamp(0.0, 1.0));
+                            }
+                        }
+                    }
+                    slice
+                })
+                .collect();
+            chain.push(slices);
         }
 
-        [
-            (r_sum / count) as u8,
-            (g_sum / count) as u8,
-            (b_sum / count) as u8,
-            (a_sum / count) as u8,
-        ]
+        chain
     }
 
-    fn create_dxt1_solid_block(&self, color: [u8; 4]) -> [u8; 8] {
-        let r5 = (color[0] as u16 >> 3) & 0x1F;
-        let g6 = (color[1] as u16 >> 2) & 0x3F;
-        let b5 = (color[2] as u16 >> 3) & 0x1F;
-        let rgb565 = (r5 << 11) | (g6 << 5) | b5;
+    // Average linear color of the top mip of every frame, face and slice, like vtex.
+    // Color is weighted by alpha; normal and SSBUMP data is averaged as-is.
+    fn compute_reflectivity(&self) -> [f32; 3] {
+        let is_color = !self.is_normal_map && self.ssbump.is_none();
+        let slice_len = (self.width * self.height * 4) as usize;
+
+        let slices: Vec<Vec<f32>> = if !self.hdr_frames.is_empty() {
+            self.hdr_frames
+                .iter()
+                .flat_map(|frame| frame.chunks_exact(slice_len).map(<[f32]>::to_vec))
+                .collect()
+        } else {
+            self.frames
+                .iter()
+                .flat_map(|frame| frame.chunks_exact(slice_len))
+                .map(|slice| {
+                    let mut slice: Vec<f32> = slice.iter().map(|&v| v as f32 / 255.0).collect();
+                    if is_color && self.srgb {
+                        linearize(&mut slice);
+                    }
+                    slice
+                })
+                .collect()
+        };
+        average_reflectivity(slices.iter().map(Vec::as_slice), is_color)
+    }
 
-        let mut block = [0u8; 8];
-        block[0..2].copy_from_slice(&rgb565.to_le_bytes());
-        block[2..4].copy_from_slice(&rgb565.to_le_bytes());
-        block[4] = 0;
-        block[5] = 0;
-        block[6] = 0;
-        block[7] = 0;
+    // Run edge dilation over every slice of every face and frame
+    fn dilate_frames(&mut self) {
+        let (width, height) = (self.width, self.height);
+        let slice_len = (width * height * 4) as usize;
+
+        for frame in &mut self.frames {
+            for slice in frame.chunks_exact_mut(slice_len) {
+                let mut rgba: Vec<f32> = slice.iter().map(|&v| v as f32).collect();
+                dilate(&mut rgba, width, height);
+                for (byte, value) in slice.iter_mut().zip(&rgba) {
+                    *byte = value.round() as u8;
+                }
+            }
+        }
+        for frame in &mut self.hdr_frames {
+            for slice in frame.chunks_exact_mut(slice_len) {
+                dilate(slice, width, height);
+            }
+        }
+    }
 
-        block
+    // Largest size that fits in 16x16 while keeping the aspect ratio, like vtex does
+    fn low_res_size(width: u32, height: u32) -> (u32, u32) {
+        let (mut w, mut h) = (width.max(1), height.max(1));
+        while w > 16 || h > 16 {
+            w = (w / 2).max(1);
+            h = (h / 2).max(1);
+        }
+        (w, h)
+    }
+
+    // Downsample the top mip of the first frame into the DXT1 low-res image
+    fn build_thumbnail(&self, width: u32, height: u32) -> VtfResult<Vec<u8>> {
+        if let Some(thumbnail) = &self.thumbnail
+            && thumbnail.len() == VtfFormat::Dxt1.compute_image_size(width, height) as usize
+        {
+            return Ok(thumbnail.clone());
+        }
+
+        let frame = self
+            .frames
+            .first()
+            .ok_or(VtfError::InvalidData("No frames provided".into()))?;
+        // First slice only for volume textures
+        let slice = frame[..(self.width * self.height * 4) as usize].to_vec();
+        let img = image::RgbaImage::from_raw(self.width, self.height, slice)
+            .ok_or(VtfError::InvalidData("Invalid image data".into()))?;
+
+        // thumbnail() averages whole pixel areas, which is what we want at this ratio
+        let small = image::imageops::thumbnail(&img, width, height);
+        compress_dxt(
+            small.as_raw(),
+            width,
+            height,
+            VtfFormat::Dxt1,
+            self.dxt_quality,
+        )
     }
 
     pub fn save<P: AsRef<Path>>(self, path: P) -> VtfResult<()> {
@@ -523,9 +1865,26 @@ impl VtfBuilder {
     }
 }
 
+// Round up to the next multiple of 16
+pub(super) fn align16(value: u32) -> u32 {
+    (value + 15) & !15
+}
+
+pub(super) fn write_resource_entry(
+    output: &mut Vec<u8>,
+    tag: VtfResourceTag,
+    flags: u8,
+    data: u32,
+) {
+    output.extend_from_slice(&tag.to_bytes());
+    output.push(flags);
+    output.extend_from_slice(&data.to_le_bytes());
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vtf::{DEFAULT_MAX_ERROR, SprayViolation};
 
     #[test]
     fn test_mipmap_count() {
@@ -535,6 +1894,41 @@ mod tests {
         assert_eq!(VtfBuilder::calculate_mipmap_count(64, 128), 8);
     }
 
+    #[test]
+    fn test_low_res_size() {
+        assert_eq!(VtfBuilder::low_res_size(512, 512), (16, 16));
+        assert_eq!(VtfBuilder::low_res_size(512, 128), (16, 4));
+        assert_eq!(VtfBuilder::low_res_size(1024, 16), (16, 1));
+        assert_eq!(VtfBuilder::low_res_size(8, 4), (8, 4));
+    }
+
+    #[test]
+    fn test_build_thumbnail() {
+        // Left half red, right half blue
+        let (width, height) = (64, 32);
+        let mut data = Vec::new();
+        for _ in 0..height {
+            for x in 0..width {
+                if x < width / 2 {
+                    data.extend_from_slice(&[255, 0, 0, 255]);
+                } else {
+                    data.extend_from_slice(&[0, 0, 255, 255]);
+                }
+            }
+        }
+
+        let built = VtfBuilder::new(width, height, data).build().unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert_eq!(
+            (vtf.header.low_res_width, vtf.header.low_res_height),
+            (16, 8)
+        );
+
+        let thumb = vtf.decode_thumbnail().unwrap();
+        assert_eq!(&thumb.data[..4], &[255, 0, 0, 255]);
+        assert_eq!(&thumb.data[thumb.data.len() - 4..], &[0, 0, 255, 255]);
+    }
+
     #[test]
     fn test_build_animated_vtf() {
         // Create two simple 4x4 frames: red and green
@@ -563,4 +1957,790 @@ mod tests {
         assert_eq!(vtf.header.frames, 2);
         assert!(vtf.header.mipmap_count >= 1);
     }
+
+    #[test]
+    fn test_build_writes_requested_dxt_format() {
+        let data: Vec<u8> = [200u8, 100, 50, 255].repeat(16 * 16);
+        let built = VtfBuilder::new(16, 16, data)
+            .format(VtfFormat::Dxt1)
+            .build()
+            .unwrap();
+
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert_eq!(vtf.format(), VtfFormat::Dxt1);
+        assert_eq!(
+            built.len() as u32,
+            vtf.header.header_size + vtf.header.total_data_size()
+        );
+
+        for mip in 0..vtf.mipmap_count() {
+            let frame = vtf.decode(mip, 0).unwrap();
+            for pixel in frame.data.chunks(4) {
+                assert!((pixel[0] as i32 - 200).abs() <= 8);
+                assert!((pixel[1] as i32 - 100).abs() <= 8);
+                assert!((pixel[2] as i32 - 50).abs() <= 8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_writes_requested_uncompressed_format() {
+        let data: Vec<u8> = [10u8, 20, 30, 40].repeat(8 * 8);
+        for format in [VtfFormat::Bgr888, VtfFormat::Ia88, VtfFormat::Rgba16161616F] {
+            let built = VtfBuilder::new(8, 8, data.clone())
+                .format(format)
+                .mipmaps(false)
+                .build()
+                .unwrap();
+
+            let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+            assert_eq!(vtf.format(), format);
+            assert_eq!(
+                built.len() as u32,
+                vtf.header.header_size + vtf.header.total_data_size()
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_with_resources() {
+        let data: Vec<u8> = [1u8, 2, 3, 255].repeat(32 * 32);
+
+        for minor in [3, 4, 5] {
+            let built = VtfBuilder::new(32, 32, data.clone())
+                .format(VtfFormat::Rgba8888)
+                .version(VtfVersion::new(7, minor))
+                .crc(0x1234_5678)
+                .lod_control(8, 16)
+                .key_values("\"texture\" { \"a\" \"1\" }")
+                .build()
+                .unwrap();
+
+            let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+            assert_eq!(vtf.header.version, VtfVersion::new(7, minor));
+            assert_eq!(vtf.header.header_size % 16, 0);
+            assert_eq!(vtf.resources().len(), 5);
+            assert_eq!(vtf.crc(), Some(0x1234_5678));
+            assert_eq!(vtf.lod_control(), Some((8, 16)));
+            assert_eq!(
+                vtf.key_values().as_deref(),
+                Some("\"texture\" { \"a\" \"1\" }")
+            );
+
+            for mip in 0..vtf.mipmap_count() {
+                let frame = vtf.decode(mip, 0).unwrap();
+                assert_eq!(&frame.data[..4], &[1, 2, 3, 255]);
+            }
+            assert!(vtf.decode_thumbnail().is_ok());
+        }
+
+        // 7.2 has nowhere to put them
+        let result = VtfBuilder::new(32, 32, data).crc(1).build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_build_volume_texture() {
+        // 8x8x4 volume, each slice a different gray level
+        let slices: Vec<Vec<u8>> = (0..4u8)
+            .map(|z| [z * 60, z * 60, z * 60, 255].repeat(8 * 8))
+            .collect();
+
+        let built = VtfBuilder::from_slices(8, 8, slices)
+            .unwrap()
+            .format(VtfFormat::Rgba8888)
+            .srgb(false)
+            .build()
+            .unwrap();
+
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert!(vtf.is_volume());
+        assert_eq!(vtf.depth(), 4);
+        assert_eq!(vtf.mipmap_count(), 4);
+        assert_eq!(
+            built.len() as u32,
+            vtf.header.header_size + vtf.header.total_data_size()
+        );
+
+        for z in 0..4u32 {
+            let slice = vtf.decode_slice(0, 0, z).unwrap();
+            assert_eq!(slice.data[0], z as u8 * 60);
+        }
+
+        // Mip 1 is 4x4x2: slice 0 averages source slices 0 and 1
+        assert_eq!(vtf.header.mipmap_depth(1), 2);
+        assert_eq!(vtf.decode_slice(1, 0, 0).unwrap().data[0], 30);
+        assert_eq!(vtf.decode_slice(1, 0, 1).unwrap().data[0], 150);
+        assert!(vtf.decode_slice(1, 0, 2).is_err());
+    }
+
+    #[test]
+    fn test_build_odd_depth_volume() {
+        // 8x8x9 volume, mips are 4, 2 and 1 slices deep
+        let slices: Vec<Vec<u8>> = (0..9u8)
+            .map(|z| [z * 20, z * 20, z * 20, 255].repeat(8 * 8))
+            .collect();
+
+        let built = VtfBuilder::from_slices(8, 8, slices)
+            .unwrap()
+            .format(VtfFormat::Rgba8888)
+            .srgb(false)
+            .build()
+            .unwrap();
+
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert_eq!(
+            built.len() as u32,
+            vtf.header.header_size + vtf.header.total_data_size()
+        );
+        assert_eq!(vtf.decode_slice(0, 0, 8).unwrap().data[0], 160);
+
+        // Slice i of a mip averages slices 2i and 2i + 1 of the one above, and the
+        // last slice of mip 1 takes in slice 8 as well
+        let gray = |mip, slice| vtf.decode_slice(mip, 0, slice).unwrap().data[0] as i32;
+        assert!((gray(1, 0) - 10).abs() <= 1);
+        assert!((gray(1, 3) - 140).abs() <= 1);
+        assert!((gray(2, 1) - 115).abs() <= 1);
+        assert!((gray(3, 0) - 73).abs() <= 1);
+        assert!(vtf.decode_slice(1, 0, 4).is_err());
+    }
+
+    #[test]
+    fn test_build_mips_in_linear_light() {
+        // Black and white stripes average to 50% linear, which is 188 in sRGB
+        let stripes: Vec<u8> = (0..8 * 8)
+            .flat_map(|i| {
+                let v = if i % 2 == 0 { 0 } else { 255 };
+                [v, v, v, 255]
+            })
+            .collect();
+
+        for (srgb, expected) in [(true, 188), (false, 128)] {
+            let built = VtfBuilder::new(8, 8, stripes.clone())
+                .format(VtfFormat::Rgba8888)
+                .mip_filter(MipFilter::Box)
+                .srgb(srgb)
+                .build()
+                .unwrap();
+            let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+            let mip = vtf.decode(1, 0).unwrap();
+            assert!(mip.data.chunks(4).all(|p| p[0] == expected), "{}", srgb);
+        }
+    }
+
+    #[test]
+    fn test_build_mips_keep_alpha_test_coverage() {
+        // Sparse opaque texels: a plain filter fades them below the reference
+        let leaves: Vec<u8> = (0..16 * 16)
+            .flat_map(|i| [0, 255, 0, if i % 3 == 0 { 255 } else { 0 }])
+            .collect();
+
+        let build = |reference: Option<f32>| {
+            let mut builder = VtfBuilder::new(16, 16, leaves.clone()).format(VtfFormat::Rgba8888);
+            if let Some(reference) = reference {
+                builder = builder.alpha_test_reference(reference);
+            }
+            VtfDecoder::load_from_memory(&builder.build().unwrap()).unwrap()
+        };
+        let passing = |vtf: &VtfImage, mip: u8| {
+            let decoded = vtf.decode(mip, 0).unwrap();
+            let count = decoded.data.chunks(4).filter(|p| p[3] > 127).count();
+            count as f32 / (decoded.width * decoded.height) as f32
+        };
+
+        let plain = build(None);
+        let kept = build(Some(0.5));
+        let top = passing(&kept, 0);
+        assert!(passing(&plain, 2) < top / 2.0);
+        assert!((passing(&kept, 2) - top).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_build_dilates_transparent_edges() {
+        // Opaque red left half, transparent black right half
+        let rgba: Vec<u8> = (0..8 * 8)
+            .flat_map(|i| if i % 8 < 4 { [255, 0, 0, 255] } else { [0; 4] })
+            .collect();
+
+        for (dilate, expected) in [(true, 255), (false, 0)] {
+            let built = VtfBuilder::new(8, 8, rgba.clone())
+                .format(VtfFormat::Rgba8888)
+                .dilate(dilate)
+                .build()
+                .unwrap();
+            let top = VtfDecoder::load_from_memory(&built)
+                .unwrap()
+                .decode(0, 0)
+                .unwrap();
+            assert_eq!(top.data[7 * 4], expected);
+            // Alpha stays transparent
+            assert_eq!(top.data[7 * 4 + 3], 0);
+        }
+    }
+
+    #[test]
+    fn test_build_normal_from_height() {
+        let bump: Vec<u8> = (0..8 * 8)
+            .flat_map(|i| {
+                let v = if i % 8 == 4 { 255 } else { 0 };
+                [v, v, v, 255]
+            })
+            .collect();
+
+        let settings = HeightToNormal {
+            height_in_alpha: true,
+            ..Default::default()
+        };
+        let built = VtfBuilder::new(8, 8, bump)
+            .format(VtfFormat::Rgba8888)
+            .normal_from_height(settings)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert!(vtf.header.is_normal_map());
+
+        let top = vtf.decode(0, 0).unwrap();
+        // Left of the ridge leans left, right of it leans right, height kept in alpha
+        assert!(top.data[3 * 4] < 128);
+        assert!(top.data[5 * 4] > 128);
+        assert_eq!(top.data[4 * 4 + 3], 255);
+        assert_eq!(top.data[3], 0);
+    }
+
+    #[test]
+    fn test_build_custom_mips() {
+        let blue = [0u8, 0, 255, 255].repeat(8 * 8);
+        let red = [255u8, 0, 0, 255].repeat(4 * 4);
+        let green = [0u8, 255, 0, 255].repeat(4 * 4);
+
+        let built = VtfBuilder::from_frames(8, 8, vec![blue.clone(), blue.clone()])
+            .unwrap()
+            .format(VtfFormat::Rgba8888)
+            .mip_image(0, 1, red)
+            .unwrap()
+            .mip_image(1, 1, green)
+            .unwrap()
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+
+        assert_eq!(&vtf.decode(0, 0).unwrap().data[..4], &[0, 0, 255, 255]);
+        assert_eq!(&vtf.decode(1, 0).unwrap().data[..4], &[255, 0, 0, 255]);
+        assert_eq!(&vtf.decode(1, 1).unwrap().data[..4], &[0, 255, 0, 255]);
+        // Levels below a custom one are filtered from it
+        assert_eq!(&vtf.decode(3, 0).unwrap().data[..4], &[255, 0, 0, 255]);
+
+        // Sizes and levels are checked against the mip chain
+        let builder = || VtfBuilder::new(8, 8, blue.clone());
+        assert!(builder().mip_image(0, 1, vec![0; 8 * 8 * 4]).is_err());
+        assert!(builder().mip_image(0, 0, vec![0; 8 * 8 * 4]).is_err());
+        assert!(builder().mip_image(0, 4, vec![0; 4]).is_err());
+        assert!(builder().mip_image(1, 3, vec![0; 4]).is_err());
+        assert!(builder().mip_image(0, 3, vec![0; 4]).is_ok());
+    }
+
+    #[test]
+    fn test_build_auto_format_and_alpha_flags() {
+        let gray: Vec<u8> = (0..8 * 8)
+            .flat_map(|i| [i as u8, i as u8, i as u8, 255])
+            .collect();
+        let built = VtfBuilder::new(8, 8, gray)
+            .auto_format(DEFAULT_MAX_ERROR)
+            .build()
+            .unwrap();
+        let header = VtfDecoder::load_from_memory(&built).unwrap().header;
+        assert_eq!(header.high_res_format, VtfFormat::I8);
+        assert!(
+            !header
+                .flags
+                .intersects(VtfFlags::ONEBITALPHA | VtfFlags::EIGHTBITALPHA)
+        );
+
+        let cutout: Vec<u8> = (0..8 * 8)
+            .flat_map(|i| {
+                if i % 2 == 0 {
+                    [200, 30, 30, 255]
+                } else {
+                    [0, 0, 0, 0]
+                }
+            })
+            .collect();
+        let built = VtfBuilder::new(8, 8, cutout.clone())
+            .auto_format(DEFAULT_MAX_ERROR)
+            .build()
+            .unwrap();
+        let header = VtfDecoder::load_from_memory(&built).unwrap().header;
+        assert_eq!(header.high_res_format, VtfFormat::Dxt1OneBitAlpha);
+        assert!(header.flags.contains(VtfFlags::ONEBITALPHA));
+
+        // The alpha flags follow the content even when the format is forced
+        let built = VtfBuilder::new(8, 8, cutout).build().unwrap();
+        let flags = VtfDecoder::load_from_memory(&built).unwrap().header.flags;
+        assert!(flags.contains(VtfFlags::ONEBITALPHA) && !flags.contains(VtfFlags::EIGHTBITALPHA));
+    }
+
+    #[test]
+    fn test_build_reflectivity() {
+        // Half red, half transparent blue: only the red counts
+        let rgba = [[255u8, 0, 0, 255], [0, 0, 255, 0]].concat().repeat(8);
+        let built = VtfBuilder::new(4, 4, rgba.clone())
+            .format(VtfFormat::Rgba8888)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert_eq!(vtf.header.reflectivity, [1.0, 0.0, 0.0]);
+        assert_eq!(vtf.compute_reflectivity().unwrap(), [1.0, 0.0, 0.0]);
+
+        let gray = [128u8, 128, 128, 255].repeat(16);
+        let built = VtfBuilder::new(4, 4, gray)
+            .format(VtfFormat::Rgba8888)
+            .build()
+            .unwrap();
+        let reflectivity = VtfDecoder::load_from_memory(&built)
+            .unwrap()
+            .header
+            .reflectivity;
+        // sRGB 128 is about 0.216 in linear light
+        assert!(reflectivity.iter().all(|v| (v - 0.216).abs() < 0.001));
+
+        let built = VtfBuilder::new(4, 4, rgba)
+            .reflectivity([0.1, 0.2, 0.3])
+            .build()
+            .unwrap();
+        let mut header = VtfDecoder::load_from_memory(&built).unwrap().header;
+        assert_eq!(header.reflectivity, [0.1, 0.2, 0.3]);
+        header.recompute_reflectivity(&built).unwrap();
+        assert!((header.reflectivity[0] - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_build_spray_fits_limits() {
+        let game = SprayGame::CounterStrikeSource;
+        let translucent = [200u8, 40, 40, 128].repeat(512 * 512);
+        let built = VtfBuilder::new(512, 512, translucent.clone())
+            .format(VtfFormat::Rgba8888)
+            .spray(game)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert_eq!((vtf.width(), vtf.height()), (256, 256));
+        assert_eq!(vtf.format(), VtfFormat::Dxt5);
+        assert!(vtf.mipmap_count() > 1);
+        assert!(vtf.spray_violations(game).is_empty());
+
+        // Animated sprays drop their mips and fall back to a smaller format
+        let frames = vec![translucent[..256 * 256 * 4].to_vec(); 3];
+        let built = VtfBuilder::from_frames(256, 256, frames)
+            .unwrap()
+            .spray(game)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert_eq!(vtf.mipmap_count(), 1);
+        assert_eq!(vtf.format(), VtfFormat::Dxt1);
+        assert!(vtf.spray_violations(game).is_empty());
+
+        // Auto format would pick I8 for grayscale, which no game takes as a spray
+        let gray: Vec<u8> = (0..64 * 64)
+            .flat_map(|i| {
+                let v = (i % 64 * 4) as u8;
+                [v, v, v, 255]
+            })
+            .collect();
+        let game = SprayGame::TeamFortress2;
+        let built = VtfBuilder::new(64, 64, gray)
+            .auto_format(DEFAULT_MAX_ERROR)
+            .spray(game)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert!(SprayGame::formats(false).contains(&vtf.format()));
+        assert!(vtf.spray_violations(game).is_empty());
+    }
+
+    #[test]
+    fn test_spray_violations() {
+        let built = VtfBuilder::from_frames(300, 200, vec![vec![255; 300 * 200 * 4]; 2])
+            .unwrap()
+            .format(VtfFormat::Rgba8888)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        let violations = vtf.spray_violations(SprayGame::CounterStrikeSource);
+        assert!(violations.contains(&SprayViolation::NotPowerOfTwo {
+            width: 300,
+            height: 200
+        }));
+        assert!(violations.contains(&SprayViolation::TooLarge {
+            width: 300,
+            height: 200,
+            max: 256
+        }));
+        assert!(violations.contains(&SprayViolation::Format(VtfFormat::Rgba8888)));
+        assert!(violations.contains(&SprayViolation::AnimatedMipmaps));
+        assert!(
+            violations
+                .iter()
+                .any(|v| matches!(v, SprayViolation::FileTooLarge { .. }))
+        );
+    }
+
+    #[test]
+    fn test_build_ssbump() {
+        let flat = [128u8, 128, 255, 255].repeat(8 * 8);
+        let built = VtfBuilder::new(8, 8, flat)
+            .format(VtfFormat::Rgba8888)
+            .ssbump_from_normal()
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert!(vtf.header.is_ssbump());
+        assert!(!vtf.header.is_normal_map());
+
+        // Mips are filtered as data, not as sRGB color
+        let mip = vtf.decode(2, 0).unwrap();
+        let expected = normal_to_ssbump(&[128, 128, 255, 255]);
+        assert!(mip.data.chunks(4).all(|p| p == expected));
+        let normal = mip.ssbump_to_normal();
+        assert_eq!(&normal.data[..4], &[128, 128, 255, 255]);
+    }
+
+    #[test]
+    fn test_build_hdr_texture() {
+        // Highlights above 1.0 survive the round trip through Rgba16161616F
+        let pixels: Vec<f32> = (0..8 * 8)
+            .flat_map(|i| [i as f32 * 0.25, 1.0, 0.5, 1.0])
+            .collect();
+        let built = VtfBuilder::from_hdr_frames(8, 8, vec![pixels.clone()])
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert_eq!(vtf.format(), VtfFormat::Rgba16161616F);
+        assert!(vtf.header.is_hdr());
+        let decoded = vtf.decode_hdr(0, 0).unwrap();
+        assert_eq!(decoded.data, pixels);
+        assert!(decoded.data.iter().any(|&v| v > 1.0));
+
+        // The 8-bit path still clamps
+        assert_eq!(vtf.decode(0, 0).unwrap().data[63 * 4], 255);
+
+        let built = VtfBuilder::from_hdr_frames(8, 8, vec![pixels])
+            .unwrap()
+            .format(VtfFormat::Rgba16161616)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        let decoded = vtf.decode_rgba16(0, 0).unwrap();
+        assert_eq!(&decoded.data[4..8], &[16384, 65535, 32768, 65535]);
+    }
+
+    #[test]
+    fn test_import_16_bit_png_as_srgb() {
+        // Black columns next to a gray ramp, so filtering in gamma space shows
+        let gray = |x: u32, y: u32| if x.is_multiple_of(2) { 0 } else { y as u8 * 17 };
+        let narrow = image::GrayImage::from_fn(16, 16, |x, y| image::Luma([gray(x, y)]));
+        let wide = image::ImageBuffer::<image::Luma<u16>, _>::from_fn(16, 16, |x, y| {
+            image::Luma([gray(x, y) as u16 * 257])
+        });
+        let dir = std::env::temp_dir();
+        let (narrow_path, wide_path) = (
+            dir.join("vfilex_test_8.png"),
+            dir.join("vfilex_test_16.png"),
+        );
+        narrow.save(&narrow_path).unwrap();
+        wide.save(&wide_path).unwrap();
+
+        let load = |path: &Path, format| {
+            let built = VtfBuilder::from_image_file(path)
+                .unwrap()
+                .format(format)
+                .build()
+                .unwrap();
+            VtfDecoder::load_from_memory(&built).unwrap()
+        };
+        let narrow = load(&narrow_path, VtfFormat::Rgba8888);
+        let wide = load(&wide_path, VtfFormat::Rgba16161616);
+        fs::remove_file(&narrow_path).ok();
+        fs::remove_file(&wide_path).ok();
+
+        for reflectivity in [
+            wide.header.reflectivity,
+            wide.compute_reflectivity().unwrap(),
+        ] {
+            for (a, b) in narrow.header.reflectivity.iter().zip(reflectivity) {
+                assert!((a - b).abs() < 0.001, "{} vs {}", a, b);
+            }
+        }
+
+        // Rgba16161616 holds linear light, which the 8-bit mip holds sRGB encoded
+        let expected = narrow.decode(1, 0).unwrap().data;
+        let mip = wide.decode_hdr(1, 0).unwrap().data;
+        for (index, (&a, &b)) in expected.iter().zip(&mip).enumerate() {
+            let b = if index % 4 == 3 { b } else { linear_to_srgb(b) };
+            assert!(a.abs_diff((b * 255.0).round() as u8) <= 1, "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_build_cubemap() {
+        let faces: Vec<Vec<u8>> = (0..6u8)
+            .map(|face| [face * 40, 0, 0, 255].repeat(8 * 8))
+            .collect();
+
+        // 7.2 with a spheremap stores 7 faces per frame
+        let built = VtfBuilder::from_cube_faces(8, faces.clone())
+            .unwrap()
+            .format(VtfFormat::Rgba8888)
+            .spheremap(true)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert!(vtf.is_envmap());
+        assert_eq!(vtf.face_count(), 7);
+        assert_eq!(
+            built.len() as u32,
+            vtf.header.header_size + vtf.header.total_data_size()
+        );
+        for face in CubemapFace::CUBE {
+            let decoded = vtf.decode_face(1, 0, face).unwrap();
+            assert_eq!(decoded.data[0], face as u8 * 40);
+        }
+        let sphere = vtf.decode_face(0, 0, CubemapFace::SphereMap).unwrap();
+        assert_eq!(sphere.data[(4 * 8 + 4) * 4], CubemapFace::Up as u8 * 40);
+
+        // Without one the first frame is 0xFFFF and only 6 faces are stored
+        let built = VtfBuilder::from_cube_faces(8, faces.clone())
+            .unwrap()
+            .format(VtfFormat::Rgba8888)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert_eq!(vtf.header.first_frame, 0xFFFF);
+        assert_eq!(vtf.face_count(), 6);
+        assert!(vtf.decode_face(0, 0, CubemapFace::SphereMap).is_err());
+        assert_eq!(vtf.decode_main().unwrap().data[0], 0);
+
+        // 7.5 never has a spheremap
+        let built = VtfBuilder::from_cube_faces(8, faces)
+            .unwrap()
+            .format(VtfFormat::Rgba8888)
+            .version(VtfVersion::new(7, 5))
+            .spheremap(true)
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&built).unwrap();
+        assert_eq!(vtf.face_count(), 6);
+        let cross = vtf
+            .decode_cross(0, 0, CubemapLayout::HorizontalCross)
+            .unwrap();
+        assert_eq!((cross.width, cross.height), (32, 24));
+        assert!(VtfBuilder::from_cube_faces(8, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_resource_directory_layout() {
+        // 7.4 file with the thumbnail stored *after* the image data
+        let header_size: u32 = 80 + 4 * 8;
+        let image_offset = header_size;
+        let thumb_offset = image_offset + 2 * 2 * 4;
+        let kvd_offset = thumb_offset + 1;
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"VTF\0");
+        data.extend_from_slice(&7u32.to_le_bytes());
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(&header_size.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&[0u8; 4]);
+        data.extend_from_slice(&[0u8; 12]);
+        data.extend_from_slice(&[0u8; 4]);
+        data.extend_from_slice(&1.0f32.to_le_bytes());
+        data.extend_from_slice(&(VtfFormat::Rgba8888 as i32).to_le_bytes());
+        data.push(1);
+        data.extend_from_slice(&(VtfFormat::I8 as i32).to_le_bytes());
+        data.push(1);
+        data.push(1);
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&[0u8; 3]);
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(&[0u8; 8]);
+
+        data.extend_from_slice(b"\x30\0\0\0");
+        data.extend_from_slice(&image_offset.to_le_bytes());
+        data.extend_from_slice(b"\x01\0\0\0");
+        data.extend_from_slice(&thumb_offset.to_le_bytes());
+        data.extend_from_slice(b"CRC\x02");
+        data.extend_from_slice(&0xDEADBEEFu32.to_le_bytes());
+        data.extend_from_slice(b"KVD\0");
+        data.extend_from_slice(&kvd_offset.to_le_bytes());
+
+        for _ in 0..4 {
+            data.extend_from_slice(&[10, 20, 30, 255]);
+        }
+        data.push(77);
+        data.extend_from_slice(&5u32.to_le_bytes());
+        data.extend_from_slice(b"a b\n\0");
+
+        let vtf = VtfDecoder::load_from_memory(&data).unwrap();
+        assert_eq!(vtf.resources().len(), 4);
+
+        let frame = vtf.decode(0, 0).unwrap();
+        assert_eq!(&frame.data[..4], &[10, 20, 30, 255]);
+
+        let thumb = vtf.decode_thumbnail().unwrap();
+        assert_eq!(thumb.data, vec![77, 77, 77, 255]);
+
+        assert_eq!(vtf.crc(), Some(0xDEADBEEF));
+        assert_eq!(vtf.key_values().as_deref(), Some("a b\n"));
+        assert_eq!(vtf.lod_control(), None);
+    }
+
+    #[test]
+    fn test_compressed_image_data() {
+        let rgba: Vec<u8> = (0..32 * 32)
+            .flat_map(|i| [(i % 32 * 8) as u8, 40, 90, 255])
+            .collect();
+        let build = |version: VtfVersion, compression: Option<CompressionMethod>| {
+            let builder = VtfBuilder::from_frames(32, 32, vec![rgba.clone(), rgba.clone()])
+                .unwrap()
+                .format(VtfFormat::Rgba8888)
+                .version(version)
+                .key_values("\"Information\" { }");
+            match compression {
+                Some(method) => builder.compression(method, 6).build(),
+                None => builder.build(),
+            }
+        };
+
+        let plain = build(VtfVersion::new(7, 6), None).unwrap();
+        let plain = VtfDecoder::load_from_memory(&plain).unwrap();
+        assert_eq!(plain.compression(), None);
+
+        for method in [CompressionMethod::Deflate, CompressionMethod::Zstd] {
+            let data = build(VtfVersion::new(7, 6), Some(method)).unwrap();
+            assert!(data.len() < plain.file_size());
+
+            let vtf = VtfDecoder::load_from_memory(&data).unwrap();
+            assert_eq!(vtf.compression(), Some((method, 6)));
+            assert_eq!(vtf.key_values(), plain.key_values());
+            for mip in 0..vtf.mipmap_count() {
+                assert_eq!(
+                    vtf.decode(mip, 1).unwrap().data,
+                    plain.decode(mip, 1).unwrap().data
+                );
+            }
+
+            let streamed = VtfDecoder::from_reader(std::io::Cursor::new(data.clone())).unwrap();
+            assert_eq!(
+                streamed.decode(0, 0).unwrap().data,
+                plain.decode(0, 0).unwrap().data
+            );
+
+            // Chunks are only inflated when decoded. A broken stream is reported by
+            // the decode reading it, not decoded as garbage.
+            let mut corrupt = data.clone();
+            let offset = vtf.header.image_data_offset() as usize;
+            corrupt[offset..offset + 8].fill(0xAA);
+            let corrupt = VtfDecoder::load_from_memory(&corrupt).unwrap();
+            let smallest = vtf.mipmap_count() - 1;
+            assert!(matches!(
+                corrupt.decode(smallest, 0),
+                Err(VtfError::DecompressionError(_))
+            ));
+            assert_eq!(
+                corrupt.decode(smallest, 1).unwrap().data,
+                plain.decode(smallest, 1).unwrap().data
+            );
+        }
+
+        assert!(build(VtfVersion::new(7, 5), Some(CompressionMethod::Zstd)).is_err());
+    }
+
+    #[test]
+    fn test_lazy_sources() {
+        let rgba: Vec<u8> = (0..64 * 64)
+            .flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 90, 255])
+            .collect();
+        let data = VtfBuilder::from_frames(64, 64, vec![rgba.clone(), rgba])
+            .unwrap()
+            .format(VtfFormat::Dxt1)
+            .version(VtfVersion::new(7, 5))
+            .key_values("\"Information\" { }")
+            .build()
+            .unwrap();
+        let path = std::env::temp_dir().join("vfilex_test_lazy_source.vtf");
+        fs::write(&path, &data).unwrap();
+
+        let memory = VtfDecoder::load_from_memory(&data).unwrap();
+        let mapped = VtfDecoder::open_mapped(&path).unwrap();
+        let streamed = VtfDecoder::from_reader(std::io::Cursor::new(data.clone())).unwrap();
+        for vtf in [&mapped, &streamed] {
+            assert_eq!(vtf.file_size(), data.len());
+            assert_eq!(vtf.key_values(), memory.key_values());
+            assert_eq!(
+                vtf.decode_thumbnail().unwrap().data,
+                memory.decode_thumbnail().unwrap().data
+            );
+            for mip in 0..vtf.mipmap_count() {
+                assert_eq!(
+                    vtf.decode(mip, 1).unwrap().data,
+                    memory.decode(mip, 1).unwrap().data
+                );
+            }
+        }
+        drop(mapped);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_decode_region() {
+        let rgba: Vec<u8> = (0..32 * 32)
+            .flat_map(|i| [(i % 32 * 8) as u8, (i / 32 * 8) as u8, (i * 3) as u8, 255])
+            .collect();
+        for format in [VtfFormat::Rgba8888, VtfFormat::Dxt5] {
+            let data = VtfBuilder::new(32, 32, rgba.clone())
+                .format(format)
+                .build()
+                .unwrap();
+            let vtf = VtfDecoder::load_from_memory(&data).unwrap();
+            let full = vtf.decode(0, 0).unwrap();
+
+            // Not aligned to the 4x4 blocks
+            let region = vtf.decode_region(0, 0, 5, 7, 10, 13).unwrap();
+            assert_eq!((region.width, region.height), (10, 13));
+            for row in 0..13 {
+                let start = ((row + 7) * 32 + 5) * 4;
+                let expected = &full.data[start..start + 40];
+                assert_eq!(&region.data[row * 40..row * 40 + 40], expected);
+            }
+
+            assert!(vtf.decode_region(0, 0, 30, 0, 4, 4).is_err());
+            assert!(vtf.decode_region(0, 0, 4, 0, u32::MAX, 1).is_err());
+            assert!(vtf.decode_region(0, 0, 0, u32::MAX, 1, 2).is_err());
+            assert_eq!(vtf.preview_mip(32), 0);
+            assert_eq!(vtf.preview_mip(10), 2);
+            assert_eq!(vtf.preview_mip(0), vtf.mipmap_count() - 1);
+        }
+    }
+
+    #[test]
+    fn test_decode_cached() {
+        let data = VtfBuilder::new(16, 16, vec![128; 16 * 16 * 4])
+            .build()
+            .unwrap();
+        let vtf = VtfDecoder::load_from_memory(&data).unwrap();
+
+        let first = vtf.decode_cached(0, 0, 0).unwrap();
+        assert!(Arc::ptr_eq(&first, &vtf.decode_cached(0, 0, 0).unwrap()));
+        assert_eq!(first.data, vtf.decode(0, 0).unwrap().data);
+
+        vtf.set_frame_cache_size(0);
+        assert!(!Arc::ptr_eq(&first, &vtf.decode_cached(0, 0, 0).unwrap()));
+    }
 }
@@ -0,0 +1,94 @@
+//! Edge padding: flood visible color into fully transparent texels
+
+// The 8 neighbours of a texel that fall inside the image
+fn neighbours(index: usize, width: usize, height: usize) -> impl Iterator<Item = usize> {
+    let (x, y) = ((index % width) as i64, (index / width) as i64);
+    (-1i64..=1)
+        .flat_map(move |dy| (-1i64..=1).map(move |dx| (x + dx, y + dy)))
+        .filter(move |&(nx, ny)| {
+            (nx, ny) != (x, y) && nx >= 0 && ny >= 0 && nx < width as i64 && ny < height as i64
+        })
+        .map(move |(nx, ny)| ny as usize * width + nx as usize)
+}
+
+// Give every texel with zero alpha the average color of its nearest visible texels,
+// one ring at a time outwards from the visible edges. Alpha is left alone, so this
+// only changes what DXT blocks and smaller mips pull in from transparent areas.
+pub(super) fn dilate(rgba: &mut [f32], width: u32, height: u32) {
+    let (width, height) = (width as usize, height as usize);
+    let mut filled: Vec<bool> = rgba.chunks_exact(4).map(|pixel| pixel[3] > 0.0).collect();
+    if filled.iter().all(|&f| f) || !filled.iter().any(|&f| f) {
+        return;
+    }
+
+    let mut queued = filled.clone();
+    let mut ring: Vec<usize> = (0..width * height)
+        .filter(|&i| !filled[i] && neighbours(i, width, height).any(|n| filled[n]))
+        .collect();
+    for &i in &ring {
+        queued[i] = true;
+    }
+
+    while !ring.is_empty() {
+        // Colors come only from texels filled before this ring
+        let colors: Vec<[f32; 3]> = ring
+            .iter()
+            .map(|&i| {
+                let mut sum = [0.0f32; 3];
+                let mut count = 0.0;
+                for n in neighbours(i, width, height).filter(|&n| filled[n]) {
+                    for (total, value) in sum.iter_mut().zip(&rgba[n * 4..n * 4 + 3]) {
+                        *total += value;
+                    }
+                    count += 1.0;
+                }
+                sum.map(|total| total / count)
+            })
+            .collect();
+
+        for (&i, color) in ring.iter().zip(&colors) {
+            rgba[i * 4..i * 4 + 3].copy_from_slice(color);
+            filled[i] = true;
+        }
+
+        let mut next = Vec::new();
+        for &i in &ring {
+            for n in neighbours(i, width, height) {
+                if !queued[n] {
+                    queued[n] = true;
+                    next.push(n);
+                }
+            }
+        }
+        ring = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dilate_fills_transparent_texels() {
+        // 5x1: red on the left, blue on the right, junk black in between
+        let mut rgba = vec![0.0f32; 5 * 4];
+        rgba[..4].copy_from_slice(&[1.0, 0.0, 0.0, 1.0]);
+        rgba[16..].copy_from_slice(&[0.0, 0.0, 1.0, 0.5]);
+        dilate(&mut rgba, 5, 1);
+
+        assert_eq!(&rgba[4..8], &[1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(&rgba[12..16], &[0.0, 0.0, 1.0, 0.0]);
+        // The middle texel is reached from both sides in the same ring
+        assert_eq!(&rgba[8..12], &[0.5, 0.0, 0.5, 0.0]);
+        // Visible texels are untouched
+        assert_eq!(&rgba[16..], &[0.0, 0.0, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn test_dilate_without_visible_texels() {
+        let mut rgba = [0.25f32, 0.5, 0.75, 0.0].repeat(4);
+        let original = rgba.clone();
+        dilate(&mut rgba, 2, 2);
+        assert_eq!(rgba, original);
+    }
+}
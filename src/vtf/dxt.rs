@@ -0,0 +1,678 @@
+//! DXT (S3TC) and 3Dc block compression
+//! The write side of the DXT and ATI1N/ATI2N decoders in formats.rs. Palettes are built with the exact same
+//! math as the decoder so what we measure is what the engine shows.
+
+use super::formats::{decode_565, interpolate_color};
+use super::header::VtfFormat;
+use super::{VtfError, VtfResult};
+
+// 4x4 block of RGBA pixels, row-major
+type Block = [[u8; 4]; 16];
+
+// Compression quality presets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DxtQuality {
+    // Bounding box endpoints, no refinement. Good enough for previews.
+    Fast,
+    // Principal axis endpoints with one least-squares pass
+    #[default]
+    Normal,
+    // Principal axis and bounding box candidates, iterated least squares
+    High,
+}
+
+impl DxtQuality {
+    // number of least-squares endpoint refinement passes
+    fn refine_passes(&self) -> usize {
+        match self {
+            DxtQuality::Fast => 0,
+            DxtQuality::Normal => 1,
+            DxtQuality::High => 8,
+        }
+    }
+}
+
+// Compress RGBA8 pixels into DXT1/DXT1A/DXT3/DXT5 blocks, or ATI1N (red) and
+// ATI2N (red and green) blocks
+pub fn compress_dxt(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    format: VtfFormat,
+    quality: DxtQuality,
+) -> VtfResult<Vec<u8>> {
+    if !matches!(
+        format,
+        VtfFormat::Dxt1
+            | VtfFormat::Dxt1OneBitAlpha
+            | VtfFormat::Dxt3
+            | VtfFormat::Dxt5
+            | VtfFormat::Ati1n
+            | VtfFormat::Ati2n
+    ) {
+        return Err(VtfError::InvalidData(format!(
+            "Not a DXT format: {:?}",
+            format
+        )));
+    }
+
+    if rgba.len() < (width * height * 4) as usize {
+        return Err(VtfError::InvalidData(format!(
+            "Expected {} bytes of RGBA data for {}x{}, got {}",
+            width * height * 4,
+            width,
+            height,
+            rgba.len()
+        )));
+    }
+
+    let block_width = width.div_ceil(4);
+    let block_height = height.div_ceil(4);
+    let mut output = Vec::with_capacity(format.compute_image_size(width, height) as usize);
+
+    for by in 0..block_height {
+        for bx in 0..block_width {
+            let block = extract_block(rgba, width, height, bx, by);
+
+            match format {
+                VtfFormat::Dxt1 => {
+                    output.extend_from_slice(&encode_color_block(&block, false, quality));
+                }
+                VtfFormat::Dxt1OneBitAlpha => {
+                    output.extend_from_slice(&encode_color_block(&block, true, quality));
+                }
+                VtfFormat::Dxt3 => {
+                    output.extend_from_slice(&encode_explicit_alpha(&block));
+                    output.extend_from_slice(&encode_color_block(&block, false, quality));
+                }
+                VtfFormat::Ati1n => {
+                    output.extend_from_slice(&encode_channel(&block, 0, quality));
+                }
+                VtfFormat::Ati2n => {
+                    output.extend_from_slice(&encode_channel(&block, 0, quality));
+                    output.extend_from_slice(&encode_channel(&block, 1, quality));
+                }
+                _ => {
+                    output.extend_from_slice(&encode_interpolated_alpha(&block, quality));
+                    output.extend_from_slice(&encode_color_block(&block, false, quality));
+                }
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+// Copy a 4x4 block out of the image, clamping to the edge for partial blocks
+fn extract_block(rgba: &[u8], width: u32, height: u32, bx: u32, by: u32) -> Block {
+    let mut block = [[0u8; 4]; 16];
+
+    for (i, pixel) in block.iter_mut().enumerate() {
+        let x = (bx * 4 + i as u32 % 4).min(width - 1);
+        let y = (by * 4 + i as u32 / 4).min(height - 1);
+        let index = ((y * width + x) * 4) as usize;
+        pixel.copy_from_slice(&rgba[index..index + 4]);
+    }
+
+    block
+}
+
+// Quantize a floating point color to RGB565
+fn quantize_565(color: [f32; 3]) -> u16 {
+    let r = (color[0].clamp(0.0, 255.0) * 31.0 / 255.0).round() as u16;
+    let g = (color[1].clamp(0.0, 255.0) * 63.0 / 255.0).round() as u16;
+    let b = (color[2].clamp(0.0, 255.0) * 31.0 / 255.0).round() as u16;
+    (r << 11) | (g << 5) | b
+}
+
+// Build the palette exactly like the decoder does
+fn color_palette(c0: u16, c1: u16, three_color: bool) -> [[u8; 4]; 4] {
+    let color0 = decode_565(c0);
+    let color1 = decode_565(c1);
+
+    if three_color {
+        [
+            color0,
+            color1,
+            interpolate_color(&color0, &color1, 1, 2),
+            [0, 0, 0, 0],
+        ]
+    } else {
+        [
+            color0,
+            color1,
+            interpolate_color(&color0, &color1, 1, 3),
+            interpolate_color(&color0, &color1, 2, 3),
+        ]
+    }
+}
+
+fn color_distance(a: &[u8; 4], b: &[u8; 4]) -> u32 {
+    let dr = a[0] as i32 - b[0] as i32;
+    let dg = a[1] as i32 - b[1] as i32;
+    let db = a[2] as i32 - b[2] as i32;
+    (dr * dr + dg * dg + db * db) as u32
+}
+
+// A candidate color encoding for one block
+#[derive(Clone, Copy)]
+struct ColorFit {
+    c0: u16,
+    c1: u16,
+    indices: [u8; 16],
+    error: u32,
+}
+
+// Pick the nearest palette entry for every pixel
+fn fit_indices(
+    block: &Block,
+    transparent: &[bool; 16],
+    c0: u16,
+    c1: u16,
+    three_color: bool,
+) -> ColorFit {
+    let palette = color_palette(c0, c1, three_color);
+    let usable = if three_color { 3 } else { 4 };
+    let mut indices = [0u8; 16];
+    let mut error = 0;
+
+    for (i, pixel) in block.iter().enumerate() {
+        if transparent[i] {
+            indices[i] = 3;
+            continue;
+        }
+
+        let (best, distance) = palette[..usable]
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (index, color_distance(pixel, entry)))
+            .min_by_key(|&(_, distance)| distance)
+            .unwrap_or((0, 0));
+
+        indices[i] = best as u8;
+        error += distance;
+    }
+
+    ColorFit {
+        c0,
+        c1,
+        indices,
+        error,
+    }
+}
+
+// Solve for the endpoints that best reproduce the pixels with the current indices
+fn least_squares_endpoints(
+    block: &Block,
+    transparent: &[bool; 16],
+    indices: &[u8; 16],
+    three_color: bool,
+) -> Option<([f32; 3], [f32; 3])> {
+    // How much of endpoint 0 each palette index contains
+    let weights: [f32; 4] = if three_color {
+        [1.0, 0.0, 0.5, 0.0]
+    } else {
+        [1.0, 0.0, 2.0 / 3.0, 1.0 / 3.0]
+    };
+
+    let mut aa = 0.0f32;
+    let mut ab = 0.0f32;
+    let mut bb = 0.0f32;
+    let mut ax = [0.0f32; 3];
+    let mut bx = [0.0f32; 3];
+
+    for (i, pixel) in block.iter().enumerate() {
+        if transparent[i] {
+            continue;
+        }
+
+        let alpha = weights[indices[i] as usize];
+        let beta = 1.0 - alpha;
+        aa += alpha * alpha;
+        ab += alpha * beta;
+        bb += beta * beta;
+
+        for c in 0..3 {
+            ax[c] += alpha * pixel[c] as f32;
+            bx[c] += beta * pixel[c] as f32;
+        }
+    }
+
+    let det = aa * bb - ab * ab;
+    if det.abs() < 1e-6 {
+        return None;
+    }
+
+    let mut start = [0.0f32; 3];
+    let mut end = [0.0f32; 3];
+    for c in 0..3 {
+        start[c] = (ax[c] * bb - bx[c] * ab) / det;
+        end[c] = (bx[c] * aa - ax[c] * ab) / det;
+    }
+
+    Some((start, end))
+}
+
+// Dominant direction of the color distribution (power iteration on the covariance)
+fn principal_axis(points: &[[f32; 3]], mean: [f32; 3]) -> [f32; 3] {
+    let mut cov = [[0.0f32; 3]; 3];
+    for point in points {
+        let d = [point[0] - mean[0], point[1] - mean[1], point[2] - mean[2]];
+        for (row, dr) in cov.iter_mut().zip(d) {
+            for (cell, dc) in row.iter_mut().zip(d) {
+                *cell += dr * dc;
+            }
+        }
+    }
+
+    let mut axis = [1.0f32, 1.0, 1.0];
+    for _ in 0..8 {
+        let next = [
+            cov[0][0] * axis[0] + cov[0][1] * axis[1] + cov[0][2] * axis[2],
+            cov[1][0] * axis[0] + cov[1][1] * axis[1] + cov[1][2] * axis[2],
+            cov[2][0] * axis[0] + cov[2][1] * axis[1] + cov[2][2] * axis[2],
+        ];
+        let length = next.iter().map(|v| v.abs()).fold(0.0f32, f32::max);
+        if length < 1e-6 {
+            return [0.0, 0.0, 0.0];
+        }
+        axis = [next[0] / length, next[1] / length, next[2] / length];
+    }
+
+    axis
+}
+
+// Starting endpoint pairs for the given quality
+fn initial_endpoints(points: &[[f32; 3]], quality: DxtQuality) -> Vec<([f32; 3], [f32; 3])> {
+    let mut min = [255.0f32; 3];
+    let mut max = [0.0f32; 3];
+    let mut mean = [0.0f32; 3];
+    for point in points {
+        for c in 0..3 {
+            min[c] = min[c].min(point[c]);
+            max[c] = max[c].max(point[c]);
+            mean[c] += point[c] / points.len() as f32;
+        }
+    }
+
+    // Inset the box a little, the extremes are rarely worth a palette entry
+    let mut inset_min = min;
+    let mut inset_max = max;
+    for c in 0..3 {
+        let inset = (max[c] - min[c]) / 16.0;
+        inset_min[c] += inset;
+        inset_max[c] -= inset;
+    }
+    let bounding_box = (inset_max, inset_min);
+
+    if quality == DxtQuality::Fast {
+        return vec![bounding_box];
+    }
+
+    let axis = principal_axis(points, mean);
+    let project = |p: &[f32; 3]| {
+        (p[0] - mean[0]) * axis[0] + (p[1] - mean[1]) * axis[1] + (p[2] - mean[2]) * axis[2]
+    };
+
+    let mut low = points[0];
+    let mut high = points[0];
+    for point in points {
+        if project(point) < project(&low) {
+            low = *point;
+        }
+        if project(point) > project(&high) {
+            high = *point;
+        }
+    }
+
+    let mut candidates = vec![(high, low)];
+    if quality == DxtQuality::High {
+        candidates.push(bounding_box);
+        candidates.push((max, min));
+    }
+    candidates
+}
+
+// Encode the 8-byte color part of a block
+fn encode_color_block(block: &Block, one_bit_alpha: bool, quality: DxtQuality) -> [u8; 8] {
+    let mut transparent = [false; 16];
+    if one_bit_alpha {
+        for (flag, pixel) in transparent.iter_mut().zip(block) {
+            *flag = pixel[3] < 128;
+        }
+    }
+
+    let points: Vec<[f32; 3]> = block
+        .iter()
+        .zip(transparent)
+        .filter(|(_, transparent)| !transparent)
+        .map(|(pixel, _)| [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32])
+        .collect();
+
+    // Nothing visible: 3-color mode with every pixel on the transparent index
+    if points.is_empty() {
+        return pack_color_block(0, 0, &[3; 16]);
+    }
+
+    // Transparent texels are only representable in 3-color mode
+    let three_color = transparent.contains(&true);
+
+    let mut best: Option<ColorFit> = None;
+    for (start, end) in initial_endpoints(&points, quality) {
+        let mut fit = fit_indices(
+            block,
+            &transparent,
+            quantize_565(start),
+            quantize_565(end),
+            three_color,
+        );
+
+        for _ in 0..quality.refine_passes() {
+            let Some((start, end)) =
+                least_squares_endpoints(block, &transparent, &fit.indices, three_color)
+            else {
+                break;
+            };
+
+            let refined = fit_indices(
+                block,
+                &transparent,
+                quantize_565(start),
+                quantize_565(end),
+                three_color,
+            );
+            if refined.error >= fit.error {
+                break;
+            }
+            fit = refined;
+        }
+
+        if best.is_none_or(|b| fit.error < b.error) {
+            best = Some(fit);
+        }
+    }
+
+    let ColorFit {
+        mut c0,
+        mut c1,
+        mut indices,
+        ..
+    } = best.expect("at least one endpoint candidate");
+
+    // The decoder picks the palette mode from the endpoint order, so enforce it
+    if three_color {
+        if c0 > c1 {
+            std::mem::swap(&mut c0, &mut c1);
+            for index in indices.iter_mut() {
+                *index = match *index {
+                    0 => 1,
+                    1 => 0,
+                    other => other,
+                };
+            }
+        }
+    } else if c0 < c1 {
+        std::mem::swap(&mut c0, &mut c1);
+        for index in indices.iter_mut() {
+            *index ^= 1;
+        }
+    } else if c0 == c1 {
+        // Equal endpoints would read as 3-color mode, stay on the endpoint
+        indices = [0; 16];
+    }
+
+    pack_color_block(c0, c1, &indices)
+}
+
+fn pack_color_block(c0: u16, c1: u16, indices: &[u8; 16]) -> [u8; 8] {
+    let mut bits = 0u32;
+    for (i, index) in indices.iter().enumerate() {
+        bits |= (*index as u32 & 0x3) << (i * 2);
+    }
+
+    let mut block = [0u8; 8];
+    block[0..2].copy_from_slice(&c0.to_le_bytes());
+    block[2..4].copy_from_slice(&c1.to_le_bytes());
+    block[4..8].copy_from_slice(&bits.to_le_bytes());
+    block
+}
+
+// DXT3: 4 bits of alpha per pixel, stored as-is
+fn encode_explicit_alpha(block: &Block) -> [u8; 8] {
+    let mut output = [0u8; 8];
+
+    for (i, pixel) in block.iter().enumerate() {
+        let nibble = (pixel[3] as u32 * 15 + 127) / 255;
+        if i % 2 == 0 {
+            output[i / 2] |= nibble as u8;
+        } else {
+            output[i / 2] |= (nibble as u8) << 4;
+        }
+    }
+
+    output
+}
+
+// Build the alpha palette exactly like the decoder does
+fn alpha_palette(a0: u8, a1: u8) -> [u8; 8] {
+    let (a0, a1) = (a0 as u32, a1 as u32);
+    if a0 > a1 {
+        [
+            a0 as u8,
+            a1 as u8,
+            ((6 * a0 + a1) / 7) as u8,
+            ((5 * a0 + 2 * a1) / 7) as u8,
+            ((4 * a0 + 3 * a1) / 7) as u8,
+            ((3 * a0 + 4 * a1) / 7) as u8,
+            ((2 * a0 + 5 * a1) / 7) as u8,
+            ((a0 + 6 * a1) / 7) as u8,
+        ]
+    } else {
+        [
+            a0 as u8,
+            a1 as u8,
+            ((4 * a0 + a1) / 5) as u8,
+            ((3 * a0 + 2 * a1) / 5) as u8,
+            ((2 * a0 + 3 * a1) / 5) as u8,
+            ((a0 + 4 * a1) / 5) as u8,
+            0,
+            255,
+        ]
+    }
+}
+
+// Pick alpha indices for an endpoint pair, returns (indices, error)
+fn fit_alpha(block: &Block, a0: u8, a1: u8) -> ([u8; 16], u32) {
+    let palette = alpha_palette(a0, a1);
+    let mut indices = [0u8; 16];
+    let mut error = 0;
+
+    for (index, pixel) in indices.iter_mut().zip(block) {
+        let (best, distance) = palette
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (i, (*entry as i32 - pixel[3] as i32).unsigned_abs()))
+            .min_by_key(|&(_, distance)| distance)
+            .unwrap_or((0, 0));
+        *index = best as u8;
+        error += distance * distance;
+    }
+
+    (indices, error)
+}
+
+// DXT5: two alpha endpoints and 3-bit interpolation indices
+fn encode_interpolated_alpha(block: &Block, quality: DxtQuality) -> [u8; 8] {
+    let min = block.iter().map(|p| p[3]).min().unwrap_or(255);
+    let max = block.iter().map(|p| p[3]).max().unwrap_or(255);
+
+    let (mut a0, mut a1) = (max, min);
+    let (mut indices, error) = fit_alpha(block, a0, a1);
+
+    // 6-alpha mode keeps exact 0/255 around, which helps cutout edges
+    if quality != DxtQuality::Fast && error > 0 {
+        let inner = block.iter().map(|p| p[3]).filter(|&a| a != 0 && a != 255);
+        let inner_min = inner.clone().min();
+        let inner_max = inner.max();
+
+        if let (Some(low), Some(high)) = (inner_min, inner_max) {
+            let (candidate, candidate_error) = fit_alpha(block, low, high);
+            if candidate_error < error {
+                a0 = low;
+                a1 = high;
+                indices = candidate;
+            }
+        }
+    }
+
+    let mut bits = 0u64;
+    for (i, index) in indices.iter().enumerate() {
+        bits |= (*index as u64 & 0x7) << (i * 3);
+    }
+
+    let mut output = [0u8; 8];
+    output[0] = a0;
+    output[1] = a1;
+    output[2..8].copy_from_slice(&bits.to_le_bytes()[..6]);
+    output
+}
+
+// 3Dc: one channel stored the way DXT5 stores alpha
+fn encode_channel(block: &Block, channel: usize, quality: DxtQuality) -> [u8; 8] {
+    let single = block.map(|pixel| [0, 0, 0, pixel[channel]]);
+    encode_interpolated_alpha(&single, quality)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vtf::formats::convert_to_rgba;
+
+    // Smooth gradient with a varying alpha ramp
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                data.push((x * 255 / (width - 1)) as u8);
+                data.push((y * 255 / (height - 1)) as u8);
+                data.push(128);
+                data.push(((x + y) * 255 / (width + height - 2)) as u8);
+            }
+        }
+        data
+    }
+
+    fn rmse(a: &[u8], b: &[u8], channels: &[usize]) -> f64 {
+        let mut sum = 0.0;
+        let mut count = 0.0;
+        for (pa, pb) in a.chunks(4).zip(b.chunks(4)) {
+            for &c in channels {
+                let d = pa[c] as f64 - pb[c] as f64;
+                sum += d * d;
+                count += 1.0;
+            }
+        }
+        (sum / count).sqrt()
+    }
+
+    #[test]
+    fn test_dxt_round_trip() {
+        let (width, height) = (32, 32);
+        let source = gradient(width, height);
+
+        for quality in [DxtQuality::Fast, DxtQuality::Normal, DxtQuality::High] {
+            for format in [VtfFormat::Dxt1, VtfFormat::Dxt3, VtfFormat::Dxt5] {
+                let compressed = compress_dxt(&source, width, height, format, quality).unwrap();
+                assert_eq!(
+                    compressed.len(),
+                    format.compute_image_size(width, height) as usize
+                );
+
+                let decoded = convert_to_rgba(&compressed, format, width, height).unwrap();
+                let color_error = rmse(&source, &decoded, &[0, 1, 2]);
+                assert!(
+                    color_error < 6.0,
+                    "{:?} {:?}: {}",
+                    format,
+                    quality,
+                    color_error
+                );
+
+                if format != VtfFormat::Dxt1 {
+                    let alpha_error = rmse(&source, &decoded, &[3]);
+                    assert!(
+                        alpha_error < 10.0,
+                        "{:?} {:?}: {}",
+                        format,
+                        quality,
+                        alpha_error
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dxt1_one_bit_alpha() {
+        // Left half transparent, right half opaque red, odd size to hit partial blocks
+        let (width, height) = (6, 5);
+        let mut source = Vec::new();
+        for _ in 0..height {
+            for x in 0..width {
+                if x < 3 {
+                    source.extend_from_slice(&[0, 0, 0, 0]);
+                } else {
+                    source.extend_from_slice(&[255, 0, 0, 255]);
+                }
+            }
+        }
+
+        let compressed = compress_dxt(
+            &source,
+            width,
+            height,
+            VtfFormat::Dxt1OneBitAlpha,
+            DxtQuality::Normal,
+        )
+        .unwrap();
+        let decoded =
+            convert_to_rgba(&compressed, VtfFormat::Dxt1OneBitAlpha, width, height).unwrap();
+
+        for (i, pixel) in decoded.chunks(4).enumerate() {
+            if i as u32 % width < 3 {
+                assert_eq!(pixel[3], 0);
+            } else {
+                assert_eq!(pixel, &[255, 0, 0, 255]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_solid_color_is_exact() {
+        // 565-representable color should survive untouched
+        let source = [255u8, 0, 255, 255].repeat(16);
+        let compressed = compress_dxt(&source, 4, 4, VtfFormat::Dxt1, DxtQuality::Normal).unwrap();
+        let decoded = convert_to_rgba(&compressed, VtfFormat::Dxt1, 4, 4).unwrap();
+        assert_eq!(decoded, source);
+    }
+
+    #[test]
+    fn test_3dc_round_trip() {
+        let (width, height) = (32, 32);
+        let source = gradient(width, height);
+
+        for (format, channels) in [
+            (VtfFormat::Ati1n, &[0][..]),
+            (VtfFormat::Ati2n, &[0, 1][..]),
+        ] {
+            let compressed =
+                compress_dxt(&source, width, height, format, DxtQuality::default()).unwrap();
+            assert_eq!(
+                compressed.len(),
+                format.compute_image_size(width, height) as usize
+            );
+            let decoded = convert_to_rgba(&compressed, format, width, height).unwrap();
+            assert!(rmse(&source, &decoded, channels) < 2.0, "{:?}", format);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! In-place header editing: change flags and header values of an existing VTF
+//! without decoding or re-encoding its image data
+
+use super::header::{VtfFlags, VtfHeader};
+use super::{VtfError, VtfResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Byte offsets of the editable fields, the same in every version
+const FLAGS_OFFSET: usize = 20;
+const FIRST_FRAME_OFFSET: usize = 26;
+const REFLECTIVITY_OFFSET: usize = 32;
+const BUMPMAP_SCALE_OFFSET: usize = 48;
+// 7.3+ resource directory entries: 3-byte tag, flags, 4-byte data
+const RESOURCE_DIRECTORY_OFFSET: usize = 80;
+
+// Loads a VTF, lets callers change `header`, and writes the file back with only
+// the changed fields patched. Flags, first frame, reflectivity, bump scale and the
+// inline values of 7.3+ resources can change; anything that decides where image
+// data lives has to stay as it was.
+pub struct VtfEditor {
+    pub header: VtfHeader,
+    original: VtfHeader,
+    data: Vec<u8>,
+    path: Option<PathBuf>,
+}
+
+impl VtfEditor {
+    pub fn open<P: AsRef<Path>>(path: P) -> VtfResult<Self> {
+        let mut editor = Self::from_memory(fs::read(path.as_ref())?)?;
+        editor.path = Some(path.as_ref().to_path_buf());
+        Ok(editor)
+    }
+
+    pub fn from_memory(data: Vec<u8>) -> VtfResult<Self> {
+        let header = VtfHeader::read(&data)?;
+        Ok(Self {
+            original: header.clone(),
+            header,
+            data,
+            path: None,
+        })
+    }
+
+    pub fn set_flag(&mut self, flag: VtfFlags, enabled: bool) {
+        self.header.flags.set(flag, enabled);
+    }
+
+    // Check that nothing changed that would move or reinterpret the image data
+    fn validate(&self) -> VtfResult<()> {
+        let (old, new) = (&self.original, &self.header);
+        let layout_unchanged = old.version == new.version
+            && old.header_size == new.header_size
+            && (old.width, old.height, old.depth) == (new.width, new.height, new.depth)
+            && old.frames == new.frames
+            && old.high_res_format == new.high_res_format
+            && old.low_res_format == new.low_res_format
+            && old.mipmap_count == new.mipmap_count
+            && (old.low_res_width, old.low_res_height) == (new.low_res_width, new.low_res_height)
+            && old.resource_count == new.resource_count;
+        if !layout_unchanged {
+            return Err(VtfError::InvalidData(
+                "Size, format, frames, mips and version can't be edited in place".into(),
+            ));
+        }
+
+        if old.flags.contains(VtfFlags::ENVMAP) != new.flags.contains(VtfFlags::ENVMAP) {
+            return Err(VtfError::InvalidData(
+                "The envmap flag decides the face layout and can't be changed".into(),
+            ));
+        }
+        // Before 7.5 the first frame of a cubemap also says whether a spheremap is stored
+        if old.first_frame != new.first_frame {
+            if old.is_envmap() && old.version.minor < 5 {
+                return Err(VtfError::InvalidData(
+                    "The first frame of a cubemap older than 7.5 can't be changed".into(),
+                ));
+            }
+            if new.first_frame >= new.frames.max(1) {
+                return Err(VtfError::InvalidFrame(new.first_frame));
+            }
+        }
+
+        if old.resources.len() != new.resources.len() {
+            return Err(VtfError::InvalidData(
+                "Resources can't be added or removed in place".into(),
+            ));
+        }
+        for (old_entry, new_entry) in old.resources.iter().zip(&new.resources) {
+            let same_slot = old_entry.tag == new_entry.tag && old_entry.flags == new_entry.flags;
+            if !same_slot || (old_entry.has_data_chunk() && old_entry.data != new_entry.data) {
+                return Err(VtfError::InvalidData(format!(
+                    "Only the inline value of resource {:?} can be edited",
+                    old_entry.tag
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // The original file with the edited fields written over it
+    pub fn to_bytes(&self) -> VtfResult<Vec<u8>> {
+        self.validate()?;
+
+        let mut output = self.data.clone();
+        let mut write = |offset: usize, bytes: &[u8]| -> VtfResult<()> {
+            output
+                .get_mut(offset..offset + bytes.len())
+                .ok_or(VtfError::InvalidData("Header is truncated".into()))?
+                .copy_from_slice(bytes);
+            Ok(())
+        };
+
+        let header = &self.header;
+        write(FLAGS_OFFSET, &header.flags.bits().to_le_bytes())?;
+        write(FIRST_FRAME_OFFSET, &header.first_frame.to_le_bytes())?;
+        for (index, value) in header.reflectivity.iter().enumerate() {
+            write(REFLECTIVITY_OFFSET + index * 4, &value.to_le_bytes())?;
+        }
+        write(BUMPMAP_SCALE_OFFSET, &header.bumpmap_scale.to_le_bytes())?;
+        for (index, entry) in header.resources.iter().enumerate() {
+            if !entry.has_data_chunk() {
+                let offset = RESOURCE_DIRECTORY_OFFSET + index * 8 + 4;
+                write(offset, &entry.data.to_le_bytes())?;
+            }
+        }
+        Ok(output)
+    }
+
+    // Write back to the file this editor was opened from
+    pub fn save(&self) -> VtfResult<()> {
+        let path = self.path.as_ref().ok_or(VtfError::InvalidData(
+            "Editor was not opened from a file".into(),
+        ))?;
+        self.save_as(path)
+    }
+
+    pub fn save_as<P: AsRef<Path>>(&self, path: P) -> VtfResult<()> {
+        fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vtf::{VtfBuilder, VtfDecoder, VtfFormat, VtfResourceTag, VtfVersion};
+
+    fn texture(version: VtfVersion) -> Vec<u8> {
+        VtfBuilder::from_frames(8, 8, vec![vec![90; 8 * 8 * 4]; 3])
+            .unwrap()
+            .format(VtfFormat::Dxt1)
+            .version(version)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_edit_changes_only_the_fields() {
+        let original = texture(VtfVersion::new(7, 2));
+        let mut editor = VtfEditor::from_memory(original.clone()).unwrap();
+        editor.set_flag(VtfFlags::CLAMPS | VtfFlags::POINTSAMPLE, true);
+        editor.header.bumpmap_scale = 2.5;
+        editor.header.first_frame = 2;
+        let edited = editor.to_bytes().unwrap();
+
+        assert_eq!(edited.len(), original.len());
+        let changed: Vec<usize> = (0..edited.len())
+            .filter(|&i| edited[i] != original[i])
+            .collect();
+        assert!(changed.iter().all(|&i| i < 64));
+
+        let header = VtfDecoder::load_from_memory(&edited).unwrap().header;
+        assert!(
+            header
+                .flags
+                .contains(VtfFlags::CLAMPS | VtfFlags::POINTSAMPLE)
+        );
+        assert_eq!(header.bumpmap_scale, 2.5);
+        assert_eq!(header.first_frame, 2);
+    }
+
+    #[test]
+    fn test_edit_inline_resources() {
+        let original = VtfBuilder::new(8, 8, vec![90; 8 * 8 * 4])
+            .version(VtfVersion::new(7, 4))
+            .lod_control(4, 4)
+            .build()
+            .unwrap();
+        let mut editor = VtfEditor::from_memory(original.clone()).unwrap();
+        let entry = editor
+            .header
+            .resources
+            .iter_mut()
+            .find(|entry| entry.tag == VtfResourceTag::LodControl)
+            .unwrap();
+        entry.data = u32::from_le_bytes([2, 3, 0, 0]);
+        editor.set_flag(VtfFlags::NOLOD, true);
+
+        let vtf = VtfDecoder::load_from_memory(&editor.to_bytes().unwrap()).unwrap();
+        assert_eq!(vtf.lod_control(), Some((2, 3)));
+        assert!(vtf.header.flags.contains(VtfFlags::NOLOD));
+        assert_eq!(
+            vtf.decode(0, 0).unwrap().data,
+            VtfDecoder::load_from_memory(&original)
+                .unwrap()
+                .decode(0, 0)
+                .unwrap()
+                .data
+        );
+    }
+
+    #[test]
+    fn test_edit_rejects_layout_changes() {
+        let mut editor = VtfEditor::from_memory(texture(VtfVersion::new(7, 5))).unwrap();
+        editor.header.width = 16;
+        assert!(editor.to_bytes().is_err());
+
+        let mut editor = VtfEditor::from_memory(texture(VtfVersion::new(7, 5))).unwrap();
+        editor.set_flag(VtfFlags::ENVMAP, true);
+        assert!(editor.to_bytes().is_err());
+
+        let mut editor = VtfEditor::from_memory(texture(VtfVersion::new(7, 5))).unwrap();
+        editor.header.first_frame = 3;
+        assert!(matches!(editor.to_bytes(), Err(VtfError::InvalidFrame(3))));
+    }
+}
@@ -2,8 +2,15 @@
 // spent a dozen hours on this
 // thank you thank you thank you thank you thank you
 
+use super::bptc::{compress_bc6h, compress_bc7, decode_bc6h_block, decode_bc7_block};
+use super::dxt::{DxtQuality, compress_dxt};
 use super::header::VtfFormat;
+use super::swizzle::Swizzle;
 use super::{VtfError, VtfResult};
+use rayon::prelude::*;
+
+#[cfg(test)]
+mod reference;
 
 // Supported image formats for conversion
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -29,454 +36,667 @@ impl ImageFormat {
     }
 }
 
-// convert raw VTF image data to RGBA8
+// Uncompressed data has to hold every pixel, the block decoders check their own
+fn check_data_size(data: &[u8], format: VtfFormat, width: u32, height: u32) -> VtfResult<()> {
+    let needed = format.compute_image_size(width, height) as usize;
+    if !format.is_compressed() && data.len() < needed {
+        return Err(VtfError::InvalidData(format!(
+            "Expected {} bytes of {:?} data for {}x{}, got {}",
+            needed,
+            format,
+            width,
+            height,
+            data.len()
+        )));
+    }
+    Ok(())
+}
+
+// convert raw VTF image data to RGBA8. Runs of pixels and rows of blocks are
+// converted in parallel, byte reorders use SIMD shuffles where the CPU has them.
 pub fn convert_to_rgba(
     data: &[u8],
     format: VtfFormat,
     width: u32,
     height: u32,
 ) -> VtfResult<Vec<u8>> {
+    check_data_size(data, format, width, height)?;
     let pixel_count = (width * height) as usize;
     let mut output = vec![0u8; pixel_count * 4];
 
-    match format {
-        VtfFormat::Rgba8888 => {
-            output.copy_from_slice(&data[..pixel_count * 4]);
-        }
-
-        VtfFormat::Abgr8888 => {
-            for i in 0..pixel_count {
-                output[i * 4] = data[i * 4 + 3]; // R from A
-                output[i * 4 + 1] = data[i * 4 + 2]; // G from B
-                output[i * 4 + 2] = data[i * 4 + 1]; // B from G
-                output[i * 4 + 3] = data[i * 4]; // A from R position
-            }
-        }
-
-        VtfFormat::Argb8888 => {
-            for i in 0..pixel_count {
-                output[i * 4] = data[i * 4 + 1]; // R
-                output[i * 4 + 1] = data[i * 4 + 2]; // G
-                output[i * 4 + 2] = data[i * 4 + 3]; // B
-                output[i * 4 + 3] = data[i * 4]; // A
-            }
-        }
-
-        VtfFormat::Bgra8888 => {
-            for i in 0..pixel_count {
-                output[i * 4] = data[i * 4 + 2]; // R from B
-                output[i * 4 + 1] = data[i * 4 + 1]; // G
-                output[i * 4 + 2] = data[i * 4]; // B from R position
-                output[i * 4 + 3] = data[i * 4 + 3]; // A
-            }
-        }
-
-        VtfFormat::Bgrx8888 => {
-            for i in 0..pixel_count {
-                output[i * 4] = data[i * 4 + 2]; // R from B
-                output[i * 4 + 1] = data[i * 4 + 1]; // G
-                output[i * 4 + 2] = data[i * 4]; // B from R position
-                output[i * 4 + 3] = 255; // A = opaque
-            }
-        }
-
-        VtfFormat::Rgb888 => {
-            for i in 0..pixel_count {
-                output[i * 4] = data[i * 3];
-                output[i * 4 + 1] = data[i * 3 + 1];
-                output[i * 4 + 2] = data[i * 3 + 2];
-                output[i * 4 + 3] = 255;
-            }
-        }
+    if let Some(swizzle) = Swizzle::for_format(format) {
+        swizzle.apply(&data[..pixel_count * swizzle.stride()], &mut output);
+        return Ok(output);
+    }
 
-        VtfFormat::Bgr888 | VtfFormat::Bgr888BlueScreen => {
-            for i in 0..pixel_count {
-                output[i * 4] = data[i * 3 + 2]; // R from B
-                output[i * 4 + 1] = data[i * 3 + 1]; // G
-                output[i * 4 + 2] = data[i * 3]; // B from R position
-                output[i * 4 + 3] = 255;
-            }
+    match format {
+        VtfFormat::Bgr888BlueScreen => {
+            convert_pixels(data, &mut output, |[b, g, r]| {
+                // Same blue screen rule as the RGB variant
+                [r, g, b, if r == 0 && g == 0 && b == 255 { 0 } else { 255 }]
+            });
         }
 
         VtfFormat::Rgb888BlueScreen => {
-            for i in 0..pixel_count {
-                let r = data[i * 3];
-                let g = data[i * 3 + 1];
-                let b = data[i * 3 + 2];
-                output[i * 4] = r;
-                output[i * 4 + 1] = g;
-                output[i * 4 + 2] = b;
+            convert_pixels(data, &mut output, |[r, g, b]| {
                 // Blue screen: if pure blue, make transparent
-                output[i * 4 + 3] = if r == 0 && g == 0 && b == 255 { 0 } else { 255 };
-            }
+                [r, g, b, if r == 0 && g == 0 && b == 255 { 0 } else { 255 }]
+            });
         }
 
         VtfFormat::Rgb565 => {
-            for i in 0..pixel_count {
-                let pixel = u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
-                output[i * 4] = (((pixel >> 11) & 0x1F) as u32 * 255 / 31) as u8;
-                output[i * 4 + 1] = (((pixel >> 5) & 0x3F) as u32 * 255 / 63) as u8;
-                output[i * 4 + 2] = ((pixel & 0x1F) as u32 * 255 / 31) as u8;
-                output[i * 4 + 3] = 255;
-            }
+            convert_pixels(data, &mut output, |bytes| {
+                decode_565(u16::from_le_bytes(bytes))
+            });
         }
 
         VtfFormat::Bgr565 => {
-            for i in 0..pixel_count {
-                let pixel = u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
-                output[i * 4 + 2] = (((pixel >> 11) & 0x1F) as u32 * 255 / 31) as u8;
-                output[i * 4 + 1] = (((pixel >> 5) & 0x3F) as u32 * 255 / 63) as u8;
-                output[i * 4] = ((pixel & 0x1F) as u32 * 255 / 31) as u8;
-                output[i * 4 + 3] = 255;
-            }
+            convert_pixels(data, &mut output, |bytes| {
+                let [b, g, r, a] = decode_565(u16::from_le_bytes(bytes));
+                [r, g, b, a]
+            });
         }
 
         VtfFormat::Bgra4444 => {
-            for i in 0..pixel_count {
-                let pixel = u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
-                output[i * 4 + 2] = (((pixel >> 12) & 0xF) as u32 * 17) as u8;
-                output[i * 4 + 1] = (((pixel >> 8) & 0xF) as u32 * 17) as u8;
-                output[i * 4] = (((pixel >> 4) & 0xF) as u32 * 17) as u8;
-                output[i * 4 + 3] = ((pixel & 0xF) as u32 * 17) as u8;
-            }
+            convert_pixels(data, &mut output, |bytes| {
+                let pixel = u16::from_le_bytes(bytes);
+                let channel = |shift: u16| ((pixel >> shift) & 0xF) as u8 * 17;
+                [channel(4), channel(8), channel(12), channel(0)]
+            });
         }
-        // fuck me sideways
-        // thank god copy pasting code saved my life
+
         VtfFormat::Bgra5551 | VtfFormat::Bgrx5551 => {
-            for i in 0..pixel_count {
-                let pixel = u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
-                output[i * 4 + 2] = (((pixel >> 10) & 0x1F) as u32 * 255 / 31) as u8;
-                output[i * 4 + 1] = (((pixel >> 5) & 0x1F) as u32 * 255 / 31) as u8;
-                output[i * 4] = ((pixel & 0x1F) as u32 * 255 / 31) as u8;
-                output[i * 4 + 3] = if format == VtfFormat::Bgrx5551 {
-                    255
-                } else if pixel & 0x8000 != 0 {
+            let opaque = format == VtfFormat::Bgrx5551;
+            convert_pixels(data, &mut output, |bytes| {
+                let pixel = u16::from_le_bytes(bytes);
+                let channel = |shift: u16| (((pixel >> shift) & 0x1F) as u32 * 255 / 31) as u8;
+                let alpha = if opaque || pixel & 0x8000 != 0 {
                     255
                 } else {
                     0
                 };
-            }
+                [channel(0), channel(5), channel(10), alpha]
+            });
         }
 
         VtfFormat::I8 => {
-            for i in 0..pixel_count {
-                let intensity = data[i];
-                output[i * 4] = intensity;
-                output[i * 4 + 1] = intensity;
-                output[i * 4 + 2] = intensity;
-                output[i * 4 + 3] = 255;
-            }
+            convert_pixels(data, &mut output, |[intensity]| {
+                [intensity, intensity, intensity, 255]
+            });
         }
 
         VtfFormat::Ia88 => {
-            for i in 0..pixel_count {
-                let intensity = data[i * 2];
-                let alpha = data[i * 2 + 1];
-                output[i * 4] = intensity;
-                output[i * 4 + 1] = intensity;
-                output[i * 4 + 2] = intensity;
-                output[i * 4 + 3] = alpha;
-            }
+            convert_pixels(data, &mut output, |[intensity, alpha]| {
+                [intensity, intensity, intensity, alpha]
+            });
         }
 
         VtfFormat::A8 => {
-            for i in 0..pixel_count {
-                output[i * 4] = 255;
-                output[i * 4 + 1] = 255;
-                output[i * 4 + 2] = 255;
-                output[i * 4 + 3] = data[i];
-            }
+            convert_pixels(data, &mut output, |[alpha]| [255, 255, 255, alpha]);
         }
 
         VtfFormat::Uv88 => {
-            for i in 0..pixel_count {
-                output[i * 4] = data[i * 2]; // U -> R
-                output[i * 4 + 1] = data[i * 2 + 1]; // V -> G
-                output[i * 4 + 2] = 128; // B = neutral
-                output[i * 4 + 3] = 255;
-            }
-        }
-
-        VtfFormat::Uvwq8888 | VtfFormat::Uvlx8888 => {
-            output.copy_from_slice(&data[..pixel_count * 4]);
+            // U and V to red and green, blue left neutral
+            convert_pixels(data, &mut output, |[u, v]| [u, v, 128, 255]);
         }
 
         VtfFormat::Dxt1 | VtfFormat::Dxt1OneBitAlpha => {
-            decode_dxt1(
-                data,
-                width,
-                height,
-                &mut output,
-                format == VtfFormat::Dxt1OneBitAlpha,
-            )?;
+            let one_bit_alpha = format == VtfFormat::Dxt1OneBitAlpha;
+            decode_blocks(data, format, width, height, &mut output, |block| {
+                decode_dxt_color_block(block, one_bit_alpha)
+            })?;
         }
 
         VtfFormat::Dxt3 => {
-            decode_dxt3(data, width, height, &mut output)?;
+            decode_blocks(data, format, width, height, &mut output, |block| {
+                let mut pixels = decode_dxt_color_block(&block[8..], false);
+                for (i, pixel) in pixels.iter_mut().enumerate() {
+                    // Explicit 4-bit alpha, low nibble first
+                    pixel[3] = ((block[i / 2] >> (i % 2 * 4)) & 0x0F) * 17;
+                }
+                pixels
+            })?;
         }
 
         VtfFormat::Dxt5 => {
-            decode_dxt5(data, width, height, &mut output)?;
+            // The alpha half is a BC4 block
+            decode_blocks(data, format, width, height, &mut output, |block| {
+                let mut pixels = decode_dxt_color_block(&block[8..], false);
+                for (pixel, alpha) in pixels.iter_mut().zip(decode_bc4_block(&block[..8])) {
+                    pixel[3] = alpha;
+                }
+                pixels
+            })?;
+        }
+
+        VtfFormat::Ati1n => {
+            decode_blocks(data, format, width, height, &mut output, |block| {
+                decode_bc4_block(block).map(|v| [v, v, v, 255])
+            })?;
+        }
+
+        VtfFormat::Ati2n => {
+            // Z is left for the shader to rebuild, like Uv88
+            decode_blocks(data, format, width, height, &mut output, |block| {
+                let (x, y) = (decode_bc4_block(&block[..8]), decode_bc4_block(&block[8..]));
+                std::array::from_fn(|i| [x[i], y[i], 128, 255])
+            })?;
+        }
+
+        VtfFormat::Bc7 => {
+            decode_blocks(data, format, width, height, &mut output, decode_bc7_block)?;
+        }
+
+        VtfFormat::Bc6h => {
+            for (value, float) in output
+                .iter_mut()
+                .zip(convert_to_rgba_f32(data, format, width, height)?)
+            {
+                *value = (float.clamp(0.0, 1.0) * 255.0) as u8;
+            }
         }
 
         VtfFormat::Rgba16161616F => {
             // Convert 16-bit float to 8-bit
-            for i in 0..pixel_count {
-                for c in 0..4 {
-                    let bytes = [data[i * 8 + c * 2], data[i * 8 + c * 2 + 1]];
-                    let f16 = half_to_float(u16::from_le_bytes(bytes));
-                    output[i * 4 + c] = (f16.clamp(0.0, 1.0) * 255.0) as u8;
-                }
-            }
+            convert_pixels(data, &mut output, |bytes: [u8; 8]| {
+                std::array::from_fn(|c| {
+                    let half = u16::from_le_bytes([bytes[c * 2], bytes[c * 2 + 1]]);
+                    (half_to_float(half).clamp(0.0, 1.0) * 255.0) as u8
+                })
+            });
         }
 
         VtfFormat::Rgba16161616 => {
-            for i in 0..pixel_count {
-                for c in 0..4 {
-                    let bytes = [data[i * 8 + c * 2], data[i * 8 + c * 2 + 1]];
-                    let value = u16::from_le_bytes(bytes);
-                    output[i * 4 + c] = (value >> 8) as u8;
-                }
-            }
+            convert_pixels(data, &mut output, |bytes: [u8; 8]| {
+                // High byte of each little endian channel
+                std::array::from_fn(|c| bytes[c * 2 + 1])
+            });
         }
 
         VtfFormat::None | VtfFormat::P8 => {
             return Err(VtfError::UnsupportedFormat(super::ImageFormat::Rgba8));
         }
+
+        VtfFormat::Rgba8888
+        | VtfFormat::Abgr8888
+        | VtfFormat::Argb8888
+        | VtfFormat::Bgra8888
+        | VtfFormat::Bgrx8888
+        | VtfFormat::Rgb888
+        | VtfFormat::Bgr888
+        | VtfFormat::Uvwq8888
+        | VtfFormat::Uvlx8888 => unreachable!("Swizzled above"),
     }
 
     Ok(output)
 }
 
-// Decode DXT1 compressed data
-fn decode_dxt1(
+// convert raw VTF image data to linear RGBA32F without clamping. Only the 16-bit
+// formats carry more than 8 bits, everything else goes through convert_to_rgba.
+pub fn convert_to_rgba_f32(
     data: &[u8],
+    format: VtfFormat,
     width: u32,
     height: u32,
-    output: &mut [u8],
-    has_alpha: bool,
-) -> VtfResult<()> {
-    let block_width = (width + 3) / 4;
-    let block_height = (height + 3) / 4;
-
-    for by in 0..block_height {
-        for bx in 0..block_width {
-            let block_index = (by * block_width + bx) as usize;
-            let block_data = &data[block_index * 8..(block_index + 1) * 8];
-
-            // Read color endpoints
-            let c0 = u16::from_le_bytes([block_data[0], block_data[1]]);
-            let c1 = u16::from_le_bytes([block_data[2], block_data[3]]);
-
-            // Decode colors
-            let color0 = decode_565(c0);
-            let color1 = decode_565(c1);
-
-            // Generate color palette
-            let colors = if c0 > c1 || !has_alpha {
-                [
-                    color0,
-                    color1,
-                    interpolate_color(&color0, &color1, 1, 3),
-                    interpolate_color(&color0, &color1, 2, 3),
-                ]
-            } else {
-                [
-                    color0,
-                    color1,
-                    interpolate_color(&color0, &color1, 1, 2),
-                    [0, 0, 0, 0], // Transparent
-                ]
-            };
+) -> VtfResult<Vec<f32>> {
+    check_data_size(data, format, width, height)?;
+    let pixel_count = (width * height) as usize;
 
-            // Read color indices
-            let indices =
-                u32::from_le_bytes([block_data[4], block_data[5], block_data[6], block_data[7]]);
-
-            // Decode them pixels
-            for py in 0..4 {
-                for px in 0..4 {
-                    let x = bx * 4 + px;
-                    let y = by * 4 + py;
-
-                    if x < width && y < height {
-                        let pixel_index = py * 4 + px;
-                        let color_index = ((indices >> (pixel_index * 2)) & 0x3) as usize;
-                        let color = &colors[color_index];
-
-                        let output_index = ((y * width + x) * 4) as usize;
-                        output[output_index] = color[0];
-                        output[output_index + 1] = color[1];
-                        output[output_index + 2] = color[2];
-                        output[output_index + 3] = color[3];
-                    }
-                }
-            }
+    let output = match format {
+        VtfFormat::Rgba16161616F => data[..pixel_count * 8]
+            .chunks_exact(2)
+            .map(|bytes| half_to_float(u16::from_le_bytes([bytes[0], bytes[1]])))
+            .collect(),
+
+        VtfFormat::Rgba16161616 => data[..pixel_count * 8]
+            .chunks_exact(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0)
+            .collect(),
+
+        VtfFormat::Bc6h => {
+            let mut output = vec![0.0; pixel_count * 4];
+            decode_blocks(data, format, width, height, &mut output, decode_bc6h_block)?;
+            output
         }
+
+        _ => convert_to_rgba(data, format, width, height)?
+            .iter()
+            .map(|&v| v as f32 / 255.0)
+            .collect(),
+    };
+
+    Ok(output)
+}
+
+// convert raw VTF image data to RGBA16, keeping all 16 bits of Rgba16161616
+pub fn convert_to_rgba16(
+    data: &[u8],
+    format: VtfFormat,
+    width: u32,
+    height: u32,
+) -> VtfResult<Vec<u16>> {
+    check_data_size(data, format, width, height)?;
+    let pixel_count = (width * height) as usize;
+
+    let output = match format {
+        VtfFormat::Rgba16161616 => data[..pixel_count * 8]
+            .chunks_exact(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect(),
+
+        VtfFormat::Rgba16161616F | VtfFormat::Bc6h => {
+            convert_to_rgba_f32(data, format, width, height)?
+                .iter()
+                .map(|&v| (v.clamp(0.0, 1.0) * 65535.0).round() as u16)
+                .collect()
+        }
+
+        _ => convert_to_rgba(data, format, width, height)?
+            .iter()
+            .map(|&v| v as u16 * 257)
+            .collect(),
+    };
+
+    Ok(output)
+}
+
+// convert RGBA32F data to raw VTF image data. The 16-bit formats keep the extra
+// precision (and range for Rgba16161616F), the rest are clamped to 8 bits first.
+pub fn convert_from_rgba_f32(
+    data: &[f32],
+    format: VtfFormat,
+    width: u32,
+    height: u32,
+    dither: Dither,
+) -> VtfResult<Vec<u8>> {
+    let pixel_count = (width * height) as usize;
+    if data.len() < pixel_count * 4 {
+        return Err(VtfError::InvalidData(format!(
+            "Expected {} floats of RGBA data for {}x{}, got {}",
+            pixel_count * 4,
+            width,
+            height,
+            data.len()
+        )));
     }
+    let data = &data[..pixel_count * 4];
+
+    let output = match format {
+        VtfFormat::Rgba16161616F => data
+            .iter()
+            .flat_map(|&v| float_to_half(v).to_le_bytes())
+            .collect(),
+
+        VtfFormat::Rgba16161616 => data
+            .iter()
+            .flat_map(|&v| ((v.clamp(0.0, 1.0) * 65535.0).round() as u16).to_le_bytes())
+            .collect(),
+
+        VtfFormat::Bc6h => compress_bc6h(data, width, height),
+
+        _ => {
+            let rgba: Vec<u8> = data
+                .iter()
+                .map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+                .collect();
+            convert_from_rgba(&rgba, format, width, height, dither)?
+        }
+    };
 
-    Ok(())
+    Ok(output)
 }
 
-// Decode DXT3 compressed data
-fn decode_dxt3(data: &[u8], width: u32, height: u32, output: &mut [u8]) -> VtfResult<()> {
-    let block_width = (width + 3) / 4;
-    let block_height = (height + 3) / 4;
-
-    for by in 0..block_height {
-        for bx in 0..block_width {
-            let block_index = (by * block_width + bx) as usize;
-            let block_data = &data[block_index * 16..(block_index + 1) * 16];
-
-            // Read explicit alpha values (first 8 bytes)
-            let alpha_data = &block_data[0..8];
-
-            // Read color data (last 8 bytes)
-            let color_data = &block_data[8..16];
-
-            let c0 = u16::from_le_bytes([color_data[0], color_data[1]]);
-            let c1 = u16::from_le_bytes([color_data[2], color_data[3]]);
-
-            let color0 = decode_565(c0);
-            let color1 = decode_565(c1);
-
-            let colors = [
-                color0,
-                color1,
-                interpolate_color(&color0, &color1, 1, 3),
-                interpolate_color(&color0, &color1, 2, 3),
-            ];
-
-            let indices =
-                u32::from_le_bytes([color_data[4], color_data[5], color_data[6], color_data[7]]);
-
-            for py in 0..4 {
-                for px in 0..4 {
-                    let x = bx * 4 + px;
-                    let y = by * 4 + py;
-
-                    if x < width && y < height {
-                        let pixel_index = py * 4 + px;
-                        let color_index = ((indices >> (pixel_index * 2)) & 0x3) as usize;
-                        let color = &colors[color_index];
-
-                        // kill em all
-                        let alpha_byte_index = (pixel_index / 2) as usize;
-                        let alpha_nibble = if pixel_index % 2 == 0 {
-                            alpha_data[alpha_byte_index] & 0x0F
-                        } else {
-                            alpha_data[alpha_byte_index] >> 4
-                        };
-                        let alpha = alpha_nibble * 17; // Scale 0-15 to 0-255
-
-                        let output_index = ((y * width + x) * 4) as usize;
-                        output[output_index] = color[0];
-                        output[output_index + 1] = color[1];
-                        output[output_index + 2] = color[2];
-                        output[output_index + 3] = alpha;
-                    }
-                }
-            }
+// Dithering applied when quantizing to formats with fewer than 8 bits per channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    // Plain rounding
+    #[default]
+    None,
+    // 4x4 Bayer matrix, stable between frames
+    Ordered,
+    // Floyd-Steinberg error diffusion
+    ErrorDiffusion,
+}
+
+// 4x4 Bayer threshold matrix
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+// convert RGBA8 data to raw VTF image data (the inverse of convert_to_rgba)
+pub fn convert_from_rgba(
+    data: &[u8],
+    format: VtfFormat,
+    width: u32,
+    height: u32,
+    dither: Dither,
+) -> VtfResult<Vec<u8>> {
+    let pixel_count = (width * height) as usize;
+    if data.len() < pixel_count * 4 {
+        return Err(VtfError::InvalidData(format!(
+            "Expected {} bytes of RGBA data for {}x{}, got {}",
+            pixel_count * 4,
+            width,
+            height,
+            data.len()
+        )));
+    }
+    let data = &data[..pixel_count * 4];
+
+    let output = match format {
+        VtfFormat::Rgba8888 | VtfFormat::Uvwq8888 | VtfFormat::Uvlx8888 => data.to_vec(),
+
+        VtfFormat::Abgr8888 => data
+            .chunks_exact(4)
+            .flat_map(|p| [p[3], p[2], p[1], p[0]])
+            .collect(),
+
+        VtfFormat::Argb8888 => data
+            .chunks_exact(4)
+            .flat_map(|p| [p[3], p[0], p[1], p[2]])
+            .collect(),
+
+        VtfFormat::Bgra8888 => data
+            .chunks_exact(4)
+            .flat_map(|p| [p[2], p[1], p[0], p[3]])
+            .collect(),
+
+        VtfFormat::Bgrx8888 => data
+            .chunks_exact(4)
+            .flat_map(|p| [p[2], p[1], p[0], 255])
+            .collect(),
+
+        VtfFormat::Rgb888 => data
+            .chunks_exact(4)
+            .flat_map(|p| [p[0], p[1], p[2]])
+            .collect(),
+
+        VtfFormat::Bgr888 => data
+            .chunks_exact(4)
+            .flat_map(|p| [p[2], p[1], p[0]])
+            .collect(),
+
+        VtfFormat::Rgb888BlueScreen => data
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let [r, g, b] = blue_screen(p);
+                [r, g, b]
+            })
+            .collect(),
+
+        VtfFormat::Bgr888BlueScreen => data
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let [r, g, b] = blue_screen(p);
+                [b, g, r]
+            })
+            .collect(),
+
+        VtfFormat::Rgb565 => quantize(data, width, height, [5, 6, 5, 0], dither)
+            .iter()
+            .flat_map(|q| ((q[0] << 11) | (q[1] << 5) | q[2]).to_le_bytes())
+            .collect(),
+
+        VtfFormat::Bgr565 => quantize(data, width, height, [5, 6, 5, 0], dither)
+            .iter()
+            .flat_map(|q| ((q[2] << 11) | (q[1] << 5) | q[0]).to_le_bytes())
+            .collect(),
+
+        VtfFormat::Bgra4444 => quantize(data, width, height, [4, 4, 4, 4], dither)
+            .iter()
+            .flat_map(|q| ((q[2] << 12) | (q[1] << 8) | (q[0] << 4) | q[3]).to_le_bytes())
+            .collect(),
+
+        VtfFormat::Bgra5551 => quantize(data, width, height, [5, 5, 5, 1], dither)
+            .iter()
+            .flat_map(|q| ((q[3] << 15) | (q[2] << 10) | (q[1] << 5) | q[0]).to_le_bytes())
+            .collect(),
+
+        VtfFormat::Bgrx5551 => quantize(data, width, height, [5, 5, 5, 0], dither)
+            .iter()
+            .flat_map(|q| (0x8000 | (q[2] << 10) | (q[1] << 5) | q[0]).to_le_bytes())
+            .collect(),
+
+        VtfFormat::I8 => data.chunks_exact(4).map(luminance).collect(),
+
+        VtfFormat::Ia88 => data
+            .chunks_exact(4)
+            .flat_map(|p| [luminance(p), p[3]])
+            .collect(),
+
+        VtfFormat::A8 => data.chunks_exact(4).map(|p| p[3]).collect(),
+
+        VtfFormat::Uv88 => data.chunks_exact(4).flat_map(|p| [p[0], p[1]]).collect(),
+
+        VtfFormat::Rgba16161616F => data
+            .iter()
+            .flat_map(|&v| float_to_half(v as f32 / 255.0).to_le_bytes())
+            .collect(),
+
+        VtfFormat::Rgba16161616 => data
+            .iter()
+            .flat_map(|&v| (v as u16 * 257).to_le_bytes())
+            .collect(),
+
+        VtfFormat::Dxt1
+        | VtfFormat::Dxt1OneBitAlpha
+        | VtfFormat::Dxt3
+        | VtfFormat::Dxt5
+        | VtfFormat::Ati1n
+        | VtfFormat::Ati2n => compress_dxt(data, width, height, format, DxtQuality::default())?,
+
+        VtfFormat::Bc7 => compress_bc7(data, width, height),
+
+        VtfFormat::Bc6h => {
+            let rgba: Vec<f32> = data.iter().map(|&v| v as f32 / 255.0).collect();
+            compress_bc6h(&rgba, width, height)
         }
+
+        VtfFormat::None | VtfFormat::P8 => {
+            return Err(VtfError::InvalidData(format!(
+                "Cannot encode to {:?}",
+                format
+            )));
+        }
+    };
+
+    Ok(output)
+}
+
+// Blue screen formats mark transparency with pure blue
+fn blue_screen(pixel: &[u8]) -> [u8; 3] {
+    if pixel[3] < 128 {
+        [0, 0, 255]
+    } else if pixel[0] == 0 && pixel[1] == 0 && pixel[2] == 255 {
+        // Opaque pure blue would read back as transparent, nudge it
+        [0, 0, 254]
+    } else {
+        [pixel[0], pixel[1], pixel[2]]
     }
+}
 
-    Ok(())
+// Rec. 601 luma, same weights the engine uses for intensity formats
+fn luminance(pixel: &[u8]) -> u8 {
+    (pixel[0] as f32 * 0.299 + pixel[1] as f32 * 0.587 + pixel[2] as f32 * 0.114).round() as u8
 }
 
-// Decode DXT5 compressed data
-fn decode_dxt5(data: &[u8], width: u32, height: u32, output: &mut [u8]) -> VtfResult<()> {
-    let block_width = (width + 3) / 4;
-    let block_height = (height + 3) / 4;
-
-    for by in 0..block_height {
-        for bx in 0..block_width {
-            let block_index = (by * block_width + bx) as usize;
-            let block_data = &data[block_index * 16..(block_index + 1) * 16];
-
-            // Read alpha endpoints
-            let a0 = block_data[0];
-            let a1 = block_data[1];
-
-            // Generate alpha palette
-            let alphas = if a0 > a1 {
-                [
-                    a0,
-                    a1,
-                    ((6 * a0 as u32 + 1 * a1 as u32) / 7) as u8,
-                    ((5 * a0 as u32 + 2 * a1 as u32) / 7) as u8,
-                    ((4 * a0 as u32 + 3 * a1 as u32) / 7) as u8,
-                    ((3 * a0 as u32 + 4 * a1 as u32) / 7) as u8,
-                    ((2 * a0 as u32 + 5 * a1 as u32) / 7) as u8,
-                    ((1 * a0 as u32 + 6 * a1 as u32) / 7) as u8,
-                ]
-            } else {
-                [
-                    a0,
-                    a1,
-                    ((4 * a0 as u32 + 1 * a1 as u32) / 5) as u8,
-                    ((3 * a0 as u32 + 2 * a1 as u32) / 5) as u8,
-                    ((2 * a0 as u32 + 3 * a1 as u32) / 5) as u8,
-                    ((1 * a0 as u32 + 4 * a1 as u32) / 5) as u8,
-                    0,
-                    255,
-                ]
-            };
+// Quantize every channel to the given bit depth (0 bits = channel unused).
+// Returns the quantized levels, not rescaled values.
+fn quantize(data: &[u8], width: u32, height: u32, bits: [u32; 4], dither: Dither) -> Vec<[u16; 4]> {
+    let width = width as usize;
+    let height = height as usize;
+    let mut output = vec![[0u16; 4]; width * height];
+
+    for (c, &channel_bits) in bits.iter().enumerate() {
+        if channel_bits == 0 {
+            continue;
+        }
 
-            // Read alpha indices (6 bytes = 48 bits for 16 pixels at 3 bits each)
-            let alpha_bits: u64 = (block_data[2] as u64)
-                | ((block_data[3] as u64) << 8)
-                | ((block_data[4] as u64) << 16)
-                | ((block_data[5] as u64) << 24)
-                | ((block_data[6] as u64) << 32)
-                | ((block_data[7] as u64) << 40);
-
-            // Read color data
-            let color_data = &block_data[8..16];
-            let c0 = u16::from_le_bytes([color_data[0], color_data[1]]);
-            let c1 = u16::from_le_bytes([color_data[2], color_data[3]]);
-
-            let color0 = decode_565(c0);
-            let color1 = decode_565(c1);
-
-            let colors = [
-                color0,
-                color1,
-                interpolate_color(&color0, &color1, 1, 3),
-                interpolate_color(&color0, &color1, 2, 3),
-            ];
-
-            let color_indices =
-                u32::from_le_bytes([color_data[4], color_data[5], color_data[6], color_data[7]]);
-
-            for py in 0..4 {
-                for px in 0..4 {
-                    let x = bx * 4 + px;
-                    let y = by * 4 + py;
-
-                    if x < width && y < height {
-                        let pixel_index = py * 4 + px;
-                        let color_index = ((color_indices >> (pixel_index * 2)) & 0x3) as usize;
-                        let alpha_index = ((alpha_bits >> (pixel_index * 3)) & 0x7) as usize;
-
-                        let color = &colors[color_index];
-                        let alpha = alphas[alpha_index];
-
-                        let output_index = ((y * width + x) * 4) as usize;
-                        output[output_index] = color[0];
-                        output[output_index + 1] = color[1];
-                        output[output_index + 2] = color[2];
-                        output[output_index + 3] = alpha;
+        let max_level = ((1u32 << channel_bits) - 1) as f32;
+        let step = 255.0 / max_level;
+        // 1-bit channels are cutout alpha, dithering those just makes noise
+        let dither = if channel_bits == 1 {
+            Dither::None
+        } else {
+            dither
+        };
+
+        // Error diffusion carries the current and next row
+        let mut error_row = vec![0.0f32; width + 2];
+        let mut next_error_row = vec![0.0f32; width + 2];
+
+        for y in 0..height {
+            for x in 0..width {
+                let index = y * width + x;
+                let mut value = data[index * 4 + c] as f32;
+
+                match dither {
+                    Dither::None => {}
+                    Dither::Ordered => {
+                        let threshold = BAYER_4X4[y % 4][x % 4] as f32;
+                        value += ((threshold + 0.5) / 16.0 - 0.5) * step;
                     }
+                    Dither::ErrorDiffusion => value += error_row[x + 1],
+                }
+
+                let level = (value / step).round().clamp(0.0, max_level);
+                output[index][c] = level as u16;
+
+                if dither == Dither::ErrorDiffusion {
+                    let error = value - level * step;
+                    error_row[x + 2] += error * 7.0 / 16.0;
+                    next_error_row[x] += error * 3.0 / 16.0;
+                    next_error_row[x + 1] += error * 5.0 / 16.0;
+                    next_error_row[x + 2] += error / 16.0;
                 }
             }
+
+            std::mem::swap(&mut error_row, &mut next_error_row);
+            next_error_row.fill(0.0);
         }
     }
 
+    output
+}
+
+// Pixels converted per parallel task, small images stay on one thread
+const PIXELS_PER_TASK: usize = 16 * 1024;
+
+// Convert pixels of N bytes each to RGBA8, in parallel over runs of pixels
+fn convert_pixels<const N: usize>(
+    data: &[u8],
+    output: &mut [u8],
+    convert: impl Fn([u8; N]) -> [u8; 4] + Sync,
+) {
+    output
+        .par_chunks_mut(PIXELS_PER_TASK * 4)
+        .zip(data.par_chunks(PIXELS_PER_TASK * N))
+        .for_each(|(output, data)| {
+            for (pixel, bytes) in output.chunks_exact_mut(4).zip(data.chunks_exact(N)) {
+                pixel.copy_from_slice(&convert(bytes.try_into().unwrap()));
+            }
+        });
+}
+
+// Color half of a DXT block. DXT1 with 1-bit alpha switches to three colors and
+// transparent black when the endpoints aren't in descending order.
+fn decode_dxt_color_block(block: &[u8], one_bit_alpha: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (color0, color1) = (decode_565(c0), decode_565(c1));
+
+    let colors = if c0 > c1 || !one_bit_alpha {
+        [
+            color0,
+            color1,
+            interpolate_color(&color0, &color1, 1, 3),
+            interpolate_color(&color0, &color1, 2, 3),
+        ]
+    } else {
+        [
+            color0,
+            color1,
+            interpolate_color(&color0, &color1, 1, 2),
+            [0, 0, 0, 0],
+        ]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|i| colors[(indices >> (i * 2)) as usize & 3])
+}
+
+// Decode a block compressed image one 4x4 block at a time, dropping the pixels of
+// partial blocks that fall outside the image. Rows of blocks decode in parallel.
+fn decode_blocks<T: Copy + Send + Sync>(
+    data: &[u8],
+    format: VtfFormat,
+    width: u32,
+    height: u32,
+    output: &mut [T],
+    decode: impl Fn(&[u8]) -> [[T; 4]; 16] + Sync,
+) -> VtfResult<()> {
+    let block_size = format.block_size().unwrap_or(16) as usize;
+    let (block_width, block_height) = (width.div_ceil(4), height.div_ceil(4));
+    let needed = (block_width * block_height) as usize * block_size;
+    if data.len() < needed {
+        return Err(VtfError::InvalidData(format!(
+            "Expected {} bytes of {:?} data for {}x{}, got {}",
+            needed,
+            format,
+            width,
+            height,
+            data.len()
+        )));
+    }
+    if needed == 0 {
+        return Ok(());
+    }
+
+    // Each row of blocks fills four rows of pixels, fewer at the bottom edge
+    let row_len = width as usize * 4;
+    let width = width as usize;
+    output
+        .par_chunks_mut(row_len * 4)
+        .zip(data[..needed].par_chunks(block_width as usize * block_size))
+        .for_each(|(rows, blocks)| {
+            for (bx, block) in blocks.chunks_exact(block_size).enumerate() {
+                let pixels = decode(block);
+                let x = bx * 4;
+                let columns = (width - x).min(4);
+                for (py, row) in rows.chunks_exact_mut(row_len).enumerate() {
+                    row[x * 4..(x + columns) * 4]
+                        .copy_from_slice(pixels[py * 4..py * 4 + columns].as_flattened());
+                }
+            }
+        });
     Ok(())
 }
 
+// One channel block of ATI1N/ATI2N, laid out like DXT5 alpha
+fn decode_bc4_block(block: &[u8]) -> [u8; 16] {
+    let (v0, v1) = (block[0] as u32, block[1] as u32);
+    let palette: [u32; 8] = if v0 > v1 {
+        std::array::from_fn(|i| match i {
+            0 => v0,
+            1 => v1,
+            _ => ((8 - i as u32) * v0 + (i as u32 - 1) * v1) / 7,
+        })
+    } else {
+        std::array::from_fn(|i| match i {
+            0 => v0,
+            1 => v1,
+            6 => 0,
+            7 => 255,
+            _ => ((6 - i as u32) * v0 + (i as u32 - 1) * v1) / 5,
+        })
+    };
+
+    let mut bytes = [0u8; 8];
+    bytes[..6].copy_from_slice(&block[2..8]);
+    let bits = u64::from_le_bytes(bytes);
+    std::array::from_fn(|i| palette[(bits >> (i * 3)) as usize & 7] as u8)
+}
+
 // Decode RGB565 color
-fn decode_565(color: u16) -> [u8; 4] {
+pub(super) fn decode_565(color: u16) -> [u8; 4] {
     let r = ((color >> 11) & 0x1F) as u32;
     let g = ((color >> 5) & 0x3F) as u32;
     let b = (color & 0x1F) as u32;
@@ -490,7 +710,7 @@ fn decode_565(color: u16) -> [u8; 4] {
 }
 
 // Interpolate between two colors
-fn interpolate_color(c0: &[u8; 4], c1: &[u8; 4], num: u32, denom: u32) -> [u8; 4] {
+pub(super) fn interpolate_color(c0: &[u8; 4], c1: &[u8; 4], num: u32, denom: u32) -> [u8; 4] {
     [
         ((c0[0] as u32 * (denom - num) + c1[0] as u32 * num) / denom) as u8,
         ((c0[1] as u32 * (denom - num) + c1[1] as u32 * num) / denom) as u8,
@@ -500,7 +720,7 @@ fn interpolate_color(c0: &[u8; 4], c1: &[u8; 4], num: u32, denom: u32) -> [u8; 4
 }
 
 // Convert half-precision float to single-precision float
-fn half_to_float(h: u16) -> f32 {
+pub(super) fn half_to_float(h: u16) -> f32 {
     let sign = (h >> 15) & 0x1;
     let exponent = (h >> 10) & 0x1F;
     let mantissa = h & 0x3FF;
@@ -533,6 +753,48 @@ fn half_to_float(h: u16) -> f32 {
     }
 }
 
+// Convert single-precision float to half-precision (round to nearest even)
+pub(super) fn float_to_half(f: f32) -> u16 {
+    let bits = f.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7FFFFF;
+
+    if exponent == 0xFF {
+        // Inf stays Inf, NaN stays NaN
+        return sign | 0x7C00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 31 {
+        return sign | 0x7C00;
+    }
+
+    if half_exponent <= 0 {
+        // Denormalized (or flushed to zero)
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x800000;
+        let shift = (14 - half_exponent) as u32;
+        let mut half_mantissa = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        if remainder > halfway || (remainder == halfway && half_mantissa & 1 != 0) {
+            half_mantissa += 1;
+        }
+        return sign | half_mantissa as u16;
+    }
+
+    let mut half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1FFF;
+    if remainder > 0x1000 || (remainder == 0x1000 && half & 1 != 0) {
+        // Carry may roll over into the exponent, which is exactly right
+        half += 1;
+    }
+    sign | half as u16
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -556,4 +818,221 @@ mod tests {
         assert!((half_to_float(0x0000) - 0.0).abs() < 0.001); // 0.0
         assert!((half_to_float(0x4000) - 2.0).abs() < 0.001); // 2.0
     }
+
+    #[test]
+    fn test_float_to_half() {
+        assert_eq!(float_to_half(1.0), 0x3C00);
+        assert_eq!(float_to_half(2.0), 0x4000);
+        assert_eq!(float_to_half(-0.5), 0xB800);
+        assert_eq!(float_to_half(1.0e9), 0x7C00);
+        for h in [0x0001u16, 0x03FF, 0x3555, 0x7BFF] {
+            assert_eq!(float_to_half(half_to_float(h)), h);
+        }
+    }
+
+    #[test]
+    fn test_convert_from_rgba_round_trip() {
+        // Every 8-bit-per-channel format should round-trip exactly
+        let source: Vec<u8> = (0..64u32)
+            .flat_map(|i| [(i * 4) as u8, (255 - i * 3) as u8, (i * 7 % 256) as u8, 255])
+            .collect();
+
+        for format in [
+            VtfFormat::Rgba8888,
+            VtfFormat::Abgr8888,
+            VtfFormat::Argb8888,
+            VtfFormat::Bgra8888,
+            VtfFormat::Bgrx8888,
+            VtfFormat::Rgb888,
+            VtfFormat::Bgr888,
+            VtfFormat::Uvwq8888,
+            VtfFormat::Rgba16161616,
+            VtfFormat::Rgb888BlueScreen,
+            VtfFormat::Bgr888BlueScreen,
+        ] {
+            let raw = convert_from_rgba(&source, format, 8, 8, Dither::None).unwrap();
+            assert_eq!(raw.len(), format.compute_image_size(8, 8) as usize);
+            let decoded = convert_to_rgba(&raw, format, 8, 8).unwrap();
+            assert_eq!(decoded, source, "{:?}", format);
+        }
+
+        // Low bit depth formats are off by at most one quantization step
+        for (format, tolerance) in [
+            (VtfFormat::Rgb565, 9),
+            (VtfFormat::Bgr565, 9),
+            (VtfFormat::Bgra4444, 18),
+            (VtfFormat::Bgra5551, 9),
+            (VtfFormat::Rgba16161616F, 1),
+        ] {
+            for dither in [Dither::None, Dither::Ordered, Dither::ErrorDiffusion] {
+                let raw = convert_from_rgba(&source, format, 8, 8, dither).unwrap();
+                let decoded = convert_to_rgba(&raw, format, 8, 8).unwrap();
+                for (a, b) in source.iter().zip(&decoded) {
+                    assert!(
+                        (*a as i32 - *b as i32).abs() <= tolerance,
+                        "{:?} {:?}: {} vs {}",
+                        format,
+                        dither,
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hdr_round_trip() {
+        // Values above 1.0 survive the half float format, 16-bit integers stay exact
+        let source: Vec<f32> = (0..16).map(|i| i as f32 * 0.75).collect();
+        let raw =
+            convert_from_rgba_f32(&source, VtfFormat::Rgba16161616F, 2, 2, Dither::None).unwrap();
+        assert_eq!(
+            convert_to_rgba_f32(&raw, VtfFormat::Rgba16161616F, 2, 2).unwrap(),
+            source
+        );
+
+        let source: Vec<u16> = (0..16).map(|i| i * 4099).collect();
+        let raw: Vec<u8> = source.iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(
+            convert_to_rgba16(&raw, VtfFormat::Rgba16161616, 2, 2).unwrap(),
+            source
+        );
+        let floats = convert_to_rgba_f32(&raw, VtfFormat::Rgba16161616, 2, 2).unwrap();
+        let encoded =
+            convert_from_rgba_f32(&floats, VtfFormat::Rgba16161616, 2, 2, Dither::None).unwrap();
+        assert_eq!(encoded, raw);
+
+        // Short buffers are an error, not a panic
+        for format in [VtfFormat::Rgba16161616F, VtfFormat::Rgba16161616] {
+            assert!(matches!(
+                convert_to_rgba_f32(&raw[..31], format, 2, 2),
+                Err(VtfError::InvalidData(_))
+            ));
+            assert!(matches!(
+                convert_to_rgba16(&raw[..31], format, 2, 2),
+                Err(VtfError::InvalidData(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_dithering_preserves_average() {
+        // A flat value between two 565 levels should average out when dithered
+        let source = [100u8, 100, 100, 255].repeat(16 * 16);
+        for dither in [Dither::Ordered, Dither::ErrorDiffusion] {
+            let raw = convert_from_rgba(&source, VtfFormat::Rgb565, 16, 16, dither).unwrap();
+            let decoded = convert_to_rgba(&raw, VtfFormat::Rgb565, 16, 16).unwrap();
+            let average = decoded.chunks(4).map(|p| p[0] as f32).sum::<f32>() / (16.0 * 16.0);
+            assert!((average - 100.0).abs() < 1.5, "{:?}: {}", dither, average);
+        }
+    }
+
+    // Every format convert_to_rgba decodes
+    const DECODED_FORMATS: [VtfFormat; 30] = [
+        VtfFormat::Rgba8888,
+        VtfFormat::Abgr8888,
+        VtfFormat::Rgb888,
+        VtfFormat::Bgr888,
+        VtfFormat::Rgb565,
+        VtfFormat::I8,
+        VtfFormat::Ia88,
+        VtfFormat::A8,
+        VtfFormat::Rgb888BlueScreen,
+        VtfFormat::Bgr888BlueScreen,
+        VtfFormat::Argb8888,
+        VtfFormat::Bgra8888,
+        VtfFormat::Dxt1,
+        VtfFormat::Dxt3,
+        VtfFormat::Dxt5,
+        VtfFormat::Bgrx8888,
+        VtfFormat::Bgr565,
+        VtfFormat::Bgrx5551,
+        VtfFormat::Bgra4444,
+        VtfFormat::Dxt1OneBitAlpha,
+        VtfFormat::Bgra5551,
+        VtfFormat::Uv88,
+        VtfFormat::Uvwq8888,
+        VtfFormat::Rgba16161616F,
+        VtfFormat::Rgba16161616,
+        VtfFormat::Uvlx8888,
+        VtfFormat::Ati2n,
+        VtfFormat::Ati1n,
+        VtfFormat::Bc7,
+        VtfFormat::Bc6h,
+    ];
+
+    // Xorshift bytes, so every palette mode and bit pattern shows up
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545F491u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_scalar_reference() {
+        // Partial blocks, and enough pixels to split into several parallel tasks
+        for format in DECODED_FORMATS {
+            for (width, height) in [(1, 1), (3, 5), (257, 129), (300, 300)] {
+                let data = noise(format.compute_image_size(width, height) as usize);
+                assert_eq!(
+                    convert_to_rgba(&data, format, width, height).unwrap(),
+                    reference::convert_to_rgba(&data, format, width, height).unwrap(),
+                    "{:?} at {}x{}",
+                    format,
+                    width,
+                    height
+                );
+            }
+        }
+
+        assert!(convert_to_rgba(&[0; 15], VtfFormat::Rgba8888, 2, 2).is_err());
+    }
+
+    // cargo test --release bench_convert_to_rgba -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_convert_to_rgba() {
+        const ROUNDS: u32 = 10;
+        type Converter = fn(&[u8], VtfFormat, u32, u32) -> VtfResult<Vec<u8>>;
+        let (width, height) = (2048, 2048);
+        for format in [
+            VtfFormat::Bgra8888,
+            VtfFormat::Bgr888,
+            VtfFormat::Rgb565,
+            VtfFormat::Dxt1,
+            VtfFormat::Dxt5,
+            VtfFormat::Ati2n,
+            VtfFormat::Bc7,
+        ] {
+            let data = noise(format.compute_image_size(width, height) as usize);
+            let time = |convert: Converter| {
+                // Warm up the thread pool and caches first
+                convert(&data, format, width, height).unwrap();
+                let start = std::time::Instant::now();
+                for _ in 0..ROUNDS {
+                    convert(&data, format, width, height).unwrap();
+                }
+                start.elapsed() / ROUNDS
+            };
+
+            let before = time(reference::convert_to_rgba);
+            let after = time(convert_to_rgba);
+            println!(
+                "{:?} {}x{}: {:?} -> {:?} ({:.1}x)",
+                format,
+                width,
+                height,
+                before,
+                after,
+                before.as_secs_f64() / after.as_secs_f64()
+            );
+        }
+    }
 }
@@ -0,0 +1,537 @@
+//! The single threaded loops over pixels and blocks that convert_to_rgba used before
+//! it went parallel. The per-pixel and per-block decoders are shared with formats.rs,
+//! only the loops around them are frozen here. Kept for the bit-exact tests and the
+//! benchmark against them.
+// Frozen as it was, lints included
+#![allow(
+    clippy::if_same_then_else,
+    clippy::identity_op,
+    clippy::manual_div_ceil
+)]
+
+use super::{ImageFormat, decode_565, decode_bc4_block, half_to_float, interpolate_color};
+use crate::vtf::bptc::{decode_bc6h_block, decode_bc7_block};
+use crate::vtf::header::VtfFormat;
+use crate::vtf::{VtfError, VtfResult};
+
+// The single threaded, per-pixel conversion convert_to_rgba replaced
+pub fn convert_to_rgba(
+    data: &[u8],
+    format: VtfFormat,
+    width: u32,
+    height: u32,
+) -> VtfResult<Vec<u8>> {
+    let pixel_count = (width * height) as usize;
+    let mut output = vec![0u8; pixel_count * 4];
+
+    match format {
+        VtfFormat::Rgba8888 => {
+            output.copy_from_slice(&data[..pixel_count * 4]);
+        }
+
+        VtfFormat::Abgr8888 => {
+            for i in 0..pixel_count {
+                output[i * 4] = data[i * 4 + 3]; // R from A
+                output[i * 4 + 1] = data[i * 4 + 2]; // G from B
+                output[i * 4 + 2] = data[i * 4 + 1]; // B from G
+                output[i * 4 + 3] = data[i * 4]; // A from R position
+            }
+        }
+
+        VtfFormat::Argb8888 => {
+            for i in 0..pixel_count {
+                output[i * 4] = data[i * 4 + 1]; // R
+                output[i * 4 + 1] = data[i * 4 + 2]; // G
+                output[i * 4 + 2] = data[i * 4 + 3]; // B
+                output[i * 4 + 3] = data[i * 4]; // A
+            }
+        }
+
+        VtfFormat::Bgra8888 => {
+            for i in 0..pixel_count {
+                output[i * 4] = data[i * 4 + 2]; // R from B
+                output[i * 4 + 1] = data[i * 4 + 1]; // G
+                output[i * 4 + 2] = data[i * 4]; // B from R position
+                output[i * 4 + 3] = data[i * 4 + 3]; // A
+            }
+        }
+
+        VtfFormat::Bgrx8888 => {
+            for i in 0..pixel_count {
+                output[i * 4] = data[i * 4 + 2]; // R from B
+                output[i * 4 + 1] = data[i * 4 + 1]; // G
+                output[i * 4 + 2] = data[i * 4]; // B from R position
+                output[i * 4 + 3] = 255; // A = opaque
+            }
+        }
+
+        VtfFormat::Rgb888 => {
+            for i in 0..pixel_count {
+                output[i * 4] = data[i * 3];
+                output[i * 4 + 1] = data[i * 3 + 1];
+                output[i * 4 + 2] = data[i * 3 + 2];
+                output[i * 4 + 3] = 255;
+            }
+        }
+
+        VtfFormat::Bgr888 => {
+            for i in 0..pixel_count {
+                output[i * 4] = data[i * 3 + 2]; // R from B
+                output[i * 4 + 1] = data[i * 3 + 1]; // G
+                output[i * 4 + 2] = data[i * 3]; // B from R position
+                output[i * 4 + 3] = 255;
+            }
+        }
+
+        VtfFormat::Bgr888BlueScreen => {
+            for i in 0..pixel_count {
+                let b = data[i * 3];
+                let g = data[i * 3 + 1];
+                let r = data[i * 3 + 2];
+                output[i * 4] = r;
+                output[i * 4 + 1] = g;
+                output[i * 4 + 2] = b;
+                // Same blue screen rule as the RGB variant
+                output[i * 4 + 3] = if r == 0 && g == 0 && b == 255 { 0 } else { 255 };
+            }
+        }
+
+        VtfFormat::Rgb888BlueScreen => {
+            for i in 0..pixel_count {
+                let r = data[i * 3];
+                let g = data[i * 3 + 1];
+                let b = data[i * 3 + 2];
+                output[i * 4] = r;
+                output[i * 4 + 1] = g;
+                output[i * 4 + 2] = b;
+                // Blue screen: if pure blue, make transparent
+                output[i * 4 + 3] = if r == 0 && g == 0 && b == 255 { 0 } else { 255 };
+            }
+        }
+
+        VtfFormat::Rgb565 => {
+            for i in 0..pixel_count {
+                let pixel = u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+                output[i * 4] = (((pixel >> 11) & 0x1F) as u32 * 255 / 31) as u8;
+                output[i * 4 + 1] = (((pixel >> 5) & 0x3F) as u32 * 255 / 63) as u8;
+                output[i * 4 + 2] = ((pixel & 0x1F) as u32 * 255 / 31) as u8;
+                output[i * 4 + 3] = 255;
+            }
+        }
+
+        VtfFormat::Bgr565 => {
+            for i in 0..pixel_count {
+                let pixel = u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+                output[i * 4 + 2] = (((pixel >> 11) & 0x1F) as u32 * 255 / 31) as u8;
+                output[i * 4 + 1] = (((pixel >> 5) & 0x3F) as u32 * 255 / 63) as u8;
+                output[i * 4] = ((pixel & 0x1F) as u32 * 255 / 31) as u8;
+                output[i * 4 + 3] = 255;
+            }
+        }
+
+        VtfFormat::Bgra4444 => {
+            for i in 0..pixel_count {
+                let pixel = u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+                output[i * 4 + 2] = (((pixel >> 12) & 0xF) as u32 * 17) as u8;
+                output[i * 4 + 1] = (((pixel >> 8) & 0xF) as u32 * 17) as u8;
+                output[i * 4] = (((pixel >> 4) & 0xF) as u32 * 17) as u8;
+                output[i * 4 + 3] = ((pixel & 0xF) as u32 * 17) as u8;
+            }
+        }
+        // fuck me sideways
+        // thank god copy pasting code saved my life
+        VtfFormat::Bgra5551 | VtfFormat::Bgrx5551 => {
+            for i in 0..pixel_count {
+                let pixel = u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+                output[i * 4 + 2] = (((pixel >> 10) & 0x1F) as u32 * 255 / 31) as u8;
+                output[i * 4 + 1] = (((pixel >> 5) & 0x1F) as u32 * 255 / 31) as u8;
+                output[i * 4] = ((pixel & 0x1F) as u32 * 255 / 31) as u8;
+                output[i * 4 + 3] = if format == VtfFormat::Bgrx5551 {
+                    255
+                } else if pixel & 0x8000 != 0 {
+                    255
+                } else {
+                    0
+                };
+            }
+        }
+
+        VtfFormat::I8 => {
+            for i in 0..pixel_count {
+                let intensity = data[i];
+                output[i * 4] = intensity;
+                output[i * 4 + 1] = intensity;
+                output[i * 4 + 2] = intensity;
+                output[i * 4 + 3] = 255;
+            }
+        }
+
+        VtfFormat::Ia88 => {
+            for i in 0..pixel_count {
+                let intensity = data[i * 2];
+                let alpha = data[i * 2 + 1];
+                output[i * 4] = intensity;
+                output[i * 4 + 1] = intensity;
+                output[i * 4 + 2] = intensity;
+                output[i * 4 + 3] = alpha;
+            }
+        }
+
+        VtfFormat::A8 => {
+            for i in 0..pixel_count {
+                output[i * 4] = 255;
+                output[i * 4 + 1] = 255;
+                output[i * 4 + 2] = 255;
+                output[i * 4 + 3] = data[i];
+            }
+        }
+
+        VtfFormat::Uv88 => {
+            for i in 0..pixel_count {
+                output[i * 4] = data[i * 2]; // U -> R
+                output[i * 4 + 1] = data[i * 2 + 1]; // V -> G
+                output[i * 4 + 2] = 128; // B = neutral
+                output[i * 4 + 3] = 255;
+            }
+        }
+
+        VtfFormat::Uvwq8888 | VtfFormat::Uvlx8888 => {
+            output.copy_from_slice(&data[..pixel_count * 4]);
+        }
+
+        VtfFormat::Dxt1 | VtfFormat::Dxt1OneBitAlpha => {
+            decode_dxt1(
+                data,
+                width,
+                height,
+                &mut output,
+                format == VtfFormat::Dxt1OneBitAlpha,
+            )?;
+        }
+
+        VtfFormat::Dxt3 => {
+            decode_dxt3(data, width, height, &mut output)?;
+        }
+
+        VtfFormat::Dxt5 => {
+            decode_dxt5(data, width, height, &mut output)?;
+        }
+
+        VtfFormat::Ati1n => {
+            decode_blocks(data, format, width, height, &mut output, |block| {
+                decode_bc4_block(block).map(|v| [v, v, v, 255])
+            })?;
+        }
+
+        VtfFormat::Ati2n => {
+            // Z is left for the shader to rebuild, like Uv88
+            decode_blocks(data, format, width, height, &mut output, |block| {
+                let (x, y) = (decode_bc4_block(&block[..8]), decode_bc4_block(&block[8..]));
+                std::array::from_fn(|i| [x[i], y[i], 128, 255])
+            })?;
+        }
+
+        VtfFormat::Bc7 => {
+            decode_blocks(data, format, width, height, &mut output, decode_bc7_block)?;
+        }
+
+        VtfFormat::Bc6h => {
+            let mut floats = vec![0.0; pixel_count * 4];
+            decode_blocks(data, format, width, height, &mut floats, decode_bc6h_block)?;
+            for (value, float) in output.iter_mut().zip(floats) {
+                *value = (float.clamp(0.0, 1.0) * 255.0) as u8;
+            }
+        }
+
+        VtfFormat::Rgba16161616F => {
+            // Convert 16-bit float to 8-bit
+            for i in 0..pixel_count {
+                for c in 0..4 {
+                    let bytes = [data[i * 8 + c * 2], data[i * 8 + c * 2 + 1]];
+                    let f16 = half_to_float(u16::from_le_bytes(bytes));
+                    output[i * 4 + c] = (f16.clamp(0.0, 1.0) * 255.0) as u8;
+                }
+            }
+        }
+
+        VtfFormat::Rgba16161616 => {
+            for i in 0..pixel_count {
+                for c in 0..4 {
+                    let bytes = [data[i * 8 + c * 2], data[i * 8 + c * 2 + 1]];
+                    let value = u16::from_le_bytes(bytes);
+                    output[i * 4 + c] = (value >> 8) as u8;
+                }
+            }
+        }
+
+        VtfFormat::None | VtfFormat::P8 => {
+            return Err(VtfError::UnsupportedFormat(ImageFormat::Rgba8));
+        }
+    }
+
+    Ok(output)
+}
+
+// Decode DXT1 compressed data
+fn decode_dxt1(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    output: &mut [u8],
+    has_alpha: bool,
+) -> VtfResult<()> {
+    let block_width = (width + 3) / 4;
+    let block_height = (height + 3) / 4;
+
+    for by in 0..block_height {
+        for bx in 0..block_width {
+            let block_index = (by * block_width + bx) as usize;
+            let block_data = &data[block_index * 8..(block_index + 1) * 8];
+
+            // Read color endpoints
+            let c0 = u16::from_le_bytes([block_data[0], block_data[1]]);
+            let c1 = u16::from_le_bytes([block_data[2], block_data[3]]);
+
+            // Decode colors
+            let color0 = decode_565(c0);
+            let color1 = decode_565(c1);
+
+            // Generate color palette
+            let colors = if c0 > c1 || !has_alpha {
+                [
+                    color0,
+                    color1,
+                    interpolate_color(&color0, &color1, 1, 3),
+                    interpolate_color(&color0, &color1, 2, 3),
+                ]
+            } else {
+                [
+                    color0,
+                    color1,
+                    interpolate_color(&color0, &color1, 1, 2),
+                    [0, 0, 0, 0], // Transparent
+                ]
+            };
+
+            // Read color indices
+            let indices =
+                u32::from_le_bytes([block_data[4], block_data[5], block_data[6], block_data[7]]);
+
+            // Decode them pixels
+            for py in 0..4 {
+                for px in 0..4 {
+                    let x = bx * 4 + px;
+                    let y = by * 4 + py;
+
+                    if x < width && y < height {
+                        let pixel_index = py * 4 + px;
+                        let color_index = ((indices >> (pixel_index * 2)) & 0x3) as usize;
+                        let color = &colors[color_index];
+
+                        let output_index = ((y * width + x) * 4) as usize;
+                        output[output_index] = color[0];
+                        output[output_index + 1] = color[1];
+                        output[output_index + 2] = color[2];
+                        output[output_index + 3] = color[3];
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Decode DXT3 compressed data
+fn decode_dxt3(data: &[u8], width: u32, height: u32, output: &mut [u8]) -> VtfResult<()> {
+    let block_width = (width + 3) / 4;
+    let block_height = (height + 3) / 4;
+
+    for by in 0..block_height {
+        for bx in 0..block_width {
+            let block_index = (by * block_width + bx) as usize;
+            let block_data = &data[block_index * 16..(block_index + 1) * 16];
+
+            // Read explicit alpha values (first 8 bytes)
+            let alpha_data = &block_data[0..8];
+
+            // Read color data (last 8 bytes)
+            let color_data = &block_data[8..16];
+
+            let c0 = u16::from_le_bytes([color_data[0], color_data[1]]);
+            let c1 = u16::from_le_bytes([color_data[2], color_data[3]]);
+
+            let color0 = decode_565(c0);
+            let color1 = decode_565(c1);
+
+            let colors = [
+                color0,
+                color1,
+                interpolate_color(&color0, &color1, 1, 3),
+                interpolate_color(&color0, &color1, 2, 3),
+            ];
+
+            let indices =
+                u32::from_le_bytes([color_data[4], color_data[5], color_data[6], color_data[7]]);
+
+            for py in 0..4 {
+                for px in 0..4 {
+                    let x = bx * 4 + px;
+                    let y = by * 4 + py;
+
+                    if x < width && y < height {
+                        let pixel_index = py * 4 + px;
+                        let color_index = ((indices >> (pixel_index * 2)) & 0x3) as usize;
+                        let color = &colors[color_index];
+
+                        // kill em all
+                        let alpha_byte_index = (pixel_index / 2) as usize;
+                        let alpha_nibble = if pixel_index % 2 == 0 {
+                            alpha_data[alpha_byte_index] & 0x0F
+                        } else {
+                            alpha_data[alpha_byte_index] >> 4
+                        };
+                        let alpha = alpha_nibble * 17; // Scale 0-15 to 0-255
+
+                        let output_index = ((y * width + x) * 4) as usize;
+                        output[output_index] = color[0];
+                        output[output_index + 1] = color[1];
+                        output[output_index + 2] = color[2];
+                        output[output_index + 3] = alpha;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Decode DXT5 compressed data
+fn decode_dxt5(data: &[u8], width: u32, height: u32, output: &mut [u8]) -> VtfResult<()> {
+    let block_width = (width + 3) / 4;
+    let block_height = (height + 3) / 4;
+
+    for by in 0..block_height {
+        for bx in 0..block_width {
+            let block_index = (by * block_width + bx) as usize;
+            let block_data = &data[block_index * 16..(block_index + 1) * 16];
+
+            // Read alpha endpoints
+            let a0 = block_data[0];
+            let a1 = block_data[1];
+
+            // Generate alpha palette
+            let alphas = if a0 > a1 {
+                [
+                    a0,
+                    a1,
+                    ((6 * a0 as u32 + 1 * a1 as u32) / 7) as u8,
+                    ((5 * a0 as u32 + 2 * a1 as u32) / 7) as u8,
+                    ((4 * a0 as u32 + 3 * a1 as u32) / 7) as u8,
+                    ((3 * a0 as u32 + 4 * a1 as u32) / 7) as u8,
+                    ((2 * a0 as u32 + 5 * a1 as u32) / 7) as u8,
+                    ((1 * a0 as u32 + 6 * a1 as u32) / 7) as u8,
+                ]
+            } else {
+                [
+                    a0,
+                    a1,
+                    ((4 * a0 as u32 + 1 * a1 as u32) / 5) as u8,
+                    ((3 * a0 as u32 + 2 * a1 as u32) / 5) as u8,
+                    ((2 * a0 as u32 + 3 * a1 as u32) / 5) as u8,
+                    ((1 * a0 as u32 + 4 * a1 as u32) / 5) as u8,
+                    0,
+                    255,
+                ]
+            };
+
+            // Read alpha indices (6 bytes = 48 bits for 16 pixels at 3 bits each)
+            let alpha_bits: u64 = (block_data[2] as u64)
+                | ((block_data[3] as u64) << 8)
+                | ((block_data[4] as u64) << 16)
+                | ((block_data[5] as u64) << 24)
+                | ((block_data[6] as u64) << 32)
+                | ((block_data[7] as u64) << 40);
+
+            // Read color data
+            let color_data = &block_data[8..16];
+            let c0 = u16::from_le_bytes([color_data[0], color_data[1]]);
+            let c1 = u16::from_le_bytes([color_data[2], color_data[3]]);
+
+            let color0 = decode_565(c0);
+            let color1 = decode_565(c1);
+
+            let colors = [
+                color0,
+                color1,
+                interpolate_color(&color0, &color1, 1, 3),
+                interpolate_color(&color0, &color1, 2, 3),
+            ];
+
+            let color_indices =
+                u32::from_le_bytes([color_data[4], color_data[5], color_data[6], color_data[7]]);
+
+            for py in 0..4 {
+                for px in 0..4 {
+                    let x = bx * 4 + px;
+                    let y = by * 4 + py;
+
+                    if x < width && y < height {
+                        let pixel_index = py * 4 + px;
+                        let color_index = ((color_indices >> (pixel_index * 2)) & 0x3) as usize;
+                        let alpha_index = ((alpha_bits >> (pixel_index * 3)) & 0x7) as usize;
+
+                        let color = &colors[color_index];
+                        let alpha = alphas[alpha_index];
+
+                        let output_index = ((y * width + x) * 4) as usize;
+                        output[output_index] = color[0];
+                        output[output_index + 1] = color[1];
+                        output[output_index + 2] = color[2];
+                        output[output_index + 3] = alpha;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Decode a block compressed image one 4x4 block at a time, dropping the pixels of
+// partial blocks that fall outside the image
+fn decode_blocks<T: Copy>(
+    data: &[u8],
+    format: VtfFormat,
+    width: u32,
+    height: u32,
+    output: &mut [T],
+    decode: impl Fn(&[u8]) -> [[T; 4]; 16],
+) -> VtfResult<()> {
+    let block_size = format.block_size().unwrap_or(16) as usize;
+    let (block_width, block_height) = (width.div_ceil(4), height.div_ceil(4));
+    let needed = (block_width * block_height) as usize * block_size;
+    if data.len() < needed {
+        return Err(VtfError::InvalidData(format!(
+            "Expected {} bytes of {:?} data for {}x{}, got {}",
+            needed,
+            format,
+            width,
+            height,
+            data.len()
+        )));
+    }
+
+    for (index, block) in data[..needed].chunks_exact(block_size).enumerate() {
+        let (bx, by) = (index as u32 % block_width, index as u32 / block_width);
+        for (pixel, color) in decode(block).iter().enumerate() {
+            let (x, y) = (bx * 4 + pixel as u32 % 4, by * 4 + pixel as u32 / 4);
+            if x < width && y < height {
+                let offset = ((y * width + x) * 4) as usize;
+                output[offset..offset + 4].copy_from_slice(color);
+            }
+        }
+    }
+    Ok(())
+}
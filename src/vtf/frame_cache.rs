@@ -0,0 +1,162 @@
+//! Least recently used cache of decoded frames, bounded by the bytes of pixel data
+//! it holds, so flipping between mips and frames doesn't decode them again
+
+use super::decoder::DecodedFrame;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+// Enough for a few full 4096x4096 frames
+pub(super) const DEFAULT_FRAME_CACHE_SIZE: usize = 256 * 1024 * 1024;
+
+// (mip, frame, slice)
+pub(super) type FrameKey = (u8, u16, u32);
+
+struct Entries {
+    frames: HashMap<FrameKey, Arc<DecodedFrame>>,
+    // Least recently used first
+    order: VecDeque<FrameKey>,
+    size: usize,
+    capacity: usize,
+}
+
+impl Entries {
+    fn evict_to(&mut self, capacity: usize) {
+        while self.size > capacity {
+            let Some(key) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(frame) = self.frames.remove(&key) {
+                self.size -= frame.data.len();
+            }
+        }
+    }
+
+    fn touch(&mut self, key: FrameKey) {
+        if let Some(index) = self.order.iter().position(|&k| k == key) {
+            self.order.remove(index);
+        }
+        self.order.push_back(key);
+    }
+}
+
+pub(super) struct FrameCache {
+    entries: Mutex<Entries>,
+}
+
+impl FrameCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(Entries {
+                frames: HashMap::new(),
+                order: VecDeque::new(),
+                size: 0,
+                capacity,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        // Entries are only swapped in whole, a poisoned cache is still consistent
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn get(&self, key: FrameKey) -> Option<Arc<DecodedFrame>> {
+        let mut entries = self.lock();
+        let frame = entries.frames.get(&key).cloned()?;
+        entries.touch(key);
+        Some(frame)
+    }
+
+    pub fn insert(&self, key: FrameKey, frame: Arc<DecodedFrame>) {
+        let mut entries = self.lock();
+        let size = frame.data.len();
+        if size > entries.capacity {
+            return;
+        }
+
+        if let Some(old) = entries.frames.insert(key, frame) {
+            entries.size -= old.data.len();
+        }
+        entries.size += size;
+        entries.touch(key);
+        let capacity = entries.capacity;
+        entries.evict_to(capacity);
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut entries = self.lock();
+        entries.capacity = capacity;
+        entries.evict_to(capacity);
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.lock().frames.len()
+    }
+}
+
+impl Default for FrameCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_FRAME_CACHE_SIZE)
+    }
+}
+
+// Clones start out empty, their header may be edited to no longer match the frames
+impl Clone for FrameCache {
+    fn clone(&self) -> Self {
+        Self::new(self.capacity())
+    }
+}
+
+impl fmt::Debug for FrameCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries = self.lock();
+        f.debug_struct("FrameCache")
+            .field("frames", &entries.frames.len())
+            .field("size", &entries.size)
+            .field("capacity", &entries.capacity)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(size: usize) -> Arc<DecodedFrame> {
+        Arc::new(DecodedFrame {
+            data: vec![0; size],
+            width: 1,
+            height: 1,
+            mipmap_level: 0,
+            frame: 0,
+        })
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = FrameCache::new(100);
+        cache.insert((0, 0, 0), frame(40));
+        cache.insert((1, 0, 0), frame(40));
+        // Touch the first so the second is the oldest
+        assert!(cache.get((0, 0, 0)).is_some());
+        cache.insert((2, 0, 0), frame(40));
+
+        assert!(cache.get((0, 0, 0)).is_some());
+        assert!(cache.get((1, 0, 0)).is_none());
+        assert!(cache.get((2, 0, 0)).is_some());
+
+        // Frames bigger than the whole cache are never kept
+        cache.insert((3, 0, 0), frame(101));
+        assert!(cache.get((3, 0, 0)).is_none());
+
+        cache.set_capacity(40);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.clone().len(), 0);
+    }
+}
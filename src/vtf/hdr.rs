@@ -0,0 +1,175 @@
+//! HDR tonemapping and float/16-bit image export
+
+use super::decoder::DecodedFrame;
+use std::path::Path;
+
+// Operator used to squeeze HDR values into the displayable range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tonemap {
+    // Clip everything above 1.0
+    #[default]
+    Clamp,
+    // x / (1 + x)
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl Tonemap {
+    pub fn from_index(index: i32) -> Self {
+        match index {
+            1 => Tonemap::Reinhard,
+            2 => Tonemap::Aces,
+            _ => Tonemap::Clamp,
+        }
+    }
+
+    fn apply(&self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        match self {
+            Tonemap::Clamp => x,
+            Tonemap::Reinhard => x / (1.0 + x),
+            Tonemap::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+        }
+        .min(1.0)
+    }
+}
+
+// Linear light to the sRGB transfer curve
+pub(super) fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// sRGB transfer curve back to linear light
+pub(super) fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl DecodedFrame<f32> {
+    // Scale by 2^exposure, tonemap and encode as 8-bit sRGB for display.
+    // Alpha is only clamped.
+    pub fn tonemap(&self, exposure: f32, tonemap: Tonemap) -> DecodedFrame {
+        let scale = exposure.exp2();
+        let data = self
+            .data
+            .chunks_exact(4)
+            .flat_map(|pixel| {
+                let color = |c: f32| {
+                    let value = linear_to_srgb(tonemap.apply(c * scale));
+                    (value * 255.0).round() as u8
+                };
+                [
+                    color(pixel[0]),
+                    color(pixel[1]),
+                    color(pixel[2]),
+                    (pixel[3].clamp(0.0, 1.0) * 255.0).round() as u8,
+                ]
+            })
+            .collect();
+
+        DecodedFrame {
+            data,
+            width: self.width,
+            height: self.height,
+            mipmap_level: self.mipmap_level,
+            frame: self.frame,
+        }
+    }
+
+    pub fn to_image(&self) -> image::DynamicImage {
+        let img = image::Rgba32FImage::from_raw(self.width, self.height, self.data.clone())
+            .expect("The pixels have betrayed us");
+        image::DynamicImage::ImageRgba32F(img)
+    }
+
+    // .exr keeps RGBA floats, .hdr keeps RGB floats (Radiance has no alpha),
+    // anything else gets 16-bit sRGB encoded RGBA clamped to 0..1
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), image::ImageError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        let img = self.to_image();
+        match extension.as_str() {
+            "exr" => img.save(path),
+            "hdr" => image::DynamicImage::ImageRgb32F(img.into_rgb32f()).save(path),
+            _ => {
+                let mut img = img.into_rgba32f();
+                for pixel in img.pixels_mut() {
+                    for value in &mut pixel.0[..3] {
+                        *value = linear_to_srgb(value.clamp(0.0, 1.0));
+                    }
+                }
+                image::DynamicImage::ImageRgba32F(img)
+                    .into_rgba16()
+                    .save(path)
+            }
+        }
+    }
+}
+
+impl DecodedFrame<u16> {
+    pub fn to_image(&self) -> image::DynamicImage {
+        let img = image::ImageBuffer::from_raw(self.width, self.height, self.data.clone())
+            .expect("The pixels have betrayed us");
+        image::DynamicImage::ImageRgba16(img)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), image::ImageError> {
+        self.to_image().save(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(data: Vec<f32>) -> DecodedFrame<f32> {
+        DecodedFrame {
+            width: (data.len() / 4) as u32,
+            height: 1,
+            data,
+            mipmap_level: 0,
+            frame: 0,
+        }
+    }
+
+    #[test]
+    fn test_tonemap_operators() {
+        let hdr = frame(vec![0.0, 0.5, 4.0, 2.0]);
+
+        let clamped = hdr.tonemap(0.0, Tonemap::Clamp);
+        assert_eq!(clamped.data, vec![0, 188, 255, 255]);
+
+        // Reinhard keeps highlights below white, one stop down halves the input
+        let reinhard = hdr.tonemap(-1.0, Tonemap::Reinhard);
+        let expected = (linear_to_srgb(2.0 / 3.0) * 255.0).round() as u8;
+        assert_eq!(reinhard.data[2], expected);
+
+        for tonemap in [Tonemap::Clamp, Tonemap::Reinhard, Tonemap::Aces] {
+            let mapped = hdr.tonemap(0.0, tonemap);
+            assert!(mapped.data[0] <= mapped.data[1] && mapped.data[1] <= mapped.data[2]);
+        }
+    }
+
+    #[test]
+    fn test_save_exr_keeps_range() {
+        let hdr = frame(vec![8.5, 0.25, 0.0, 1.0, 0.0, 1.0, 100.0, 0.5]);
+        let path = std::env::temp_dir().join("vfilex_test_hdr_export.exr");
+        hdr.save(&path).unwrap();
+
+        let loaded = image::open(&path).unwrap().into_rgba32f();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.into_raw(), hdr.data);
+    }
+}
@@ -8,7 +8,13 @@ use std::io::{Cursor, Read};
 
 pub const VTF_SIGNATURE: [u8; 4] = [0x56, 0x54, 0x46, 0x00];
 
-// VTF file version (we stopped at 7.5 because counting is hard)
+// Resource entry flag: the data field holds the value itself, not an offset
+pub const RESOURCE_FLAG_NO_DATA_CHUNK: u8 = 0x02;
+
+// Upper bound on resource entries, same as VTFLib (anything above is garbage)
+pub const MAX_RESOURCES: u32 = 32;
+
+// VTF file version (7.6 is Strata's, the rest are Valve's)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct VtfVersion {
     pub major: u32,
@@ -22,7 +28,7 @@ impl VtfVersion {
 
     // Check if this version is supported
     pub fn is_supported(&self) -> bool {
-        self.major == 7 && self.minor <= 5
+        self.major == 7 && self.minor <= 6
     }
 }
 
@@ -71,6 +77,93 @@ bitflags::bitflags! {
     }
 }
 
+// Known resource tags from the 7.3+ resource directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VtfResourceTag {
+    // Low-resolution thumbnail image ("\x01\0\0")
+    LowResImage,
+    // High-resolution image data, all mips/frames/faces/slices ("\x30\0\0")
+    HighResImage,
+    // Animated particle sheet ("\x10\0\0")
+    ParticleSheet,
+    // CRC of the source image, stored inline ("CRC")
+    Crc,
+    // Texture LOD clamp resolution, stored inline ("LOD")
+    LodControl,
+    // Extended texture settings flags, stored inline ("TSO")
+    ExtendedFlags,
+    // Arbitrary KeyValues text ("KVD")
+    KeyValues,
+    // Compression method and chunk sizes of 7.6 image data ("AXC")
+    AuxCompression,
+    // Anything else, kept so the directory can be round-tripped
+    Unknown([u8; 3]),
+}
+
+impl VtfResourceTag {
+    pub fn from_bytes(tag: [u8; 3]) -> Self {
+        match &tag {
+            b"\x01\0\0" => VtfResourceTag::LowResImage,
+            b"\x30\0\0" => VtfResourceTag::HighResImage,
+            b"\x10\0\0" => VtfResourceTag::ParticleSheet,
+            b"CRC" => VtfResourceTag::Crc,
+            b"LOD" => VtfResourceTag::LodControl,
+            b"TSO" => VtfResourceTag::ExtendedFlags,
+            b"KVD" => VtfResourceTag::KeyValues,
+            b"AXC" => VtfResourceTag::AuxCompression,
+            _ => VtfResourceTag::Unknown(tag),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 3] {
+        match self {
+            VtfResourceTag::LowResImage => *b"\x01\0\0",
+            VtfResourceTag::HighResImage => *b"\x30\0\0",
+            VtfResourceTag::ParticleSheet => *b"\x10\0\0",
+            VtfResourceTag::Crc => *b"CRC",
+            VtfResourceTag::LodControl => *b"LOD",
+            VtfResourceTag::ExtendedFlags => *b"TSO",
+            VtfResourceTag::KeyValues => *b"KVD",
+            VtfResourceTag::AuxCompression => *b"AXC",
+            VtfResourceTag::Unknown(tag) => *tag,
+        }
+    }
+}
+
+// Resource payload for writing: inline values live in the directory entry,
+// chunks are written after the image data with a 4-byte size prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VtfResourceData {
+    Inline(u32),
+    Chunk(Vec<u8>),
+}
+
+// A single entry of the resource directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VtfResourceEntry {
+    pub tag: VtfResourceTag,
+    pub flags: u8,
+    // Offset into the file, or the value itself when there is no data chunk
+    pub data: u32,
+}
+
+impl VtfResourceEntry {
+    // check if the data lives in the file rather than in the entry
+    pub fn has_data_chunk(&self) -> bool {
+        self.flags & RESOURCE_FLAG_NO_DATA_CHUNK == 0
+    }
+
+    // file offset of the resource data, if it has any
+    pub fn offset(&self) -> Option<u32> {
+        self.has_data_chunk().then_some(self.data)
+    }
+
+    // inline 4-byte value, for resources without a data chunk
+    pub fn inline_value(&self) -> Option<u32> {
+        (!self.has_data_chunk()).then_some(self.data)
+    }
+}
+
 // VTF format enumeration
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(i32)]
@@ -103,6 +196,12 @@ pub enum VtfFormat {
     Rgba16161616F = 24,
     Rgba16161616 = 25,
     Uvlx8888 = 26,
+    // 3Dc, from the Portal 2, Alien Swarm and CS:GO branches (VTFLib numbering)
+    Ati2n = 37,
+    Ati1n = 38,
+    // Strata Source
+    Bc7 = 70,
+    Bc6h = 71,
 }
 
 impl TryFrom<i32> for VtfFormat {
@@ -138,6 +237,10 @@ impl TryFrom<i32> for VtfFormat {
             24 => Ok(VtfFormat::Rgba16161616F),
             25 => Ok(VtfFormat::Rgba16161616),
             26 => Ok(VtfFormat::Uvlx8888),
+            37 => Ok(VtfFormat::Ati2n),
+            38 => Ok(VtfFormat::Ati1n),
+            70 => Ok(VtfFormat::Bc7),
+            71 => Ok(VtfFormat::Bc6h),
             _ => Err(VtfError::InvalidData(format!("Unknown format: {}", value))),
         }
     }
@@ -167,8 +270,12 @@ impl VtfFormat {
             | VtfFormat::Bgra5551
             | VtfFormat::Uv88 => 16,
             VtfFormat::I8 | VtfFormat::P8 | VtfFormat::A8 => 8,
-            VtfFormat::Dxt1 | VtfFormat::Dxt1OneBitAlpha => 4,
-            VtfFormat::Dxt3 | VtfFormat::Dxt5 => 8,
+            VtfFormat::Dxt1 | VtfFormat::Dxt1OneBitAlpha | VtfFormat::Ati1n => 4,
+            VtfFormat::Dxt3
+            | VtfFormat::Dxt5
+            | VtfFormat::Ati2n
+            | VtfFormat::Bc7
+            | VtfFormat::Bc6h => 8,
             VtfFormat::Rgba16161616F | VtfFormat::Rgba16161616 => 64,
         }
     }
@@ -177,15 +284,47 @@ impl VtfFormat {
     pub fn is_compressed(&self) -> bool {
         matches!(
             self,
-            VtfFormat::Dxt1 | VtfFormat::Dxt3 | VtfFormat::Dxt5 | VtfFormat::Dxt1OneBitAlpha
+            VtfFormat::Dxt1
+                | VtfFormat::Dxt3
+                | VtfFormat::Dxt5
+                | VtfFormat::Dxt1OneBitAlpha
+                | VtfFormat::Ati1n
+                | VtfFormat::Ati2n
+                | VtfFormat::Bc7
+                | VtfFormat::Bc6h
+        )
+    }
+
+    // check if this format stores an alpha channel
+    pub fn has_alpha_channel(&self) -> bool {
+        matches!(
+            self,
+            VtfFormat::Rgba8888
+                | VtfFormat::Abgr8888
+                | VtfFormat::Argb8888
+                | VtfFormat::Bgra8888
+                | VtfFormat::Dxt3
+                | VtfFormat::Dxt5
+                | VtfFormat::Dxt1OneBitAlpha
+                | VtfFormat::Bgra4444
+                | VtfFormat::Bgra5551
+                | VtfFormat::A8
+                | VtfFormat::Ia88
+                | VtfFormat::Rgba16161616F
+                | VtfFormat::Rgba16161616
+                | VtfFormat::Bc7
         )
     }
 
     // get the block size for compressed formats
     pub fn block_size(&self) -> Option<u32> {
         match self {
-            VtfFormat::Dxt1 | VtfFormat::Dxt1OneBitAlpha => Some(8),
-            VtfFormat::Dxt3 | VtfFormat::Dxt5 => Some(16),
+            VtfFormat::Dxt1 | VtfFormat::Dxt1OneBitAlpha | VtfFormat::Ati1n => Some(8),
+            VtfFormat::Dxt3
+            | VtfFormat::Dxt5
+            | VtfFormat::Ati2n
+            | VtfFormat::Bc7
+            | VtfFormat::Bc6h => Some(16),
             _ => None,
         }
     }
@@ -237,6 +376,8 @@ pub struct VtfHeader {
     pub depth: u16,
     // Number of resources (version 7.3+)
     pub resource_count: u32,
+    // Resource directory (version 7.3+, empty before that)
+    pub resources: Vec<VtfResourceEntry>,
 }
 
 impl VtfHeader {
@@ -315,6 +456,32 @@ impl VtfHeader {
             0
         };
 
+        if resource_count > MAX_RESOURCES {
+            return Err(VtfError::InvalidData(format!(
+                "Too many resources: {}",
+                resource_count
+            )));
+        }
+
+        // read resource directory (version 7.3+)
+        let mut resources = Vec::with_capacity(resource_count as usize);
+        if resource_count > 0 {
+            // skip padding (8 bytes), entries start at offset 80
+            cursor.set_position(80);
+
+            for _ in 0..resource_count {
+                let mut tag = [0u8; 3];
+                cursor.read_exact(&mut tag)?;
+                let flags = cursor.read_u8()?;
+                let data = cursor.read_u32::<LittleEndian>()?;
+                resources.push(VtfResourceEntry {
+                    tag: VtfResourceTag::from_bytes(tag),
+                    flags,
+                    data,
+                });
+            }
+        }
+
         Ok(Self {
             version,
             header_size,
@@ -332,9 +499,44 @@ impl VtfHeader {
             low_res_height,
             depth,
             resource_count,
+            resources,
         })
     }
 
+    // find a resource entry by tag
+    pub fn resource(&self, tag: VtfResourceTag) -> Option<&VtfResourceEntry> {
+        self.resources.iter().find(|entry| entry.tag == tag)
+    }
+
+    // size of the low-resolution thumbnail in bytes
+    pub fn thumbnail_data_size(&self) -> u32 {
+        self.low_res_format
+            .compute_image_size(self.low_res_width as u32, self.low_res_height as u32)
+    }
+
+    // offset to the low-resolution thumbnail, None if the file doesn't store one
+    pub fn thumbnail_offset(&self) -> Option<u32> {
+        if self.resources.is_empty() {
+            Some(self.header_size)
+        } else {
+            self.resource(VtfResourceTag::LowResImage)
+                .and_then(|entry| entry.offset())
+        }
+    }
+
+    // offset to the start of the high-resolution image data
+    pub fn image_data_offset(&self) -> u32 {
+        // 7.3+ files point at it through the resource directory,
+        // older ones put it right after the thumbnail
+        match self
+            .resource(VtfResourceTag::HighResImage)
+            .and_then(|entry| entry.offset())
+        {
+            Some(offset) => offset,
+            None => self.header_size + self.thumbnail_data_size(),
+        }
+    }
+
     // calculate the size of a specific mipmap level
     pub fn mipmap_size(&self, level: u8) -> (u32, u32) {
         let divisor = 1u32 << level;
@@ -343,42 +545,56 @@ impl VtfHeader {
         (width, height)
     }
 
-    // calculate the data size for a specific mipmap level
+    // calculate the depth of a specific mipmap level (volume textures halve it too)
+    pub fn mipmap_depth(&self, level: u8) -> u32 {
+        (self.depth.max(1) as u32 >> level).max(1)
+    }
+
+    // number of faces stored per frame: 6 for cubemaps, plus a spheremap before 7.5
+    // unless the first frame is 0xFFFF
+    pub fn face_count(&self) -> u32 {
+        if !self.is_envmap() {
+            1
+        } else if self.version.minor < 5 && self.first_frame != 0xFFFF {
+            7
+        } else {
+            6
+        }
+    }
+
+    // calculate the data size of a single slice at a specific mipmap level
     pub fn mipmap_data_size(&self, level: u8) -> u32 {
         let (width, height) = self.mipmap_size(level);
         self.high_res_format.compute_image_size(width, height)
     }
 
+    // calculate the data size of one frame (all faces and slices) at a specific mipmap level
+    pub fn mipmap_frame_size(&self, level: u8) -> u32 {
+        self.mipmap_data_size(level) * self.face_count() * self.mipmap_depth(level)
+    }
+
     // calculate the offset to a specific mipmap level
     pub fn mipmap_offset(&self, level: u8, frame: u16) -> u32 {
-        let mut offset = self.header_size;
-
-        // add thumbnail size
-        offset += self
-            .low_res_format
-            .compute_image_size(self.low_res_width as u32, self.low_res_height as u32);
+        let mut offset = self.image_data_offset();
 
         // add all previous mipmap levels for all frames
         // VTF stores mipmaps from smallest to largest
         for mip in (level + 1..self.mipmap_count).rev() {
-            let size = self.mipmap_data_size(mip);
-            offset += size * self.frames as u32 * self.depth as u32;
+            offset += self.mipmap_frame_size(mip) * self.frames as u32;
         }
 
         // add previous frames at this mipmap level
-        offset += self.mipmap_data_size(level) * frame as u32 * self.depth as u32;
+        offset += self.mipmap_frame_size(level) * frame as u32;
 
         offset
     }
 
     // get the total size of all image data
     pub fn total_data_size(&self) -> u32 {
-        let mut size = self
-            .low_res_format
-            .compute_image_size(self.low_res_width as u32, self.low_res_height as u32);
+        let mut size = self.thumbnail_data_size();
 
         for level in 0..self.mipmap_count {
-            size += self.mipmap_data_size(level) * self.frames as u32 * self.depth as u32;
+            size += self.mipmap_frame_size(level) * self.frames as u32;
         }
 
         size
@@ -401,9 +617,18 @@ impl VtfHeader {
                     | VtfFormat::Bgra5551
                     | VtfFormat::A8
                     | VtfFormat::Ia88
+                    | VtfFormat::Bc7
             )
     }
 
+    // check if the image data holds more than 8 bits per channel
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self.high_res_format,
+            VtfFormat::Rgba16161616F | VtfFormat::Rgba16161616 | VtfFormat::Bc6h
+        )
+    }
+
     // check if this is an animated texture
     pub fn is_animated(&self) -> bool {
         self.frames > 1
@@ -423,4 +648,9 @@ impl VtfHeader {
     pub fn is_normal_map(&self) -> bool {
         self.flags.contains(VtfFlags::NORMAL)
     }
+
+    // check if this is a self-shadowing bump map
+    pub fn is_ssbump(&self) -> bool {
+        self.flags.contains(VtfFlags::SSBUMP)
+    }
 }
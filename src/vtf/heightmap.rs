@@ -0,0 +1,188 @@
+//! Tangent-space normal map generation from height maps
+
+// Derivative kernel used to take the height gradient
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeightKernel {
+    // 3x3 with 1-2-1 smoothing
+    #[default]
+    Sobel,
+    // 3x3 with 3-10-3 smoothing, better rotational symmetry
+    Scharr,
+    // 3x3 with flat 1-1-1 smoothing
+    Prewitt,
+}
+
+impl HeightKernel {
+    pub fn from_index(index: i32) -> Self {
+        match index {
+            1 => HeightKernel::Scharr,
+            2 => HeightKernel::Prewitt,
+            _ => HeightKernel::Sobel,
+        }
+    }
+
+    // Weights across the derivative direction for the -1, 0 and +1 rows
+    fn smoothing(&self) -> [f32; 3] {
+        match self {
+            HeightKernel::Sobel => [1.0, 2.0, 1.0],
+            HeightKernel::Scharr => [3.0, 10.0, 3.0],
+            HeightKernel::Prewitt => [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+// Settings for turning a height map into a normal map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeightToNormal {
+    pub kernel: HeightKernel,
+    // Scale applied to the height gradient, higher is bumpier
+    pub strength: f32,
+    // Sample across the opposite edge for tiling textures, clamp otherwise
+    pub wrap: bool,
+    // Keep the height in alpha for $parallaxmap style materials
+    pub height_in_alpha: bool,
+}
+
+impl Default for HeightToNormal {
+    fn default() -> Self {
+        Self {
+            kernel: HeightKernel::default(),
+            strength: 2.0,
+            wrap: true,
+            height_in_alpha: false,
+        }
+    }
+}
+
+// Rec. 709 luma of each texel in 0..1, so color height maps still work
+pub(super) fn heights(rgba: &[u8]) -> Vec<f32> {
+    rgba.chunks_exact(4)
+        .map(|pixel| {
+            (0.2126 * pixel[0] as f32 + 0.7152 * pixel[1] as f32 + 0.0722 * pixel[2] as f32) / 255.0
+        })
+        .collect()
+}
+
+// Height change per texel along x and y (y pointing down the image)
+pub(super) fn gradients(
+    heights: &[f32],
+    width: u32,
+    height: u32,
+    kernel: HeightKernel,
+    wrap: bool,
+) -> Vec<(f32, f32)> {
+    let (w, h) = (width as i64, height as i64);
+    let sample = |x: i64, y: i64| {
+        let (x, y) = if wrap {
+            (x.rem_euclid(w), y.rem_euclid(h))
+        } else {
+            (x.clamp(0, w - 1), y.clamp(0, h - 1))
+        };
+        heights[(y * w + x) as usize]
+    };
+
+    let weights = kernel.smoothing();
+    // Both taps are two texels apart, so the total weight is doubled
+    let norm = 2.0 * weights.iter().sum::<f32>();
+
+    (0..h)
+        .flat_map(|y| (0..w).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let mut dx = 0.0;
+            let mut dy = 0.0;
+            for (offset, weight) in (-1..=1).zip(weights) {
+                dx += weight * (sample(x + 1, y + offset) - sample(x - 1, y + offset));
+                dy += weight * (sample(x + offset, y + 1) - sample(x + offset, y - 1));
+            }
+            (dx / norm, dy / norm)
+        })
+        .collect()
+}
+
+// Build an RGBA8 normal map in Source's DirectX convention (green points down the image)
+pub(super) fn height_to_normal(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    settings: &HeightToNormal,
+) -> Vec<u8> {
+    let heights = heights(rgba);
+    let slopes = gradients(&heights, width, height, settings.kernel, settings.wrap);
+
+    let encode = |v: f32| ((v * 0.5 + 0.5) * 255.0).round() as u8;
+    slopes
+        .iter()
+        .zip(&heights)
+        .flat_map(|(&(dx, dy), &h)| {
+            let (x, y, z) = (-dx * settings.strength, -dy * settings.strength, 1.0f32);
+            let length = (x * x + y * y + z * z).sqrt();
+            let alpha = if settings.height_in_alpha {
+                (h * 255.0).round() as u8
+            } else {
+                255
+            };
+            [
+                encode(x / length),
+                encode(y / length),
+                encode(z / length),
+                alpha,
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(values: &[u8]) -> Vec<u8> {
+        values.iter().flat_map(|&v| [v, v, v, 255]).collect()
+    }
+
+    #[test]
+    fn test_flat_height_points_up() {
+        let flat = gray(&[100; 16]);
+        for kernel in [
+            HeightKernel::Sobel,
+            HeightKernel::Scharr,
+            HeightKernel::Prewitt,
+        ] {
+            let settings = HeightToNormal {
+                kernel,
+                ..Default::default()
+            };
+            let normals = height_to_normal(&flat, 4, 4, &settings);
+            assert!(normals.chunks(4).all(|p| p == [128, 128, 255, 255]));
+        }
+    }
+
+    #[test]
+    fn test_slope_direction() {
+        // Height rises to the right and down the image
+        let ramp: Vec<u8> = (0..4 * 4).map(|i| ((i % 4) + (i / 4)) as u8 * 40).collect();
+        let settings = HeightToNormal {
+            wrap: false,
+            height_in_alpha: true,
+            ..Default::default()
+        };
+        let normals = height_to_normal(&gray(&ramp), 4, 4, &settings);
+
+        // Center texel (1, 1): normal leans left and up the image
+        let center = &normals[(4 + 1) * 4..(4 + 2) * 4];
+        assert!(center[0] < 128 && center[1] < 128 && center[2] > 128);
+        assert_eq!(center[0], center[1]);
+        assert_eq!(center[3], 80);
+    }
+
+    #[test]
+    fn test_wrap_sees_opposite_edge() {
+        // A single bump on the left edge tilts the right edge only when wrapping
+        let mut line = [0u8; 4];
+        line[0] = 255;
+        let heights = heights(&gray(&line));
+        let wrapped = gradients(&heights, 4, 1, HeightKernel::Sobel, true);
+        let clamped = gradients(&heights, 4, 1, HeightKernel::Sobel, false);
+        assert!(wrapped[3].0 > 0.0);
+        assert_eq!(clamped[3].0, 0.0);
+    }
+}
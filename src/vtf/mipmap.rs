@@ -0,0 +1,314 @@
+//! Mipmap generation: resampling filters, alpha-test coverage and normal renormalization
+
+use std::f32::consts::PI;
+
+// Filter used to downsample each mip from the one above it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MipFilter {
+    // Plain average of the covered texels
+    Box,
+    // Kaiser-windowed sinc, sharp with little ringing
+    Kaiser,
+    // Lanczos3 windowed sinc
+    #[default]
+    Lanczos,
+    // Mitchell-Netravali cubic (B = C = 1/3), soft with no visible ringing
+    Mitchell,
+}
+
+impl MipFilter {
+    pub fn from_index(index: i32) -> Self {
+        match index {
+            0 => MipFilter::Box,
+            1 => MipFilter::Kaiser,
+            3 => MipFilter::Mitchell,
+            _ => MipFilter::Lanczos,
+        }
+    }
+
+    // Radius of the filter in destination texels
+    fn support(&self) -> f32 {
+        match self {
+            MipFilter::Box => 0.5,
+            MipFilter::Kaiser | MipFilter::Lanczos => 3.0,
+            MipFilter::Mitchell => 2.0,
+        }
+    }
+
+    fn weight(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            MipFilter::Box => {
+                if x <= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            MipFilter::Kaiser => {
+                const ALPHA: f32 = 4.0;
+                let t = x / 3.0;
+                if t >= 1.0 {
+                    return 0.0;
+                }
+                sinc(x) * bessel_i0(ALPHA * (1.0 - t * t).sqrt()) / bessel_i0(ALPHA)
+            }
+            MipFilter::Lanczos => {
+                if x >= 3.0 {
+                    0.0
+                } else {
+                    sinc(x) * sinc(x / 3.0)
+                }
+            }
+            MipFilter::Mitchell => {
+                const B: f32 = 1.0 / 3.0;
+                const C: f32 = 1.0 / 3.0;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * B - 6.0 * C) * x * x * x
+                        + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+                        + (6.0 - 2.0 * B))
+                        / 6.0
+                } else if x < 2.0 {
+                    ((-B - 6.0 * C) * x * x * x
+                        + (6.0 * B + 30.0 * C) * x * x
+                        + (-12.0 * B - 48.0 * C) * x
+                        + (8.0 * B + 24.0 * C))
+                        / 6.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// Modified Bessel function of the first kind, order 0 (power series)
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_squared = x * x / 4.0;
+    for k in 1..32 {
+        term *= half_squared / (k * k) as f32;
+        sum += term;
+        if term < sum * 1e-8 {
+            break;
+        }
+    }
+    sum
+}
+
+// Normalized source taps (index, weight) for every destination texel along one axis
+fn axis_taps(
+    source: u32,
+    destination: u32,
+    filter: MipFilter,
+    wrap: bool,
+) -> Vec<Vec<(usize, f32)>> {
+    let scale = (source as f32 / destination as f32).max(1.0);
+    let radius = filter.support() * scale;
+
+    (0..destination)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * source as f32 / destination as f32;
+            let start = (center - radius).floor() as i64;
+            let end = (center + radius).ceil() as i64;
+
+            let mut taps: Vec<(usize, f32)> = (start..end)
+                .filter_map(|j| {
+                    let weight = filter.weight((j as f32 + 0.5 - center) / scale);
+                    if weight == 0.0 {
+                        return None;
+                    }
+                    let index = if wrap {
+                        j.rem_euclid(source as i64)
+                    } else {
+                        j.clamp(0, source as i64 - 1)
+                    };
+                    Some((index as usize, weight))
+                })
+                .collect();
+
+            let total: f32 = taps.iter().map(|(_, weight)| weight).sum();
+            if total.abs() < 1e-6 {
+                // Nothing in range, fall back to the nearest texel
+                let nearest = (center as usize).min(source as usize - 1);
+                return vec![(nearest, 1.0)];
+            }
+            for (_, weight) in &mut taps {
+                *weight /= total;
+            }
+            taps
+        })
+        .collect()
+}
+
+// Resample RGBA32F data with a separable filter, wrapping or clamping at the edges
+pub(super) fn resample(
+    rgba: &[f32],
+    width: u32,
+    height: u32,
+    new_width: u32,
+    new_height: u32,
+    filter: MipFilter,
+    wrap: (bool, bool),
+) -> Vec<f32> {
+    let (w, nw, nh) = (width as usize, new_width as usize, new_height as usize);
+
+    let columns = axis_taps(width, new_width, filter, wrap.0);
+    let mut horizontal = vec![0.0f32; nw * height as usize * 4];
+    for y in 0..height as usize {
+        let row = &rgba[y * w * 4..(y + 1) * w * 4];
+        for (x, taps) in columns.iter().enumerate() {
+            let out = &mut horizontal[(y * nw + x) * 4..(y * nw + x + 1) * 4];
+            for &(index, weight) in taps {
+                for c in 0..4 {
+                    out[c] += row[index * 4 + c] * weight;
+                }
+            }
+        }
+    }
+
+    let rows = axis_taps(height, new_height, filter, wrap.1);
+    let mut output = vec![0.0f32; nw * nh * 4];
+    for (y, taps) in rows.iter().enumerate() {
+        let out_row = &mut output[y * nw * 4..(y + 1) * nw * 4];
+        for &(index, weight) in taps {
+            let row = &horizontal[index * nw * 4..(index + 1) * nw * 4];
+            for (out, value) in out_row.iter_mut().zip(row) {
+                *out += value * weight;
+            }
+        }
+    }
+
+    output
+}
+
+// Fraction of texels that pass an alpha test at `reference` after scaling alpha
+fn alpha_coverage(rgba: &[f32], reference: f32, scale: f32) -> f32 {
+    let pixels = rgba.len() / 4;
+    if pixels == 0 {
+        return 0.0;
+    }
+    let passing = rgba
+        .chunks_exact(4)
+        .filter(|pixel| (pixel[3] * scale).min(1.0) > reference)
+        .count();
+    passing as f32 / pixels as f32
+}
+
+// Fraction of texels that pass an alpha test at `reference`
+pub(super) fn coverage(rgba: &[f32], reference: f32) -> f32 {
+    alpha_coverage(rgba, reference, 1.0)
+}
+
+// Scale alpha so the same fraction of texels passes the alpha test as in the top mip,
+// otherwise alpha-tested foliage thins out and vanishes with distance
+pub(super) fn preserve_alpha_coverage(rgba: &mut [f32], reference: f32, target: f32) {
+    let (mut low, mut high) = (0.0f32, 4.0f32);
+    for _ in 0..16 {
+        let mid = (low + high) * 0.5;
+        if alpha_coverage(rgba, reference, mid) < target {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    // Pick whichever end of the final range lands closer to the target
+    let scale = if (alpha_coverage(rgba, reference, low) - target).abs()
+        <= (alpha_coverage(rgba, reference, high) - target).abs()
+    {
+        low
+    } else {
+        high
+    };
+    for pixel in rgba.chunks_exact_mut(4) {
+        pixel[3] = (pixel[3] * scale).min(1.0);
+    }
+}
+
+// Filtering shortens normals, push them back to unit length
+pub(super) fn renormalize(rgba: &mut [f32]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let x = pixel[0] * 2.0 - 1.0;
+        let y = pixel[1] * 2.0 - 1.0;
+        let z = pixel[2] * 2.0 - 1.0;
+        let length = (x * x + y * y + z * z).sqrt();
+        let (x, y, z) = if length > 1e-6 {
+            (x / length, y / length, z / length)
+        } else {
+            (0.0, 0.0, 1.0)
+        };
+        pixel[0] = x * 0.5 + 0.5;
+        pixel[1] = y * 0.5 + 0.5;
+        pixel[2] = z * 0.5 + 0.5;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_preserve_flat_color() {
+        let flat = [0.25f32, 0.5, 0.75, 1.0].repeat(16 * 16);
+        for filter in [
+            MipFilter::Box,
+            MipFilter::Kaiser,
+            MipFilter::Lanczos,
+            MipFilter::Mitchell,
+        ] {
+            for wrap in [(false, false), (true, true)] {
+                let small = resample(&flat, 16, 16, 8, 4, filter, wrap);
+                assert_eq!(small.len(), 8 * 4 * 4);
+                for (a, b) in small.iter().zip(flat.iter()) {
+                    assert!((a - b).abs() < 1e-5, "{:?}: {} vs {}", filter, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_box_filter_averages_pairs() {
+        // One row of alternating black and white averages to gray
+        let row: Vec<f32> = (0..8)
+            .flat_map(|x| {
+                let v = (x % 2) as f32;
+                [v, v, v, 1.0]
+            })
+            .collect();
+        let half = resample(&row, 8, 1, 4, 1, MipFilter::Box, (false, false));
+        assert!(half.chunks(4).all(|p| (p[0] - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_preserve_alpha_coverage() {
+        // A quarter of the texels pass at 0.5, blurring would leave none passing
+        let mut rgba: Vec<f32> = (0..64)
+            .flat_map(|i| [1.0, 1.0, 1.0, if i % 4 == 0 { 0.45 } else { 0.2 }])
+            .collect();
+        rgba[3] = 0.9;
+        let target = 0.25;
+        preserve_alpha_coverage(&mut rgba, 0.5, target);
+        assert!((coverage(&rgba, 0.5) - target).abs() <= 1.0 / 64.0);
+    }
+
+    #[test]
+    fn test_renormalize() {
+        let mut rgba = vec![0.75, 0.5, 0.75, 1.0, 0.5, 0.5, 0.5, 1.0];
+        renormalize(&mut rgba);
+        let x = rgba[0] * 2.0 - 1.0;
+        let z = rgba[2] * 2.0 - 1.0;
+        assert!((x * x + z * z - 1.0).abs() < 1e-5);
+        // Degenerate normals point straight out
+        assert_eq!(&rgba[4..8], &[0.5, 0.5, 1.0, 1.0]);
+    }
+}
@@ -1,13 +1,51 @@
 //! VTF (Valve Texture Format) decoder
 //!
 
+mod analyze;
+mod bptc;
+mod compression;
+mod console;
+mod cubemap;
 mod decoder;
+mod dilate;
+mod dxt;
+mod editor;
 mod formats;
+mod frame_cache;
+mod hdr;
 mod header;
+mod heightmap;
+mod mipmap;
+mod reflectivity;
+mod source;
+mod spray;
+mod ssbump;
+mod swizzle;
+mod transcode;
 
+pub use analyze::{
+    AlphaContent, ContentAnalysis, DEFAULT_MAX_ERROR, alpha_content, analyze, compression_error,
+    recommend_format,
+};
+pub use compression::CompressionMethod;
+pub use console::{ConsolePlatform, VtfxHeader, is_vtfx, vtfx_to_vtf};
+pub use cubemap::{CubemapFace, CubemapLayout};
 pub use decoder::{DecodedFrame, VtfBuilder, VtfDecoder, VtfImage};
-pub use formats::ImageFormat;
-pub use header::{VtfFlags, VtfFormat, VtfHeader, VtfVersion};
+pub use dxt::{DxtQuality, compress_dxt};
+pub use editor::VtfEditor;
+pub use formats::{
+    Dither, ImageFormat, convert_from_rgba, convert_from_rgba_f32, convert_to_rgba_f32,
+    convert_to_rgba16,
+};
+pub use hdr::Tonemap;
+pub use header::{
+    VtfFlags, VtfFormat, VtfHeader, VtfResourceData, VtfResourceEntry, VtfResourceTag, VtfVersion,
+};
+pub use heightmap::{HeightKernel, HeightToNormal};
+pub use mipmap::MipFilter;
+pub use spray::{SprayGame, SprayLimits, SprayViolation};
+pub use ssbump::HeightToSsbump;
+pub use transcode::{MipPolicy, TranscodeOptions};
 
 use thiserror::Error;
 
@@ -37,6 +75,12 @@ pub enum VtfError {
 
     #[error("Invalid frame index: {0}")]
     InvalidFrame(u16),
+
+    #[error("Invalid slice index: {0}")]
+    InvalidSlice(u32),
+
+    #[error("Invalid face index: {0}")]
+    InvalidFace(u32),
 }
 
 // Result type for VTF operations
@@ -0,0 +1,115 @@
+//! Reflectivity: the average linear color VRAD bounces light off a surface with
+
+use super::VtfResult;
+use super::cubemap::CubemapFace;
+use super::decoder::{VtfDecoder, VtfImage};
+use super::hdr::srgb_to_linear;
+use super::header::VtfHeader;
+
+// Average linear RGB of RGBA32F slices. With `alpha_weighted`, translucent texels
+// count for as much as they cover, and a fully transparent image falls back to
+// the plain average.
+pub(super) fn average_reflectivity<'a>(
+    slices: impl IntoIterator<Item = &'a [f32]>,
+    alpha_weighted: bool,
+) -> [f32; 3] {
+    let mut weighted = [0.0f64; 3];
+    let mut plain = [0.0f64; 3];
+    let (mut total_weight, mut count) = (0.0f64, 0usize);
+
+    for slice in slices {
+        for pixel in slice.chunks_exact(4) {
+            let weight = pixel[3].clamp(0.0, 1.0) as f64;
+            for channel in 0..3 {
+                let value = pixel[channel].max(0.0) as f64;
+                weighted[channel] += value * weight;
+                plain[channel] += value;
+            }
+            total_weight += weight;
+            count += 1;
+        }
+    }
+
+    if alpha_weighted && total_weight > 0.0 {
+        weighted.map(|sum| (sum / total_weight) as f32)
+    } else if count > 0 {
+        plain.map(|sum| (sum / count as f64) as f32)
+    } else {
+        [0.0; 3]
+    }
+}
+
+// sRGB RGBA32F to linear light, alpha untouched
+pub(super) fn linearize(slice: &mut [f32]) {
+    for pixel in slice.chunks_exact_mut(4) {
+        for value in &mut pixel[..3] {
+            *value = srgb_to_linear(value.clamp(0.0, 1.0));
+        }
+    }
+}
+
+impl VtfImage {
+    // Reflectivity from the top mip of every frame, face and slice, the way the
+    // builder writes it. Normal and SSBUMP data is averaged as-is, without alpha.
+    pub fn compute_reflectivity(&self) -> VtfResult<[f32; 3]> {
+        let is_color = !self.header.is_normal_map() && !self.header.is_ssbump();
+        let mut slices = Vec::new();
+
+        for frame in 0..self.frame_count() {
+            if self.is_envmap() {
+                for face in CubemapFace::CUBE {
+                    slices.push(self.decode_face_hdr(0, frame, face)?.data);
+                }
+            } else {
+                for slice in 0..self.depth().max(1) {
+                    slices.push(self.decode_slice_hdr(0, frame, slice)?.data);
+                }
+            }
+        }
+
+        // Float formats are already linear
+        if is_color && !self.header.is_hdr() {
+            for slice in &mut slices {
+                linearize(slice);
+            }
+        }
+        Ok(average_reflectivity(
+            slices.iter().map(|slice| slice.as_slice()),
+            is_color,
+        ))
+    }
+}
+
+impl VtfHeader {
+    // Recompute the reflectivity vector from the image data of the file this header
+    // was read from
+    pub fn recompute_reflectivity(&mut self, data: &[u8]) -> VtfResult<()> {
+        self.reflectivity = VtfDecoder::load_from_memory(data)?.compute_reflectivity()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_average_reflectivity_alpha_weighting() {
+        let slice = [1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0];
+        assert_eq!(average_reflectivity([&slice[..]], true), [1.0, 0.0, 0.0]);
+        assert_eq!(average_reflectivity([&slice[..]], false), [0.5, 0.5, 0.0]);
+
+        // Nothing visible, nothing to weight by
+        let clear = [0.2, 0.4, 0.6, 0.0];
+        let average = average_reflectivity([&clear[..]], true);
+        assert!((average[1] - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_linearize() {
+        let mut slice = [0.5, 1.0, 0.0, 0.5];
+        linearize(&mut slice);
+        assert!((slice[0] - 0.214).abs() < 0.001);
+        assert_eq!(&slice[1..], &[1.0, 0.0, 0.5]);
+    }
+}
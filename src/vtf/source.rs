@@ -0,0 +1,83 @@
+//! Where a VtfImage reads its bytes from. Mapped and streamed files only read the
+//! mips, frames and faces that actually get decoded.
+
+use super::{VtfError, VtfResult};
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+// Anything a VtfImage can stream its data from
+pub(super) trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+#[derive(Clone)]
+pub(super) enum VtfSource {
+    Memory(Vec<u8>),
+    Mapped(Arc<Mmap>),
+    // Shared between clones, every read seeks to its own offset first
+    Reader {
+        reader: Arc<Mutex<Box<dyn ReadSeek>>>,
+        len: usize,
+    },
+}
+
+impl VtfSource {
+    pub fn reader<R: Read + Seek + Send + 'static>(mut reader: R) -> VtfResult<Self> {
+        let len = reader.seek(SeekFrom::End(0))? as usize;
+        Ok(VtfSource::Reader {
+            reader: Arc::new(Mutex::new(Box::new(reader))),
+            len,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            VtfSource::Memory(data) => data.len(),
+            VtfSource::Mapped(map) => map.len(),
+            VtfSource::Reader { len, .. } => *len,
+        }
+    }
+
+    // The bytes in offset..offset + len, borrowed unless they had to be read in
+    pub fn read(&self, offset: usize, len: usize) -> VtfResult<Cow<'_, [u8]>> {
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| end <= self.len())
+            .ok_or_else(|| {
+                VtfError::InvalidData(format!(
+                    "Data out of bounds: offset {} + size {} > data size {}",
+                    offset,
+                    len,
+                    self.len()
+                ))
+            })?;
+
+        match self {
+            VtfSource::Memory(data) => Ok(Cow::Borrowed(&data[offset..end])),
+            VtfSource::Mapped(map) => Ok(Cow::Borrowed(&map[offset..end])),
+            VtfSource::Reader { reader, .. } => {
+                // A panic mid-read can't leave the reader in a state the seek below
+                // doesn't fix, so a poisoned lock is still usable
+                let mut reader = reader.lock().unwrap_or_else(|e| e.into_inner());
+                reader.seek(SeekFrom::Start(offset as u64))?;
+                let mut data = vec![0u8; len];
+                reader.read_exact(&mut data)?;
+                Ok(Cow::Owned(data))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for VtfSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            VtfSource::Memory(_) => "Memory",
+            VtfSource::Mapped(_) => "Mapped",
+            VtfSource::Reader { .. } => "Reader",
+        };
+        write!(f, "{}({} bytes)", kind, self.len())
+    }
+}
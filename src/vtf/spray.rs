@@ -0,0 +1,227 @@
+//! Player spray limits for the Source multiplayer games, and fitting textures to them
+
+use super::decoder::VtfImage;
+use super::header::VtfFormat;
+use std::fmt;
+
+// Games with player sprays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprayGame {
+    TeamFortress2,
+    CounterStrikeSource,
+    HalfLife2Deathmatch,
+    GarrysMod,
+}
+
+// What a game accepts as a spray
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SprayLimits {
+    // Largest VTF the game will upload, in bytes
+    pub max_file_size: u32,
+    // Largest width or height
+    pub max_size: u32,
+    // Frames in an animated spray
+    pub max_frames: u16,
+    // Animated sprays are drawn from the top mip only, mips just waste the budget
+    pub animated_mipmaps: bool,
+}
+
+// Formats every game draws sprays in, best quality first
+const SPRAY_FORMATS: [VtfFormat; 3] = [VtfFormat::Bgra8888, VtfFormat::Dxt5, VtfFormat::Dxt1];
+
+impl SprayGame {
+    pub const ALL: [SprayGame; 4] = [
+        SprayGame::TeamFortress2,
+        SprayGame::CounterStrikeSource,
+        SprayGame::HalfLife2Deathmatch,
+        SprayGame::GarrysMod,
+    ];
+
+    // Index into ALL, for combo boxes
+    pub fn from_index(index: i32) -> Option<Self> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| Self::ALL.get(index).copied())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SprayGame::TeamFortress2 => "Team Fortress 2",
+            SprayGame::CounterStrikeSource => "Counter-Strike: Source",
+            SprayGame::HalfLife2Deathmatch => "Half-Life 2: Deathmatch",
+            SprayGame::GarrysMod => "Garry's Mod",
+        }
+    }
+
+    pub fn limits(&self) -> SprayLimits {
+        match self {
+            SprayGame::TeamFortress2 | SprayGame::GarrysMod => SprayLimits {
+                max_file_size: 512 * 1024,
+                max_size: 1024,
+                max_frames: 32,
+                animated_mipmaps: false,
+            },
+            SprayGame::CounterStrikeSource | SprayGame::HalfLife2Deathmatch => SprayLimits {
+                max_file_size: 120 * 1024,
+                max_size: 256,
+                max_frames: 32,
+                animated_mipmaps: false,
+            },
+        }
+    }
+
+    // Formats worth trying for an image, largest first. Opaque images skip DXT5,
+    // which only spends its extra bytes on alpha.
+    pub fn formats(has_alpha: bool) -> Vec<VtfFormat> {
+        SPRAY_FORMATS
+            .into_iter()
+            .filter(|format| has_alpha || *format != VtfFormat::Dxt5)
+            .collect()
+    }
+}
+
+// A rule an existing spray breaks
+#[derive(Debug, Clone, PartialEq)]
+pub enum SprayViolation {
+    NotPowerOfTwo { width: u32, height: u32 },
+    TooLarge { width: u32, height: u32, max: u32 },
+    FileTooLarge { size: u32, max: u32 },
+    Format(VtfFormat),
+    TooManyFrames { frames: u16, max: u16 },
+    AnimatedMipmaps,
+    NotTwoDimensional,
+}
+
+impl fmt::Display for SprayViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SprayViolation::NotPowerOfTwo { width, height } => {
+                write!(f, "{}x{} is not a power of two", width, height)
+            }
+            SprayViolation::TooLarge { width, height, max } => {
+                write!(f, "{}x{} is larger than {}x{}", width, height, max, max)
+            }
+            SprayViolation::FileTooLarge { size, max } => write!(
+                f,
+                "File is {} KB, the limit is {} KB",
+                size.div_ceil(1024),
+                max / 1024
+            ),
+            SprayViolation::Format(format) => {
+                write!(
+                    f,
+                    "{:?} is not a spray format (DXT1, DXT5 or BGRA8888)",
+                    format
+                )
+            }
+            SprayViolation::TooManyFrames { frames, max } => {
+                write!(f, "{} frames, the limit is {}", frames, max)
+            }
+            SprayViolation::AnimatedMipmaps => write!(f, "Animated sprays can't have mipmaps"),
+            SprayViolation::NotTwoDimensional => {
+                write!(f, "Sprays can't be cubemaps or volume textures")
+            }
+        }
+    }
+}
+
+// Sizes worth trying for a spray, largest first: each side rounded down to a power
+// of two, then halved together to keep the aspect ratio
+pub(super) fn candidate_sizes(width: u32, height: u32, limits: &SprayLimits) -> Vec<(u32, u32)> {
+    let floor_pow2 = |v: u32| 1u32 << v.max(1).ilog2();
+    let (mut w, mut h) = (floor_pow2(width), floor_pow2(height));
+    while w.max(h) > limits.max_size {
+        w = (w / 2).max(1);
+        h = (h / 2).max(1);
+    }
+    let mut sizes = vec![(w, h)];
+    while w > 1 || h > 1 {
+        w = (w / 2).max(1);
+        h = (h / 2).max(1);
+        sizes.push((w, h));
+    }
+    sizes
+}
+
+// Bytes of image data alone, a lower bound on the file size
+pub(super) fn image_data_size(
+    width: u32,
+    height: u32,
+    format: VtfFormat,
+    frames: u32,
+    mipmap_count: u8,
+) -> u32 {
+    (0..mipmap_count as u32)
+        .map(|mip| format.compute_image_size((width >> mip).max(1), (height >> mip).max(1)))
+        .sum::<u32>()
+        * frames
+}
+
+impl VtfImage {
+    // Everything about this texture the game would refuse as a spray
+    pub fn spray_violations(&self, game: SprayGame) -> Vec<SprayViolation> {
+        let limits = game.limits();
+        let (width, height) = (self.width(), self.height());
+        let mut violations = Vec::new();
+
+        if self.is_envmap() || self.is_volume() {
+            violations.push(SprayViolation::NotTwoDimensional);
+        }
+        if !width.is_power_of_two() || !height.is_power_of_two() {
+            violations.push(SprayViolation::NotPowerOfTwo { width, height });
+        }
+        if width > limits.max_size || height > limits.max_size {
+            violations.push(SprayViolation::TooLarge {
+                width,
+                height,
+                max: limits.max_size,
+            });
+        }
+        let size = self.file_size() as u32;
+        if size > limits.max_file_size {
+            violations.push(SprayViolation::FileTooLarge {
+                size,
+                max: limits.max_file_size,
+            });
+        }
+        if !SPRAY_FORMATS.contains(&self.format()) {
+            violations.push(SprayViolation::Format(self.format()));
+        }
+        if self.frame_count() > limits.max_frames {
+            violations.push(SprayViolation::TooManyFrames {
+                frames: self.frame_count(),
+                max: limits.max_frames,
+            });
+        }
+        if self.is_animated() && self.mipmap_count() > 1 && !limits.animated_mipmaps {
+            violations.push(SprayViolation::AnimatedMipmaps);
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidate_sizes() {
+        let limits = SprayGame::CounterStrikeSource.limits();
+        let sizes = candidate_sizes(600, 300, &limits);
+        assert_eq!(sizes[0], (256, 128));
+        assert_eq!(sizes[1], (128, 64));
+        assert_eq!(*sizes.last().unwrap(), (1, 1));
+
+        let sizes = candidate_sizes(512, 128, &SprayGame::TeamFortress2.limits());
+        assert_eq!(&sizes[..3], &[(512, 128), (256, 64), (128, 32)]);
+    }
+
+    #[test]
+    fn test_formats_skip_dxt5_when_opaque() {
+        assert_eq!(
+            SprayGame::formats(false),
+            vec![VtfFormat::Bgra8888, VtfFormat::Dxt1]
+        );
+        assert_eq!(SprayGame::formats(true).len(), 3);
+    }
+}
@@ -0,0 +1,254 @@
+//! Self-shadowing bump maps: conversion from normal and height maps and back
+
+use super::decoder::DecodedFrame;
+use super::heightmap::{HeightKernel, gradients, heights};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+// Tangent-space basis the SSBUMP channels are projected onto, same as the engine's
+const BUMP_BASIS: [[f32; 3]; 3] = [
+    [0.816_496_6, 0.0, 0.577_350_3],
+    [-0.408_248_3, FRAC_1_SQRT_2, 0.577_350_3],
+    [-0.408_248_3, -FRAC_1_SQRT_2, 0.577_350_3],
+];
+
+// Settings for baking an SSBUMP from a height map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeightToSsbump {
+    pub kernel: HeightKernel,
+    // Height of white above black in texels. Scales both the slopes and the shadows.
+    pub strength: f32,
+    // Sample across the opposite edge for tiling textures, clamp otherwise
+    pub wrap: bool,
+    // How far to trace for self-shadowing in texels, 0 skips the occlusion bake
+    pub occlusion_radius: u32,
+}
+
+impl Default for HeightToSsbump {
+    fn default() -> Self {
+        Self {
+            kernel: HeightKernel::default(),
+            strength: 2.0,
+            wrap: true,
+            occlusion_radius: 16,
+        }
+    }
+}
+
+// What the builder's frames hold when writing an SSBUMP
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum SsbumpSource {
+    Normal,
+    Height(HeightToSsbump),
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn encode(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// Project a unit normal onto the three basis vectors
+fn project(normal: [f32; 3]) -> [f32; 3] {
+    BUMP_BASIS.map(|basis| dot(normal, basis).max(0.0))
+}
+
+// Tangent-space normal map (DirectX convention) to SSBUMP. Alpha is kept.
+pub(super) fn normal_to_ssbump(rgba: &[u8]) -> Vec<u8> {
+    rgba.chunks_exact(4)
+        .flat_map(|pixel| {
+            let decode = |v: u8| v as f32 / 255.0 * 2.0 - 1.0;
+            let (x, y, z) = (decode(pixel[0]), decode(pixel[1]), decode(pixel[2]));
+            let length = (x * x + y * y + z * z).sqrt().max(1e-6);
+            let [r, g, b] = project([x / length, y / length, z / length]);
+            [encode(r), encode(g), encode(b), pixel[3]]
+        })
+        .collect()
+}
+
+// Fraction of a few rays around `basis` that leave the height field without hitting it
+fn visibility(
+    heights: &[f32],
+    width: u32,
+    height: u32,
+    (x, y): (i64, i64),
+    basis: [f32; 3],
+    settings: &HeightToSsbump,
+) -> f32 {
+    let (w, h) = (width as i64, height as i64);
+    let sample = |sx: i64, sy: i64| -> Option<f32> {
+        if settings.wrap {
+            Some(heights[(sy.rem_euclid(h) * w + sx.rem_euclid(w)) as usize])
+        } else if sx < 0 || sy < 0 || sx >= w || sy >= h {
+            None
+        } else {
+            Some(heights[(sy * w + sx) as usize])
+        }
+    };
+
+    let start = heights[(y * w + x) as usize] * settings.strength;
+    let azimuth = basis[1].atan2(basis[0]);
+    let elevation = basis[2].asin();
+    // The basis direction plus four rays spread around it
+    let spread = [(0.0, 0.0), (-0.5, 0.0), (0.5, 0.0), (0.0, -0.3), (0.0, 0.3)];
+
+    let open = spread
+        .iter()
+        .filter(|&&(d_azimuth, d_elevation)| {
+            let (sin_a, cos_a) = (azimuth + d_azimuth).sin_cos();
+            let rise = (elevation + d_elevation).tan();
+            (1..=settings.occlusion_radius).all(|step| {
+                let t = step as f32;
+                let sx = x + (cos_a * t).round() as i64;
+                let sy = y + (sin_a * t).round() as i64;
+                match sample(sx, sy) {
+                    Some(ground) => ground * settings.strength <= start + rise * t,
+                    // Left the texture without hitting anything
+                    None => true,
+                }
+            })
+        })
+        .count();
+    open as f32 / spread.len() as f32
+}
+
+// Height map to SSBUMP, with the slopes from the height gradient and optional
+// self-shadowing traced along each basis direction. Alpha is opaque.
+pub(super) fn height_to_ssbump(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    settings: &HeightToSsbump,
+) -> Vec<u8> {
+    let heights = heights(rgba);
+    let slopes = gradients(&heights, width, height, settings.kernel, settings.wrap);
+
+    slopes
+        .iter()
+        .enumerate()
+        .flat_map(|(index, &(dx, dy))| {
+            let (x, y, z) = (-dx * settings.strength, -dy * settings.strength, 1.0f32);
+            let length = (x * x + y * y + z * z).sqrt();
+            let mut channels = project([x / length, y / length, z / length]);
+
+            if settings.occlusion_radius > 0 {
+                let position = ((index as u32 % width) as i64, (index as u32 / width) as i64);
+                for (channel, basis) in channels.iter_mut().zip(BUMP_BASIS) {
+                    *channel *= visibility(&heights, width, height, position, basis, settings);
+                }
+            }
+            [
+                encode(channels[0]),
+                encode(channels[1]),
+                encode(channels[2]),
+                255,
+            ]
+        })
+        .collect()
+}
+
+// SSBUMP back to a tangent-space normal map the way the shader reconstructs it
+pub(super) fn ssbump_to_normal(rgba: &[u8]) -> Vec<u8> {
+    rgba.chunks_exact(4)
+        .flat_map(|pixel| {
+            let weights = [pixel[0], pixel[1], pixel[2]].map(|v| v as f32 / 255.0);
+            let mut normal = [0.0f32; 3];
+            for (weight, basis) in weights.iter().zip(BUMP_BASIS) {
+                for (n, b) in normal.iter_mut().zip(basis) {
+                    *n += weight * b;
+                }
+            }
+            let length = dot(normal, normal).sqrt();
+            let normal = if length > 1e-6 {
+                normal.map(|n| n / length)
+            } else {
+                [0.0, 0.0, 1.0]
+            };
+            let encode = |v: f32| ((v * 0.5 + 0.5) * 255.0).round() as u8;
+            [
+                encode(normal[0]),
+                encode(normal[1]),
+                encode(normal[2]),
+                pixel[3],
+            ]
+        })
+        .collect()
+}
+
+impl DecodedFrame {
+    // Reconstruct the normal map from a decoded SSBUMP frame, for inspection
+    pub fn ssbump_to_normal(&self) -> DecodedFrame {
+        DecodedFrame {
+            data: ssbump_to_normal(&self.data),
+            width: self.width,
+            height: self.height,
+            mipmap_level: self.mipmap_level,
+            frame: self.frame,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_normal_round_trip() {
+        let flat = [128u8, 128, 255, 200];
+        let ssbump = normal_to_ssbump(&flat);
+        // Every basis vector is equally lit, give or take 128 not being exactly 0
+        assert!(ssbump[..3].iter().all(|&v| v.abs_diff(147) <= 1));
+        assert_eq!(ssbump[3], 200);
+        assert_eq!(ssbump_to_normal(&ssbump), vec![128, 128, 255, 200]);
+    }
+
+    #[test]
+    fn test_tilted_normal_round_trip() {
+        // A normal well inside the basis cone survives the trip both ways
+        let n = [0.3f32, -0.2, 0.933];
+        let encode = |v: f32| ((v * 0.5 + 0.5) * 255.0).round() as u8;
+        let normal = [encode(n[0]), encode(n[1]), encode(n[2]), 255];
+        let back = ssbump_to_normal(&normal_to_ssbump(&normal));
+        for (a, b) in back.iter().zip(normal.iter()) {
+            assert!(
+                (*a as i32 - *b as i32).abs() <= 2,
+                "{:?} vs {:?}",
+                back,
+                normal
+            );
+        }
+    }
+
+    #[test]
+    fn test_height_occlusion_darkens_toward_wall() {
+        // Low floor with a tall wall on the right edge
+        let heights: Vec<u8> = (0..16 * 16)
+            .flat_map(|i| {
+                let v = if i % 16 >= 12 { 255 } else { 0 };
+                [v, v, v, 255]
+            })
+            .collect();
+        let settings = HeightToSsbump {
+            wrap: false,
+            strength: 16.0,
+            ..Default::default()
+        };
+        let shadowed = height_to_ssbump(&heights, 16, 16, &settings);
+        let open = height_to_ssbump(
+            &heights,
+            16,
+            16,
+            &HeightToSsbump {
+                occlusion_radius: 0,
+                ..settings
+            },
+        );
+
+        // On the floor next to the wall, the basis facing the wall (+x) is blocked
+        let texel = (8 * 16 + 9) * 4;
+        assert!(shadowed[texel] < open[texel]);
+        // The basis facing away from it is not
+        assert_eq!(shadowed[texel + 1], open[texel + 1]);
+    }
+}
@@ -0,0 +1,188 @@
+//! Byte reordering of the 8-bit RGB and RGBA formats into RGBA8. Four pixels go
+//! through one SSSE3 or NEON shuffle where the CPU has it, the rest byte by byte.
+
+use super::header::VtfFormat;
+use rayon::prelude::*;
+
+// Pixels swizzled per parallel task
+const PIXELS_PER_TASK: usize = 64 * 1024;
+
+// Channel filled with 255 instead of read from the source
+const OPAQUE: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Swizzle {
+    // Bytes per source pixel, 3 or 4
+    stride: usize,
+    // Source byte of each output channel, or OPAQUE
+    order: [u8; 4],
+}
+
+impl Swizzle {
+    pub fn for_format(format: VtfFormat) -> Option<Self> {
+        let (stride, order) = match format {
+            VtfFormat::Rgba8888 | VtfFormat::Uvwq8888 | VtfFormat::Uvlx8888 => (4, [0, 1, 2, 3]),
+            VtfFormat::Abgr8888 => (4, [3, 2, 1, 0]),
+            VtfFormat::Argb8888 => (4, [1, 2, 3, 0]),
+            VtfFormat::Bgra8888 => (4, [2, 1, 0, 3]),
+            VtfFormat::Bgrx8888 => (4, [2, 1, 0, OPAQUE]),
+            VtfFormat::Rgb888 => (3, [0, 1, 2, OPAQUE]),
+            VtfFormat::Bgr888 => (3, [2, 1, 0, OPAQUE]),
+            _ => return None,
+        };
+        Some(Self { stride, order })
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    // Swizzle whole pixels of data into output, in parallel over runs of pixels
+    pub fn apply(&self, data: &[u8], output: &mut [u8]) {
+        if self.stride == 4 && self.order == [0, 1, 2, 3] {
+            output.copy_from_slice(data);
+            return;
+        }
+
+        output
+            .par_chunks_mut(PIXELS_PER_TASK * 4)
+            .zip(data.par_chunks(PIXELS_PER_TASK * self.stride))
+            .for_each(|(output, data)| {
+                let done = self.apply_simd(data, output);
+                self.apply_scalar(&data[done * self.stride..], &mut output[done * 4..]);
+            });
+    }
+
+    fn apply_scalar(&self, data: &[u8], output: &mut [u8]) {
+        for (pixel, source) in output
+            .chunks_exact_mut(4)
+            .zip(data.chunks_exact(self.stride))
+        {
+            for (channel, &index) in pixel.iter_mut().zip(&self.order) {
+                *channel = if index == OPAQUE {
+                    255
+                } else {
+                    source[index as usize]
+                };
+            }
+        }
+    }
+
+    // Shuffle indices for four pixels and the bytes to OR in after. 0x80 zeroes the
+    // byte for both pshufb and tbl.
+    fn masks(&self) -> ([u8; 16], [u8; 16]) {
+        let mut shuffle = [0u8; 16];
+        let mut fill = [0u8; 16];
+        for pixel in 0..4 {
+            for (channel, &index) in self.order.iter().enumerate() {
+                let byte = pixel * 4 + channel;
+                if index == OPAQUE {
+                    shuffle[byte] = 0x80;
+                    fill[byte] = 0xFF;
+                } else {
+                    shuffle[byte] = (pixel * self.stride) as u8 + index;
+                }
+            }
+        }
+        (shuffle, fill)
+    }
+
+    // Groups of four pixels whose 16 byte load stays inside data. Three byte pixels
+    // read a pixel past the group, so the last group is left to the scalar loop.
+    fn simd_groups(&self, data: &[u8]) -> usize {
+        let pixels = data.len() / self.stride;
+        if data.len() < 16 {
+            return 0;
+        }
+        ((data.len() - 16) / (self.stride * 4) + 1).min(pixels / 4)
+    }
+
+    // Returns how many pixels were swizzled
+    #[cfg(target_arch = "x86_64")]
+    fn apply_simd(&self, data: &[u8], output: &mut [u8]) -> usize {
+        if !is_x86_feature_detected!("ssse3") {
+            return 0;
+        }
+        // SAFETY: SSSE3 support was checked above
+        unsafe { self.apply_ssse3(data, output) }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "ssse3")]
+    unsafe fn apply_ssse3(&self, data: &[u8], output: &mut [u8]) -> usize {
+        use std::arch::x86_64::{
+            __m128i, _mm_loadu_si128, _mm_or_si128, _mm_shuffle_epi8, _mm_storeu_si128,
+        };
+
+        let (shuffle, fill) = self.masks();
+        let groups = self.simd_groups(data);
+        // SAFETY: every load reads 16 bytes at most data.len() - 16 by simd_groups,
+        // and every store writes 16 of the 4 * pixels bytes output holds
+        unsafe {
+            let shuffle = _mm_loadu_si128(shuffle.as_ptr().cast::<__m128i>());
+            let fill = _mm_loadu_si128(fill.as_ptr().cast::<__m128i>());
+            for group in 0..groups {
+                let source = data.as_ptr().add(group * self.stride * 4);
+                let pixels = _mm_loadu_si128(source.cast::<__m128i>());
+                let pixels = _mm_or_si128(_mm_shuffle_epi8(pixels, shuffle), fill);
+                let target = output.as_mut_ptr().add(group * 16);
+                _mm_storeu_si128(target.cast::<__m128i>(), pixels);
+            }
+        }
+        groups * 4
+    }
+
+    // NEON is part of the aarch64 baseline, no runtime check needed
+    #[cfg(target_arch = "aarch64")]
+    fn apply_simd(&self, data: &[u8], output: &mut [u8]) -> usize {
+        use std::arch::aarch64::{vld1q_u8, vorrq_u8, vqtbl1q_u8, vst1q_u8};
+
+        let (shuffle, fill) = self.masks();
+        let groups = self.simd_groups(data);
+        // SAFETY: same bounds as the SSSE3 path
+        unsafe {
+            let shuffle = vld1q_u8(shuffle.as_ptr());
+            let fill = vld1q_u8(fill.as_ptr());
+            for group in 0..groups {
+                let pixels = vld1q_u8(data.as_ptr().add(group * self.stride * 4));
+                let pixels = vorrq_u8(vqtbl1q_u8(pixels, shuffle), fill);
+                vst1q_u8(output.as_mut_ptr().add(group * 16), pixels);
+            }
+        }
+        groups * 4
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    fn apply_simd(&self, _data: &[u8], _output: &mut [u8]) -> usize {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simd_matches_scalar() {
+        let data: Vec<u8> = (0..3 * 4 * 37).map(|i| (i * 7 % 251) as u8).collect();
+        for format in [
+            VtfFormat::Abgr8888,
+            VtfFormat::Argb8888,
+            VtfFormat::Bgra8888,
+            VtfFormat::Bgrx8888,
+            VtfFormat::Rgb888,
+            VtfFormat::Bgr888,
+        ] {
+            let swizzle = Swizzle::for_format(format).unwrap();
+            // Odd pixel counts leave a scalar tail after the shuffled groups
+            for pixels in [0, 3, 4, 5, 37] {
+                let data = &data[..pixels * swizzle.stride()];
+                let mut simd = vec![0u8; pixels * 4];
+                let mut scalar = vec![0u8; pixels * 4];
+                swizzle.apply(data, &mut simd);
+                swizzle.apply_scalar(data, &mut scalar);
+                assert_eq!(simd, scalar, "{:?} with {} pixels", format, pixels);
+            }
+        }
+    }
+}
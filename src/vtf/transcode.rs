@@ -0,0 +1,298 @@
+//! Transcoding: rebuild an existing VTF in another format or version, without the
+//! source image
+
+use super::cubemap::CubemapFace;
+use super::decoder::{VtfBuilder, VtfImage};
+use super::editor::VtfEditor;
+use super::header::{VtfFlags, VtfFormat, VtfResourceData, VtfResourceTag, VtfVersion};
+use super::{VtfError, VtfResult};
+
+// What happens to the mipmaps of the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MipPolicy {
+    // Re-encode the source levels as they are. Missing levels are filtered from
+    // the smallest one present; volume and HDR textures are filtered from the top.
+    #[default]
+    Keep,
+    // Filter a new chain from the top mip
+    Regenerate,
+    // Top mip only
+    Remove,
+}
+
+impl MipPolicy {
+    // Index as shown in the UI: keep, regenerate, remove
+    pub fn from_index(index: i32) -> Option<Self> {
+        match index {
+            0 => Some(MipPolicy::Keep),
+            1 => Some(MipPolicy::Regenerate),
+            2 => Some(MipPolicy::Remove),
+            _ => None,
+        }
+    }
+}
+
+// Target of a transcode. Anything left at None stays as in the source.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TranscodeOptions {
+    pub version: Option<VtfVersion>,
+    pub format: Option<VtfFormat>,
+    pub mips: MipPolicy,
+}
+
+impl VtfImage {
+    // Rebuild this texture with `options`. Flags, frames, faces, reflectivity, bump
+    // scale, the thumbnail and 7.3+ resources carry over; resources are dropped
+    // when the target version has no resource directory.
+    pub fn transcode(&self, options: &TranscodeOptions) -> VtfResult<Vec<u8>> {
+        let header = &self.header;
+        let version = options.version.unwrap_or(header.version);
+        let format = options.format.unwrap_or(header.high_res_format);
+        let hdr = header.is_hdr();
+        let (width, height, depth) = (self.width(), self.height(), self.depth().max(1));
+        // The pre-7.5 spheremap is regenerated from the cube faces if the target stores one
+        let faces = if self.is_envmap() { 6 } else { 1 };
+
+        let mut frames = Vec::with_capacity(self.frame_count() as usize);
+        let mut hdr_frames = Vec::new();
+        for frame in 0..self.frame_count() {
+            let (rgba, linear) = self.decode_layers(0, frame, hdr)?;
+            frames.push(rgba);
+            if hdr {
+                hdr_frames.push(linear);
+            }
+        }
+
+        let keep_levels = options.mips == MipPolicy::Keep && depth == 1 && !hdr;
+        let generate_mipmaps = match options.mips {
+            MipPolicy::Keep => self.mipmap_count() > 1,
+            MipPolicy::Regenerate => true,
+            MipPolicy::Remove => false,
+        };
+
+        let flags = header.flags;
+        let mut builder = VtfBuilder::from_layout(width, height, depth, faces, frames, hdr_frames)
+            .format(format)
+            .version(version)
+            .mipmaps(generate_mipmaps)
+            .normal_map(header.is_normal_map())
+            .srgb(!header.is_ssbump())
+            .clamp_axes(
+                flags.contains(VtfFlags::CLAMPS),
+                flags.contains(VtfFlags::CLAMPT),
+            )
+            .no_lod(flags.contains(VtfFlags::NOLOD))
+            .flags(flags)
+            .reflectivity(header.reflectivity)
+            .spheremap(header.face_count() == 7);
+
+        if keep_levels {
+            for level in 1..self.mipmap_count() {
+                for frame in 0..self.frame_count() {
+                    let (rgba, _) = self.decode_layers(level, frame, false)?;
+                    builder = builder.mip_image(frame as usize, level, rgba)?;
+                }
+            }
+        }
+
+        // The stored thumbnail is reused when the builder would write the same size
+        if header.low_res_format == VtfFormat::Dxt1
+            && let Some(offset) = header.thumbnail_offset()
+        {
+            let size = header.thumbnail_data_size() as usize;
+            if let Ok(thumbnail) = self.read_bytes(offset as usize, size) {
+                builder = builder.thumbnail_data(thumbnail.to_vec());
+            }
+        }
+
+        if version.minor >= 3 {
+            for entry in self.resources() {
+                // Written by the builder from the compression setting below
+                if entry.tag == VtfResourceTag::AuxCompression {
+                    continue;
+                }
+                let data = if !entry.has_data_chunk() {
+                    VtfResourceData::Inline(entry.data)
+                } else if let Some(chunk) = self.resource_data(entry.tag) {
+                    VtfResourceData::Chunk(chunk.to_vec())
+                } else {
+                    // The thumbnail and image data, written by the builder
+                    continue;
+                };
+                builder = builder.resource(entry.tag, data);
+            }
+        }
+
+        // Compressed sources stay compressed where the target version allows it
+        if version.minor >= 6
+            && let Some((method, level)) = self.compression()
+        {
+            builder = builder.compression(method, level);
+        }
+
+        let mut editor = VtfEditor::from_memory(builder.build()?)?;
+        editor.header.bumpmap_scale = header.bumpmap_scale;
+        // On cubemaps older than 7.5 the first frame marks the spheremap instead
+        if !self.is_envmap() && header.first_frame < header.frames {
+            editor.header.first_frame = header.first_frame;
+        }
+        editor.to_bytes()
+    }
+
+    // Every face or slice of one frame at `mip`, back to back, as RGBA8 and, with
+    // `hdr`, as linear RGBA32F
+    fn decode_layers(&self, mip: u8, frame: u16, hdr: bool) -> VtfResult<(Vec<u8>, Vec<f32>)> {
+        let (mut rgba, mut linear) = (Vec::new(), Vec::new());
+        if self.is_envmap() {
+            for face in CubemapFace::CUBE {
+                rgba.extend(self.decode_face(mip, frame, face)?.data);
+                if hdr {
+                    linear.extend(self.decode_face_hdr(mip, frame, face)?.data);
+                }
+            }
+        } else {
+            for slice in 0..self.header.mipmap_depth(mip) {
+                rgba.extend(self.decode_slice(mip, frame, slice)?.data);
+                if hdr {
+                    linear.extend(self.decode_slice_hdr(mip, frame, slice)?.data);
+                }
+            }
+        }
+        if rgba.is_empty() {
+            return Err(VtfError::InvalidData("Texture has no image data".into()));
+        }
+        Ok((rgba, linear))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vtf::VtfDecoder;
+
+    fn gradient(size: u32, frame: u8) -> Vec<u8> {
+        (0..size * size)
+            .flat_map(|i| [(i % size * 255 / size) as u8, frame * 40, 90, 255])
+            .collect()
+    }
+
+    #[test]
+    fn test_transcode_carries_over_header() {
+        let source = VtfBuilder::from_frames(16, 16, vec![gradient(16, 0), gradient(16, 1)])
+            .unwrap()
+            .format(VtfFormat::Bgra8888)
+            .version(VtfVersion::new(7, 4))
+            .clamp_axes(true, false)
+            .flags(VtfFlags::POINTSAMPLE)
+            .reflectivity([0.1, 0.2, 0.3])
+            .key_values("\"Information\" { \"Author\" \"me\" }")
+            .lod_control(8, 8)
+            .build()
+            .unwrap();
+        let mut editor = VtfEditor::from_memory(source).unwrap();
+        editor.header.bumpmap_scale = 2.0;
+        editor.header.first_frame = 1;
+        let source = VtfDecoder::load_from_memory(&editor.to_bytes().unwrap()).unwrap();
+
+        let options = TranscodeOptions {
+            version: Some(VtfVersion::new(7, 5)),
+            format: Some(VtfFormat::Dxt1),
+            mips: MipPolicy::Keep,
+        };
+        let vtf = VtfDecoder::load_from_memory(&source.transcode(&options).unwrap()).unwrap();
+
+        assert_eq!(vtf.header.version, VtfVersion::new(7, 5));
+        assert_eq!(vtf.format(), VtfFormat::Dxt1);
+        assert_eq!(vtf.frame_count(), 2);
+        assert_eq!(vtf.mipmap_count(), source.mipmap_count());
+        assert!(
+            vtf.header
+                .flags
+                .contains(VtfFlags::POINTSAMPLE | VtfFlags::CLAMPS)
+        );
+        assert!(!vtf.header.flags.contains(VtfFlags::CLAMPT));
+        assert_eq!(vtf.header.reflectivity, [0.1, 0.2, 0.3]);
+        assert_eq!(vtf.header.bumpmap_scale, 2.0);
+        assert_eq!(vtf.header.first_frame, 1);
+        assert_eq!(vtf.lod_control(), Some((8, 8)));
+        assert_eq!(vtf.key_values(), source.key_values());
+        assert_eq!(
+            vtf.decode_thumbnail().unwrap().data,
+            source.decode_thumbnail().unwrap().data
+        );
+
+        // Back to 7.2, which has nowhere to keep the resources
+        let options = TranscodeOptions {
+            version: Some(VtfVersion::new(7, 2)),
+            ..Default::default()
+        };
+        let vtf = VtfDecoder::load_from_memory(&source.transcode(&options).unwrap()).unwrap();
+        assert!(vtf.resources().is_empty());
+        assert_eq!(vtf.format(), VtfFormat::Bgra8888);
+        assert_eq!(
+            vtf.decode(2, 1).unwrap().data,
+            source.decode(2, 1).unwrap().data
+        );
+    }
+
+    #[test]
+    fn test_transcode_keeps_hand_made_mips() {
+        let source = VtfBuilder::new(8, 8, vec![200; 8 * 8 * 4])
+            .format(VtfFormat::Rgba8888)
+            .mip_image(0, 1, vec![10; 4 * 4 * 4])
+            .unwrap()
+            .build()
+            .unwrap();
+        let source = VtfDecoder::load_from_memory(&source).unwrap();
+
+        let keep = source.transcode(&TranscodeOptions::default()).unwrap();
+        let vtf = VtfDecoder::load_from_memory(&keep).unwrap();
+        assert_eq!(vtf.decode(1, 0).unwrap().data, vec![10; 4 * 4 * 4]);
+
+        let options = TranscodeOptions {
+            mips: MipPolicy::Regenerate,
+            ..Default::default()
+        };
+        let vtf = VtfDecoder::load_from_memory(&source.transcode(&options).unwrap()).unwrap();
+        assert_eq!(vtf.decode(1, 0).unwrap().data, vec![200; 4 * 4 * 4]);
+
+        let options = TranscodeOptions {
+            mips: MipPolicy::Remove,
+            ..Default::default()
+        };
+        let vtf = VtfDecoder::load_from_memory(&source.transcode(&options).unwrap()).unwrap();
+        assert_eq!(vtf.mipmap_count(), 1);
+    }
+
+    #[test]
+    fn test_transcode_cubemap_across_spheremap_versions() {
+        let faces: Vec<Vec<u8>> = (0..6u8).map(|face| vec![face * 40; 4 * 4 * 4]).collect();
+        let source = VtfBuilder::from_cube_faces(4, faces)
+            .unwrap()
+            .format(VtfFormat::Rgba8888)
+            .version(VtfVersion::new(7, 5))
+            .mip_image(
+                0,
+                1,
+                (0..6u8).flat_map(|face| vec![face * 30; 16]).collect(),
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+        let source = VtfDecoder::load_from_memory(&source).unwrap();
+
+        let options = TranscodeOptions {
+            version: Some(VtfVersion::new(7, 1)),
+            ..Default::default()
+        };
+        let vtf = VtfDecoder::load_from_memory(&source.transcode(&options).unwrap()).unwrap();
+        assert!(vtf.is_envmap());
+        assert_eq!(vtf.face_count(), 6);
+        for face in CubemapFace::CUBE {
+            assert_eq!(
+                vtf.decode_face(1, 0, face).unwrap().data,
+                source.decode_face(1, 0, face).unwrap().data
+            );
+        }
+    }
+}